@@ -0,0 +1,161 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use futures::executor::block_on;
+use image::{DynamicImage, Rgb, RgbImage};
+use image_colorizer::{
+    colorize::{colorize, ColorizeError},
+    constants::{KMEANS_ITERATIONS, KMEANS_SEED},
+    dither::error_diffusion,
+    palette::{extract_kmeans, extract_median_cut},
+    progress::Progress,
+    spatial::{box_average, gaussian_average, separable_box_average},
+    types::{AppConfig, BlendFactor, DitherAlgorithm, KernelShape, WorkingSpace},
+    utils::compute_integral_image,
+};
+use palette::Lab;
+
+const SIZE: u32 = 512;
+
+fn image() -> RgbImage {
+    RgbImage::from_fn(SIZE, SIZE, |x, y| {
+        Rgb([
+            (x * 255 / SIZE) as u8,
+            (y * 255 / SIZE) as u8,
+            ((x ^ y) & 0xff) as u8,
+        ])
+    })
+}
+
+// Evenly spread over the lightness and the a/b plane so every pixel has several close candidates
+fn palette(size: usize) -> Vec<Lab> {
+    (0..size)
+        .map(|i| {
+            let angle = i as f32 * 2.399_963;
+            let chroma = 10.0 + (i % 7) as f32 * 10.0;
+            Lab::new(
+                (i * 100 / size) as f32,
+                chroma * angle.cos(),
+                chroma * angle.sin(),
+            )
+        })
+        .collect()
+}
+
+fn config(colors: Vec<Lab>) -> AppConfig {
+    AppConfig {
+        blend_factor: BlendFactor::Uniform(0.9),
+        colors,
+        dither_amount: 0.1,
+        spatial_averaging_radius: 4,
+        ..AppConfig::default()
+    }
+}
+
+// The first pass runs on the GPU unless error diffusion is used, so the CPU side of mapping
+// pixels onto the palette is measured through error diffusion
+fn first_pass(c: &mut Criterion) {
+    let img = DynamicImage::ImageRgb8(image());
+    let mut group = c.benchmark_group("first_pass");
+
+    for palette_size in [16, 256] {
+        let mut config = config(palette(palette_size));
+        config.dither_algorithm = DitherAlgorithm::FloydSteinberg;
+
+        group.bench_with_input(
+            BenchmarkId::new("error_diffusion", palette_size),
+            &config,
+            |b, config| b.iter(|| error_diffusion(&img, config)),
+        );
+    }
+
+    group.finish();
+}
+
+// Both extract a palette from the same image, k-means with the iteration count the CLI uses
+fn palette_extraction(c: &mut Criterion) {
+    let img = DynamicImage::ImageRgb8(image());
+    let mut group = c.benchmark_group("palette_extraction");
+
+    for k in [16, 64, 256] {
+        group.bench_with_input(BenchmarkId::new("kmeans", k), &k, |b, &k| {
+            b.iter(|| extract_kmeans(&img, k, KMEANS_ITERATIONS, KMEANS_SEED))
+        });
+        group.bench_with_input(BenchmarkId::new("median_cut", k), &k, |b, &k| {
+            b.iter(|| extract_median_cut(&img, k))
+        });
+    }
+
+    group.finish();
+}
+
+fn spatial_averaging(c: &mut Criterion) {
+    let img = image();
+    let progress = Progress::new(None, 0);
+    let mut group = c.benchmark_group("spatial_averaging");
+
+    group.bench_function("integral_image", |b| {
+        b.iter(|| compute_integral_image(&img, None, WorkingSpace::Lab, &progress))
+    });
+
+    let integral = compute_integral_image(&img, None, WorkingSpace::Lab, &progress);
+    for radius in [2, 8, 32] {
+        for shape in [KernelShape::Square, KernelShape::Circle] {
+            group.bench_with_input(
+                BenchmarkId::new(format!("box_average_{:?}", shape).to_lowercase(), radius),
+                &radius,
+                |b, &radius| b.iter(|| box_average(&integral, radius, shape)),
+            );
+        }
+
+        group.bench_with_input(
+            BenchmarkId::new("separable_box_average", radius),
+            &radius,
+            |b, &radius| {
+                b.iter(|| separable_box_average(&img, None, WorkingSpace::Lab, radius, &progress))
+            },
+        );
+    }
+
+    for sigma in [1.0, 4.0] {
+        group.bench_with_input(
+            BenchmarkId::new("gaussian_average", sigma),
+            &sigma,
+            |b, &sigma| {
+                b.iter(|| gaussian_average(&img, None, WorkingSpace::Lab, sigma, &progress))
+            },
+        );
+    }
+
+    group.finish();
+}
+
+fn full_colorize(c: &mut Criterion) {
+    let img = DynamicImage::ImageRgb8(image());
+
+    // Nothing to measure on machines without a GPU
+    if let Err(ColorizeError::NoAdapter) = block_on(colorize(&img, &config(palette(16)))) {
+        return;
+    }
+
+    let mut group = c.benchmark_group("colorize");
+    group.sample_size(10);
+
+    for radius in [2, 8, 32] {
+        let mut config = config(palette(16));
+        config.spatial_averaging_radius = radius;
+
+        group.bench_with_input(BenchmarkId::new("radius", radius), &config, |b, config| {
+            b.iter(|| block_on(colorize(&img, config)).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    first_pass,
+    palette_extraction,
+    spatial_averaging,
+    full_colorize
+);
+criterion_main!(benches);
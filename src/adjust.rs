@@ -0,0 +1,63 @@
+use crate::{
+    platform::prelude::*,
+    types::WorkingSpace,
+    utils::{to_buffer, RgbBuffer, RgbPixel},
+};
+
+use std::ops::Deref;
+
+use image::ImageBuffer;
+use palette::{FromColor, Lab, Srgb};
+
+/// Shifts the Lab lightness of every pixel of `image` by `brightness` and scales its distance from
+/// mid grey by `contrast`, clamped to 0..=100. A brightness of 0 with a contrast of 1 is a no-op.
+pub fn adjust_lightness<P: RgbPixel, C: Deref<Target = [P::Subpixel]>>(
+    image: &ImageBuffer<P, C>,
+    brightness: f32,
+    contrast: f32,
+) -> RgbBuffer<P> {
+    let mut output = to_buffer(image);
+    let row_length = image.width() as usize * 3;
+
+    output.par_chunks_mut(row_length.max(1)).for_each(|row| {
+        for pixel in row.chunks_exact_mut(3) {
+            let pixel = P::from_slice_mut(pixel);
+            let [l, a, b] = pixel.to_working(WorkingSpace::Lab);
+            let l = ((l - 50.0) * contrast + 50.0 + brightness).clamp(0.0, 100.0);
+
+            // Brightening saturated colors can push them out of the sRGB gamut
+            let rgb = Srgb::from_color(Lab::new(l, a, b));
+            *pixel = P::from_scaled(
+                [rgb.red, rgb.green, rgb.blue].map(|c| (c.clamp(0.0, 1.0) * P::MAX).round()),
+            );
+        }
+    });
+
+    output
+}
+
+/// Shifts the Lab b channel of every pixel of `image` by `temperature`, towards amber when
+/// positive and blue when negative, and the a channel by `tint`, towards magenta when positive
+/// and green when negative. Zero for both is a no-op.
+pub fn adjust_white_balance<P: RgbPixel, C: Deref<Target = [P::Subpixel]>>(
+    image: &ImageBuffer<P, C>,
+    temperature: f32,
+    tint: f32,
+) -> RgbBuffer<P> {
+    let mut output = to_buffer(image);
+    let row_length = image.width() as usize * 3;
+
+    output.par_chunks_mut(row_length.max(1)).for_each(|row| {
+        for pixel in row.chunks_exact_mut(3) {
+            let pixel = P::from_slice_mut(pixel);
+            let [l, a, b] = pixel.to_working(WorkingSpace::Lab);
+
+            let rgb = Srgb::from_color(Lab::new(l, a + tint, b + temperature));
+            *pixel = P::from_scaled(
+                [rgb.red, rgb.green, rgb.blue].map(|c| (c.clamp(0.0, 1.0) * P::MAX).round()),
+            );
+        }
+    });
+
+    output
+}
@@ -0,0 +1,158 @@
+use crate::{
+    colorize::{run_rgba_pipeline, ColorizeError},
+    progress::{Progress, ProgressCallback},
+    types::AppConfig,
+};
+
+use std::{
+    io::{Read, Write},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use image::{DynamicImage, Rgba, RgbaImage};
+
+/// Colorizes every frame of the animated GIF read from `reader` and writes the result to `writer`,
+/// keeping the delay, position and disposal method of each frame.
+///
+/// Every pixel is mapped on its own, so identical source colors map identically in every frame.
+/// Random dithering is still tied to the pixel position and stays put, but error diffusion and
+/// spatial averaging carry changes into the pixels around them. `config.temporal_coherence` keeps
+/// unchanged pixels exactly as they were in the frame before, whatever the dithering algorithm.
+pub async fn colorize_gif<R: Read, W: Write>(
+    reader: R,
+    writer: W,
+    config: &AppConfig,
+) -> Result<(), ColorizeError> {
+    run_gif_pipeline(reader, writer, config, None).await
+}
+
+/// Same as `colorize_gif`, reporting the combined progress of all frames to `progress`
+pub async fn colorize_gif_with_progress<R: Read, W: Write>(
+    reader: R,
+    writer: W,
+    config: &AppConfig,
+    progress: &ProgressCallback<'_>,
+) -> Result<(), ColorizeError> {
+    run_gif_pipeline(reader, writer, config, Some(progress)).await
+}
+
+async fn run_gif_pipeline<R: Read, W: Write>(
+    reader: R,
+    writer: W,
+    config: &AppConfig,
+    progress: Option<&ProgressCallback<'_>>,
+) -> Result<(), ColorizeError> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options.read_info(reader)?;
+
+    // Frames are only as large as the region they update, so each one is colorized on its own
+    // rather than the composited canvas
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame()? {
+        frames.push(frame.clone());
+    }
+
+    let total = frames
+        .iter()
+        .map(|frame| frame.width as u64 * frame.height as u64 + 2)
+        .sum();
+    let overall = Progress::new(progress, total);
+
+    let mut encoder = gif::Encoder::new(writer, decoder.width(), decoder.height(), &[])?;
+    encoder.set_repeat(decoder.repeat())?;
+
+    let mut canvas = config
+        .temporal_coherence
+        .then(|| Canvas::new(decoder.width(), decoder.height()));
+
+    for frame in frames {
+        let rgba = RgbaImage::from_raw(
+            frame.width as u32,
+            frame.height as u32,
+            frame.buffer.into_owned(),
+        )
+        .ok_or(ColorizeError::InvalidFrame)?;
+
+        let frame_done = AtomicU64::new(0);
+        let frame_progress = |done: u64, _| {
+            let previous = frame_done.fetch_max(done, Ordering::Relaxed);
+            overall.inc(done.saturating_sub(previous));
+        };
+
+        let source = DynamicImage::ImageRgba8(rgba);
+        let mut colorized = run_rgba_pipeline(&source, config, Some(&frame_progress)).await?;
+        if let Some(canvas) = canvas.as_mut() {
+            canvas.reuse_unchanged(
+                source.as_rgba8().unwrap(),
+                &mut colorized,
+                (frame.left, frame.top),
+            );
+        }
+
+        // GIF transparency is a single palette entry, so every transparent pixel needs the same
+        // color to end up on it
+        let mut pixels = colorized.into_raw();
+        for pixel in pixels.chunks_exact_mut(4).filter(|pixel| pixel[3] == 0) {
+            pixel.copy_from_slice(&[0; 4]);
+        }
+        let mut output = gif::Frame::from_rgba_speed(frame.width, frame.height, &mut pixels, 10);
+        output.delay = frame.delay;
+        output.dispose = frame.dispose;
+        output.left = frame.left;
+        output.top = frame.top;
+        output.needs_user_input = frame.needs_user_input;
+        output.interlaced = frame.interlaced;
+
+        encoder.write_frame(&output)?;
+    }
+
+    Ok(())
+}
+
+/// Source and colorized pixel last drawn at every position of the GIF canvas
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<Option<(Rgba<u8>, Rgba<u8>)>>,
+}
+
+impl Canvas {
+    fn new(width: u16, height: u16) -> Self {
+        let (width, height) = (width as usize, height as usize);
+        Canvas {
+            width,
+            height,
+            pixels: vec![None; width * height],
+        }
+    }
+
+    /// Replaces every pixel of `colorized` whose source matches the source last drawn at the same
+    /// position with the colorized pixel drawn there, and remembers the rest. Frames only cover the
+    /// region they update, so their pixels are moved onto the canvas by the frame's `offset`.
+    /// Transparent pixels don't draw anything, so they're skipped
+    fn reuse_unchanged(
+        &mut self,
+        source: &RgbaImage,
+        colorized: &mut RgbaImage,
+        offset: (u16, u16),
+    ) {
+        for (x, y, pixel) in colorized.enumerate_pixels_mut() {
+            let source_pixel = *source.get_pixel(x, y);
+            let (canvas_x, canvas_y) = (
+                offset.0 as usize + x as usize,
+                offset.1 as usize + y as usize,
+            );
+            if source_pixel[3] == 0 || canvas_x >= self.width || canvas_y >= self.height {
+                continue;
+            }
+
+            match &mut self.pixels[canvas_y * self.width + canvas_x] {
+                Some((previous_source, previous)) if *previous_source == source_pixel => {
+                    *pixel = *previous;
+                }
+                slot => *slot = Some((source_pixel, *pixel)),
+            }
+        }
+    }
+}
@@ -0,0 +1,322 @@
+use crate::{
+    animation::colorize_gif_with_progress,
+    colorize::{
+        colorize_rgb16_with_progress, colorize_rgba16_with_progress, colorize_rgba_with_progress,
+        colorize_with_progress, ColorizeError, ThreadPool,
+    },
+    exif::{apply_orientation, read_orientation},
+    platform::current_num_threads,
+    progress::ProgressCallback,
+    types::AppConfig,
+    utils::is_high_bit_depth,
+};
+
+use std::{
+    any::Any,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Cursor},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    thread,
+};
+
+use futures::executor::block_on;
+use image::{DynamicImage, ImageFormat, ImageOutputFormat};
+
+/// Outcome of colorizing a directory. Files that aren't images, or that failed to colorize, are
+/// listed in `skipped` along with the reason instead of aborting the whole batch.
+#[derive(Debug, Default)]
+pub struct BatchSummary {
+    pub colorized: usize,
+    pub skipped: Vec<(PathBuf, String)>,
+}
+
+/// Something that happened to the images of a batch, as passed to a `BatchCallback`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BatchEvent<'a> {
+    /// The directory was read and `images` of its files are going to be colorized
+    Listed { images: usize },
+    /// The image at `path` is being colorized
+    Started { path: &'a Path },
+    /// (done, total) of the image at `path`, like a `ProgressCallback` gets
+    Progress {
+        path: &'a Path,
+        done: u64,
+        total: u64,
+    },
+    /// The image at `path` is done, with the reason it was skipped when it failed
+    Finished {
+        path: &'a Path,
+        error: Option<&'a str>,
+    },
+}
+
+/// Called with every `BatchEvent` of a batch. Images are colorized on several threads at once, so
+/// the events of different images interleave.
+pub type BatchCallback<'a> = dyn Fn(BatchEvent<'_>) + Send + Sync + 'a;
+
+/// Colorizes every image directly inside `input` in parallel, writing each result to `output`
+/// under the same file name
+pub fn colorize_dir(
+    input: &Path,
+    output: &Path,
+    config: &AppConfig,
+) -> Result<BatchSummary, ColorizeError> {
+    run_batch(input, output, config, None, None)
+}
+
+/// Same as `colorize_dir`, reporting the combined progress of all images to `progress`
+pub fn colorize_dir_with_progress(
+    input: &Path,
+    output: &Path,
+    config: &AppConfig,
+    progress: &ProgressCallback<'_>,
+) -> Result<BatchSummary, ColorizeError> {
+    run_batch(input, output, config, Some(progress), None)
+}
+
+/// Same as `colorize_dir`, reporting when every image starts, progresses and finishes to `events`
+/// so each one can be followed on its own
+pub fn colorize_dir_with_events(
+    input: &Path,
+    output: &Path,
+    config: &AppConfig,
+    events: &BatchCallback<'_>,
+) -> Result<BatchSummary, ColorizeError> {
+    run_batch(input, output, config, None, Some(events))
+}
+
+fn run_batch(
+    input: &Path,
+    output: &Path,
+    config: &AppConfig,
+    progress: Option<&ProgressCallback<'_>>,
+    events: Option<&BatchCallback<'_>>,
+) -> Result<BatchSummary, ColorizeError> {
+    fs::create_dir_all(output)?;
+
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(input)? {
+        let path = entry?.path();
+        if path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    // Reading the dimensions up front both weeds out files that aren't images and gives the
+    // amount of work for the combined progress
+    let mut summary = BatchSummary::default();
+    let mut images = Vec::new();
+    for path in paths {
+        match image::image_dimensions(&path) {
+            Ok((width, height)) => images.push((path, width as u64 * height as u64 + 2)),
+            Err(e) => summary.skipped.push((path, e.to_string())),
+        }
+    }
+
+    let total: u64 = images.iter().map(|(_, work)| work).sum();
+    let done = AtomicU64::new(0);
+    let send = |event: BatchEvent<'_>| {
+        if let Some(events) = events {
+            events(event);
+        }
+    };
+    send(BatchEvent::Listed {
+        images: images.len(),
+    });
+
+    // Every image gets a dedicated thread instead of a rayon task. A rayon thread waiting on the
+    // CPU stages of one image could otherwise steal another image whole, holding the first one up
+    // until that's done. The CPU work inside runs on a rayon pool per worker, and the pools split
+    // the threads between them so the batch stays within the limit
+    let threads = config.threads.unwrap_or_else(current_num_threads).max(1);
+    let workers = threads.min(images.len().max(1));
+    let pools = (0..workers)
+        .map(|worker| {
+            ThreadPool::with_threads(threads / workers + usize::from(worker < threads % workers))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let next = AtomicUsize::new(0);
+
+    let mut results: Vec<_> = thread::scope(|scope| {
+        let handles: Vec<_> = pools
+            .iter()
+            .map(|pool| {
+                let next = &next;
+                let images = &images;
+                let done = &done;
+                let send = &send;
+                scope.spawn(move || {
+                    let mut results = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some((path, _)) = images.get(index) else {
+                            break results;
+                        };
+
+                        send(BatchEvent::Started { path });
+                        let file_done = AtomicU64::new(0);
+                        let file_progress = |current: u64, file_total| {
+                            let delta = current
+                                .saturating_sub(file_done.fetch_max(current, Ordering::Relaxed));
+                            let total_done = done.fetch_add(delta, Ordering::Relaxed) + delta;
+                            if let Some(progress) = progress {
+                                progress(total_done, total);
+                            }
+                            send(BatchEvent::Progress {
+                                path,
+                                done: current,
+                                total: file_total,
+                            });
+                        };
+
+                        let output_path = output.join(path.file_name().unwrap_or_default());
+                        // A panic skips the file it happened in instead of ending the whole batch
+                        let result = match panic::catch_unwind(AssertUnwindSafe(|| {
+                            pool.install(|| {
+                                process_file_with_progress(
+                                    path,
+                                    &output_path,
+                                    config,
+                                    &file_progress,
+                                )
+                            })
+                        })) {
+                            Ok(result) => result.map_err(|e| e.to_string()),
+                            Err(panic) => Err(panic_message(panic)),
+                        }
+                        .map_err(|reason| (path.clone(), reason));
+                        send(BatchEvent::Finished {
+                            path,
+                            error: result.as_ref().err().map(|(_, reason)| reason.as_str()),
+                        });
+                        results.push((index, result));
+                    }
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            // Only the callbacks are left to panic, and that's passed on to the caller
+            .flat_map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|panic| panic::resume_unwind(panic))
+            })
+            .collect()
+    });
+    results.sort_by_key(|(index, _)| *index);
+
+    for (_, result) in results {
+        match result {
+            Ok(()) => summary.colorized += 1,
+            Err(skipped) => summary.skipped.push(skipped),
+        }
+    }
+
+    Ok(summary)
+}
+
+// Panics carry their message as a `&str`, or as a `String` when it was formatted
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    let message = match panic.downcast_ref::<String>() {
+        Some(message) => message.as_str(),
+        None => panic
+            .downcast_ref::<&str>()
+            .copied()
+            .unwrap_or("unknown reason"),
+    };
+    format!("Panicked: {}", message)
+}
+
+/// Colorizes the image at `input` and writes it to `output`, picking the format from the extension
+/// of `output`. Photos are turned upright according to their EXIF orientation first, and animated
+/// GIFs are colorized frame by frame.
+pub fn process_file(input: &Path, output: &Path, config: &AppConfig) -> Result<(), ColorizeError> {
+    process_file_with_progress(input, output, config, &|_, _| {})
+}
+
+/// Same as `process_file`, reporting progress to `progress` along the way
+pub fn process_file_with_progress(
+    input: &Path,
+    output: &Path,
+    config: &AppConfig,
+    progress: &ProgressCallback<'_>,
+) -> Result<(), ColorizeError> {
+    let is_gif = input
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("gif"));
+    if is_gif {
+        let reader = BufReader::new(File::open(input)?);
+        let writer = BufWriter::new(File::create(output)?);
+        return block_on(colorize_gif_with_progress(reader, writer, config, progress));
+    }
+
+    // Phones store photos sideways and rely on EXIF orientation to show them upright
+    let bytes = fs::read(input)?;
+    let mut reader = image::io::Reader::new(Cursor::new(&bytes));
+    if let Ok(format) = ImageFormat::from_path(input) {
+        reader.set_format(format);
+    }
+    let img = apply_orientation(
+        reader.with_guessed_format()?.decode()?,
+        read_orientation(&bytes).unwrap_or(1),
+    );
+
+    let colorized = if img.color().has_alpha() && is_high_bit_depth(&img) {
+        DynamicImage::ImageRgba16(block_on(colorize_rgba16_with_progress(
+            &img, config, progress,
+        ))?)
+    } else if img.color().has_alpha() {
+        DynamicImage::ImageRgba8(block_on(colorize_rgba_with_progress(
+            &img, config, progress,
+        ))?)
+    } else if is_high_bit_depth(&img) {
+        DynamicImage::ImageRgb16(block_on(colorize_rgb16_with_progress(
+            &img, config, progress,
+        ))?)
+    } else {
+        DynamicImage::ImageRgb8(block_on(colorize_with_progress(&img, config, progress))?)
+    };
+
+    save_image(&colorized, output, config)
+}
+
+/// Writes `img` to `path` in the format of its extension. JPEG is written with
+/// `config.jpeg_quality`, OpenEXR with 32 bit float channels, and formats that can't store 16 bits
+/// per channel get 8 bits instead. Nothing is written when the format can't encode the image.
+pub fn save_image(
+    img: &DynamicImage,
+    path: &Path,
+    config: &AppConfig,
+) -> Result<(), ColorizeError> {
+    let format = ImageFormat::from_path(path)?;
+    // Encoded in memory first, so a format turning the image down doesn't leave an empty file
+    let mut writer = Cursor::new(Vec::new());
+
+    match format {
+        // JPEG has neither transparency nor more than 8 bits
+        ImageFormat::Jpeg => {
+            DynamicImage::ImageRgb8(img.to_rgb8())
+                .write_to(&mut writer, ImageOutputFormat::Jpeg(config.jpeg_quality))?;
+        }
+        ImageFormat::Png | ImageFormat::Tiff => img.write_to(&mut writer, format)?,
+        // The EXR encoder only takes float channels
+        ImageFormat::OpenExr if img.color().has_alpha() => {
+            DynamicImage::ImageRgba32F(img.to_rgba32f()).write_to(&mut writer, format)?
+        }
+        ImageFormat::OpenExr => {
+            DynamicImage::ImageRgb32F(img.to_rgb32f()).write_to(&mut writer, format)?
+        }
+        _ if img.color().has_alpha() => {
+            DynamicImage::ImageRgba8(img.to_rgba8()).write_to(&mut writer, format)?
+        }
+        _ => DynamicImage::ImageRgb8(img.to_rgb8()).write_to(&mut writer, format)?,
+    }
+    fs::write(path, writer.into_inner())?;
+
+    Ok(())
+}
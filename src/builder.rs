@@ -0,0 +1,171 @@
+use crate::{
+    distance::ColorDistance,
+    dither::CustomDither,
+    palette::{builtin, ColorKey},
+    types::{
+        AppConfig, BlendFactor, BlendSpace, ConfigError, CvdType, DistanceMetric, DitherAlgorithm,
+        GamutMapping, KernelShape, MappingMode, SkinTone, SpatialImpl, SpatialKernel, SplitTone,
+        ToneMap, WorkingSpace,
+    },
+};
+
+use palette::Lab;
+
+/// Chainable way to put together an `AppConfig`, starting from defaults that colorize fully with
+/// the kanagawa colorscheme, no dithering noise and light spatial averaging. Settings that aren't
+/// given keep their defaults, so new settings don't break existing callers.
+pub struct AppConfigBuilder {
+    config: AppConfig,
+}
+
+// Generates a setter per field that only stores the value
+macro_rules! setters {
+    ($($field:ident: $ty:ty),* $(,)?) => {
+        $(
+            pub fn $field(mut self, $field: $ty) -> Self {
+                self.config.$field = $field;
+                self
+            }
+        )*
+    };
+}
+
+impl AppConfigBuilder {
+    pub fn new() -> Self {
+        AppConfigBuilder {
+            config: AppConfig {
+                input_output_pairs: Vec::new(),
+                input_output_dirs: Vec::new(),
+                blend_factor: BlendFactor::Uniform(1.0),
+                blend_space: BlendSpace::LinearRgb,
+                colors: builtin("kanagawa").unwrap_or_default(),
+                mapping_mode: MappingMode::Nearest,
+                distance_metric: DistanceMetric::Cie76,
+                custom_distance: None,
+                soft_k: 1,
+                stochastic_assignment: false,
+                luminance_weight: 1.0,
+                preserve_extremes: false,
+                extreme_threshold: 0,
+                protect_skin: false,
+                skin_tone: SkinTone::default(),
+                replace_only: None,
+                transfer_luminance: true,
+                invert_luminance: false,
+                gamut_mapping: GamutMapping::Clip,
+                tone_map: ToneMap::None,
+                temperature: 0.0,
+                tint: 0.0,
+                brightness: 0.0,
+                contrast: 1.0,
+                dither_amount: 0.0,
+                dither_seed: 0,
+                dither_algorithm: DitherAlgorithm::Random,
+                custom_dither: None,
+                serpentine: false,
+                temporal_coherence: false,
+                spatial_averaging_radius: 1,
+                adaptive_radius: false,
+                multi_scale_weights: None,
+                spatial_kernel: SpatialKernel::Box,
+                refine_iterations: 1,
+                sharpen_amount: 0.0,
+                chroma_scale: 1.0,
+                split_tone: None,
+                posterize_levels: None,
+                simulate_cvd: None,
+                kernel_shape: KernelShape::Square,
+                spatial_impl: SpatialImpl::Integral,
+                working_space: WorkingSpace::Lab,
+                show_progress: false,
+                force_progress: false,
+                compute_ssim: false,
+                jpeg_quality: 90,
+                threads: None,
+                tile_height: None,
+                dry_run: false,
+            },
+        }
+    }
+
+    /// Either a single factor for every channel or one per red, green and blue channel
+    pub fn blend_factor(mut self, blend_factor: impl Into<BlendFactor>) -> Self {
+        self.config.blend_factor = blend_factor.into();
+        self
+    }
+
+    /// Matches palette colors with `distance` instead of `distance_metric`
+    pub fn custom_distance(mut self, distance: impl ColorDistance + 'static) -> Self {
+        self.config.custom_distance = Some(Box::new(distance));
+        self
+    }
+
+    /// Dithers with `dither` instead of `dither_algorithm`
+    pub fn custom_dither(mut self, dither: CustomDither) -> Self {
+        self.config.custom_dither = Some(dither);
+        self
+    }
+
+    setters! {
+        blend_space: BlendSpace,
+        colors: Vec<Lab>,
+        mapping_mode: MappingMode,
+        distance_metric: DistanceMetric,
+        soft_k: usize,
+        stochastic_assignment: bool,
+        luminance_weight: f32,
+        preserve_extremes: bool,
+        extreme_threshold: u8,
+        protect_skin: bool,
+        skin_tone: SkinTone,
+        replace_only: Option<Vec<ColorKey>>,
+        transfer_luminance: bool,
+        invert_luminance: bool,
+        gamut_mapping: GamutMapping,
+        tone_map: ToneMap,
+        temperature: f32,
+        tint: f32,
+        brightness: f32,
+        contrast: f32,
+        dither_amount: f32,
+        dither_seed: u64,
+        dither_algorithm: DitherAlgorithm,
+        serpentine: bool,
+        temporal_coherence: bool,
+        spatial_averaging_radius: u32,
+        adaptive_radius: bool,
+        multi_scale_weights: Option<Vec<f32>>,
+        spatial_kernel: SpatialKernel,
+        refine_iterations: usize,
+        sharpen_amount: f32,
+        chroma_scale: f32,
+        split_tone: Option<SplitTone>,
+        posterize_levels: Option<u8>,
+        simulate_cvd: Option<CvdType>,
+        kernel_shape: KernelShape,
+        spatial_impl: SpatialImpl,
+        working_space: WorkingSpace,
+        threads: Option<usize>,
+        tile_height: Option<u32>,
+        jpeg_quality: u8,
+        compute_ssim: bool,
+    }
+
+    /// The finished config, once `AppConfig::validate` accepts it
+    pub fn build(self) -> Result<AppConfig, ConfigError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+impl Default for AppConfigBuilder {
+    fn default() -> Self {
+        AppConfigBuilder::new()
+    }
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        AppConfigBuilder::new().config
+    }
+}
@@ -1,10 +1,172 @@
-use crate::{types::AppConfig, utils::compute_integral_image};
+use crate::{
+    adjust::{adjust_lightness, adjust_white_balance},
+    constants::{ESTIMATED_NANOS_PER_PIXEL, ESTIMATED_NANOS_PER_PIXEL_COLOR},
+    dither::{diffuse, Regions},
+    hdr::display_range,
+    metrics::ssim,
+    palette::{ColorKey, PaletteStats},
+    platform::{current_num_threads, prelude::*, request_adapter, Instant},
+    progress::{Progress, ProgressCallback, ProgressUpdate, Stage},
+    spatial::{
+        adaptive_box_average, adaptive_radii, box_average, gaussian_average,
+        multi_scale_box_average, separable_box_average, weighted_pixels,
+    },
+    types::{
+        AppConfig, ConfigError, DistanceMetric, DitherAlgorithm, KernelShape, SpatialImpl,
+        SpatialKernel, WorkingSpace,
+    },
+    utils::{
+        as_view, color_distance, compute_integral_image, lab_to_working, rgb8_to_lab,
+        texture_threshold, working_to_lab, RgbBuffer, RgbPixel, RgbView,
+    },
+};
 
-use anyhow::{Context, Result};
-use image::{DynamicImage, GenericImageView, ImageBuffer, Rgb, RgbImage};
-use indicatif::ProgressBar;
+use image::{
+    imageops::FilterType, DynamicImage, GenericImageView, GrayImage, ImageBuffer, Rgb, RgbImage,
+    Rgba, RgbaImage,
+};
+use log::{debug, info};
+use palette::Lab;
+use std::sync::{atomic::AtomicBool, mpsc::Sender};
 use wgpu::util::DeviceExt;
 
+#[derive(Debug)]
+pub enum ColorizeError {
+    Config(ConfigError),
+    NoAdapter,
+    Device(wgpu::RequestDeviceError),
+    BufferMap(wgpu::BufferAsyncError),
+    /// The GPU stopped before the result could be read back
+    ReadbackCanceled,
+    /// The cancel flag was set while colorizing
+    Cancelled,
+    Io(std::io::Error),
+    /// Reading or writing an image file failed
+    Image(image::ImageError),
+    #[cfg(feature = "parallel")]
+    ThreadPool(rayon::ThreadPoolBuildError),
+    GifDecode(gif::DecodingError),
+    GifEncode(gif::EncodingError),
+    InvalidFrame,
+    MaskDimensions {
+        mask: (u32, u32),
+        image: (u32, u32),
+    },
+    EmptyDitherTexture,
+    /// The samples passed to `colorize_raw` don't fit its dimensions
+    RawBufferSize {
+        expected: usize,
+        actual: usize,
+    },
+    LabelDimensions {
+        labels: (u32, u32),
+        image: (u32, u32),
+    },
+    /// A pixel of the label map passed to `colorize_with_regions` has no palette
+    MissingRegionPalette {
+        label: u8,
+        palettes: usize,
+    },
+}
+
+impl std::fmt::Display for ColorizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ColorizeError::Config(err) => write!(f, "Invalid config: {}", err),
+            ColorizeError::NoAdapter => write!(f, "Failed to find an appropriate adapter"),
+            ColorizeError::Device(err) => write!(f, "Failed to create device: {}", err),
+            ColorizeError::BufferMap(err) => write!(f, "Failed to read back from GPU: {}", err),
+            ColorizeError::ReadbackCanceled => write!(f, "GPU readback was canceled"),
+            ColorizeError::Cancelled => write!(f, "Colorizing was cancelled"),
+            ColorizeError::Io(err) => write!(f, "I/O error: {}", err),
+            ColorizeError::Image(err) => write!(f, "Image error: {}", err),
+            #[cfg(feature = "parallel")]
+            ColorizeError::ThreadPool(err) => write!(f, "Failed to create thread pool: {}", err),
+            ColorizeError::GifDecode(err) => write!(f, "Failed to decode GIF: {}", err),
+            ColorizeError::GifEncode(err) => write!(f, "Failed to encode GIF: {}", err),
+            ColorizeError::InvalidFrame => write!(f, "GIF frame doesn't match its dimensions"),
+            ColorizeError::MaskDimensions { mask, image } => write!(
+                f,
+                "Mask is {}x{} but the image is {}x{}",
+                mask.0, mask.1, image.0, image.1
+            ),
+            ColorizeError::EmptyDitherTexture => write!(f, "Dither texture has no pixels"),
+            ColorizeError::RawBufferSize { expected, actual } => write!(
+                f,
+                "Expected {} bytes of RGB samples but got {}",
+                expected, actual
+            ),
+            ColorizeError::LabelDimensions { labels, image } => write!(
+                f,
+                "Label map is {}x{} but the image is {}x{}",
+                labels.0, labels.1, image.0, image.1
+            ),
+            ColorizeError::MissingRegionPalette { label, palettes } => write!(
+                f,
+                "Label {} has no palette, only {} were given",
+                label, palettes
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ColorizeError {}
+
+impl From<ConfigError> for ColorizeError {
+    fn from(err: ConfigError) -> ColorizeError {
+        ColorizeError::Config(err)
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for ColorizeError {
+    fn from(err: wgpu::RequestDeviceError) -> ColorizeError {
+        ColorizeError::Device(err)
+    }
+}
+
+impl From<wgpu::BufferAsyncError> for ColorizeError {
+    fn from(err: wgpu::BufferAsyncError) -> ColorizeError {
+        ColorizeError::BufferMap(err)
+    }
+}
+
+impl From<std::io::Error> for ColorizeError {
+    fn from(err: std::io::Error) -> ColorizeError {
+        ColorizeError::Io(err)
+    }
+}
+
+impl From<image::ImageError> for ColorizeError {
+    fn from(err: image::ImageError) -> ColorizeError {
+        ColorizeError::Image(err)
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl From<rayon::ThreadPoolBuildError> for ColorizeError {
+    fn from(err: rayon::ThreadPoolBuildError) -> ColorizeError {
+        ColorizeError::ThreadPool(err)
+    }
+}
+
+impl From<gif::DecodingError> for ColorizeError {
+    fn from(err: gif::DecodingError) -> ColorizeError {
+        ColorizeError::GifDecode(err)
+    }
+}
+
+impl From<gif::EncodingError> for ColorizeError {
+    fn from(err: gif::EncodingError) -> ColorizeError {
+        ColorizeError::GifEncode(err)
+    }
+}
+
+impl From<futures::channel::oneshot::Canceled> for ColorizeError {
+    fn from(_: futures::channel::oneshot::Canceled) -> ColorizeError {
+        ColorizeError::ReadbackCanceled
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 struct Pixel {
@@ -21,35 +183,736 @@ struct ColorizedPixel {
     b: f32,
 }
 
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SatEntry {
+    l: f32,
+    a: f32,
+    b: f32,
+    weight: f32,
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 struct Params {
     width: u32,
     height: u32,
-    blend_factor: f32,
+    blend_factor_r: f32,
+    blend_factor_g: f32,
+    blend_factor_b: f32,
     dither_amount: f32,
-    spatial_radius: u32,
+    dither_mode: u32,
+    bayer_size: u32,
+    distance_metric: u32,
+    working_space: u32,
+    luminance_weight: f32,
+    spatial_kernel: u32,
+    spatial_sigma: f32,
+    range_sigma: f32,
+    blend_space: u32,
+    row_offset: u32,
+    transfer_luminance: u32,
+    sharpen_amount: f32,
+    posterize_levels: u32,
+    mapping_mode: u32,
+    soft_k: u32,
+    preserve_extremes: u32,
+    extreme_threshold: f32,
+    use_mask: u32,
+    collect_usage: u32,
+    owned_start: u32,
+    owned_end: u32,
+    cvd_type: u32,
+    seed_low: u32,
+    seed_high: u32,
+    gamut_mapping: u32,
+    chroma_scale: f32,
+    dither_texture_width: u32,
+    dither_texture_height: u32,
+    split_tone: u32,
+    split_shadow_a: f32,
+    split_shadow_b: f32,
+    split_highlight_a: f32,
+    split_highlight_b: f32,
+    split_balance: f32,
+    invert_luminance: u32,
+    stochastic_assignment: u32,
+}
+
+/// Maps `img` onto the colors of `config`
+pub async fn colorize(img: &DynamicImage, config: &AppConfig) -> Result<RgbImage, ColorizeError> {
+    run_pipeline(img, config, PipelineOptions::default()).await
+}
+
+/// Same as `colorize`, but colorizes only the luma of `img`, for black and white photos. Every
+/// pixel is neutral grey, so its working space a/b are zero and the palette color is picked by
+/// lightness alone. 16 bit grayscale keeps its precision until the output is quantized.
+pub async fn colorize_grayscale(
+    img: &DynamicImage,
+    config: &AppConfig,
+) -> Result<RgbImage, ColorizeError> {
+    let gray = match img {
+        DynamicImage::ImageLuma8(_) | DynamicImage::ImageLuma16(_) => img.clone(),
+        DynamicImage::ImageLumaA16(_)
+        | DynamicImage::ImageRgb16(_)
+        | DynamicImage::ImageRgba16(_) => DynamicImage::ImageLuma16(img.to_luma16()),
+        _ => DynamicImage::ImageLuma8(img.to_luma8()),
+    };
+
+    run_pipeline(&gray, config, PipelineOptions::default()).await
+}
+
+/// Same as `colorize`, but keeps 16 bits per channel from the input through to the output, for
+/// high bit depth scans and exports. The first pass result is never quantized to 8 bits along
+/// the way.
+pub async fn colorize_rgb16(
+    img: &DynamicImage,
+    config: &AppConfig,
+) -> Result<ImageBuffer<Rgb<u16>, Vec<u16>>, ColorizeError> {
+    run_pipeline(img, config, PipelineOptions::default()).await
+}
+
+/// Same as `colorize_rgb16`, reporting progress to `progress` along the way
+pub async fn colorize_rgb16_with_progress(
+    img: &DynamicImage,
+    config: &AppConfig,
+    progress: &ProgressCallback<'_>,
+) -> Result<ImageBuffer<Rgb<u16>, Vec<u16>>, ColorizeError> {
+    run_pipeline(
+        img,
+        config,
+        PipelineOptions {
+            progress: Some(progress),
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+/// Same as `colorize`, reporting progress to `progress` along the way
+pub async fn colorize_with_progress(
+    img: &DynamicImage,
+    config: &AppConfig,
+    progress: &ProgressCallback<'_>,
+) -> Result<RgbImage, ColorizeError> {
+    run_pipeline(
+        img,
+        config,
+        PipelineOptions {
+            progress: Some(progress),
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+/// Same as `colorize`, sending progress to `updates` along the way, for passing it on to another
+/// thread. An update is sent whenever another percent is done and whenever a pass starts.
+pub async fn colorize_with_progress_updates(
+    img: &DynamicImage,
+    config: &AppConfig,
+    updates: Sender<ProgressUpdate>,
+) -> Result<RgbImage, ColorizeError> {
+    run_pipeline(
+        img,
+        config,
+        PipelineOptions {
+            updates: Some(&updates),
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+/// Same as `colorize`, but stops with `ColorizeError::Cancelled` soon after `cancel` is set, for
+/// example from another thread when the user gives up on a large image
+pub async fn colorize_with_cancel(
+    img: &DynamicImage,
+    config: &AppConfig,
+    cancel: &AtomicBool,
+) -> Result<RgbImage, ColorizeError> {
+    run_pipeline(
+        img,
+        config,
+        PipelineOptions {
+            cancel: Some(cancel),
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+/// Same as `colorize`, but only colorizes where `mask` allows it. The luma of every mask pixel
+/// scales the blend factor of the image pixel under it, so 0 keeps the original and 255 colorizes
+/// it fully. The mask has to be the same size as `img`.
+pub async fn colorize_with_mask(
+    img: &DynamicImage,
+    mask: Option<&DynamicImage>,
+    config: &AppConfig,
+) -> Result<RgbImage, ColorizeError> {
+    let mask = match mask {
+        Some(mask) if mask.dimensions() != img.dimensions() => {
+            return Err(ColorizeError::MaskDimensions {
+                mask: mask.dimensions(),
+                image: img.dimensions(),
+            })
+        }
+        Some(mask) => Some(mask.to_luma8().into_raw()),
+        None => None,
+    };
+
+    run_pipeline(
+        img,
+        config,
+        PipelineOptions {
+            mask: mask.as_deref(),
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+/// Same as `colorize`, but with a palette per region of the image. Every pixel of `labels` is the
+/// index into `palettes` of the palette its image pixel is mapped to, in place of
+/// `config.colors`, which may be empty. The label map has to be the same size as `img`.
+///
+/// Each pixel only ever matches colors of its own palette, so pixels on either side of a region
+/// boundary map cleanly. Spatial averaging still mixes neighbors across the boundary, blending the
+/// palettes within the averaging radius of it. The first pass runs on the CPU.
+pub async fn colorize_with_regions(
+    img: &DynamicImage,
+    labels: &GrayImage,
+    palettes: &[Vec<Lab>],
+    config: &AppConfig,
+) -> Result<RgbImage, ColorizeError> {
+    if labels.dimensions() != img.dimensions() {
+        return Err(ColorizeError::LabelDimensions {
+            labels: labels.dimensions(),
+            image: img.dimensions(),
+        });
+    }
+    if let Some(&label) = labels
+        .iter()
+        .find(|&&label| label as usize >= palettes.len())
+    {
+        return Err(ColorizeError::MissingRegionPalette {
+            label,
+            palettes: palettes.len(),
+        });
+    }
+    if palettes.iter().any(Vec::is_empty) {
+        return Err(ConfigError::EmptyColors.into());
+    }
+
+    run_pipeline(
+        img,
+        config,
+        PipelineOptions {
+            regions: Some((labels.as_raw(), palettes)),
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+/// Same as `colorize`, but on tightly packed 8 bit RGB samples, `width` x `height` pixels in row
+/// major order, for callers that don't go through the `image` crate. The output is laid out the
+/// same way. The pipeline reads `data` where it is rather than copying it into a buffer first.
+pub async fn colorize_raw(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    config: &AppConfig,
+) -> Result<Vec<u8>, ColorizeError> {
+    // Longer slices would fit too as far as `from_raw` is concerned
+    let expected = width as usize * height as usize * 3;
+    let wrong_size = || ColorizeError::RawBufferSize {
+        expected,
+        actual: data.len(),
+    };
+    if data.len() != expected {
+        return Err(wrong_size());
+    }
+    let input = RgbView::<Rgb<u8>>::from_raw(width, height, data).ok_or_else(wrong_size)?;
+
+    let output: RgbImage = run_buffer_pipeline(input, config, PipelineOptions::default()).await?;
+    Ok(output.into_raw())
+}
+
+/// Same as `colorize`, but dithers with the thresholds of `texture` repeated over the image in
+/// place of `dither_algorithm`, for example a blue noise mask. Every pixel is offset the way
+/// ordered dithering offsets it, scaled by `dither_amount`. Custom dithering still takes
+/// precedence, and `None` dithers as set in `config`.
+pub async fn colorize_with_dither_texture(
+    img: &DynamicImage,
+    texture: Option<&GrayImage>,
+    config: &AppConfig,
+) -> Result<RgbImage, ColorizeError> {
+    if texture.is_some_and(|texture| texture.width() == 0 || texture.height() == 0) {
+        return Err(ColorizeError::EmptyDitherTexture);
+    }
+
+    run_pipeline(
+        img,
+        config,
+        PipelineOptions {
+            dither_texture: texture,
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+/// Same as `colorize`, also counting how many pixels were mapped to each palette color. Pixels
+/// blending several colors through `soft_k` count towards the closest one and gradient maps count
+/// the closest stop. Black and white pixels left alone by `preserve_extremes` aren't counted.
+pub async fn colorize_with_stats(
+    img: &DynamicImage,
+    config: &AppConfig,
+) -> Result<(RgbImage, PaletteStats), ColorizeError> {
+    let mut usage = vec![0; config.colors.len()];
+    let colorized = run_pipeline(
+        img,
+        config,
+        PipelineOptions {
+            usage: Some(&mut usage),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    Ok((colorized, PaletteStats::new(&config.colors, &usage)))
 }
 
-pub async fn colorize(
+/// Same as `colorize`, but on a copy of `img` scaled down so its longest side is at most `max_dim`,
+/// for quickly trying out settings before colorizing the full image. Smaller images aren't scaled
+/// up. The spatial averaging radius still counts pixels of the scaled down image.
+pub async fn colorize_preview(
     img: &DynamicImage,
     config: &AppConfig,
-    pb: &ProgressBar,
-) -> Result<RgbImage> {
+    max_dim: u32,
+) -> Result<RgbImage, ColorizeError> {
     let (width, height) = img.dimensions();
+    if width.max(height) <= max_dim {
+        return colorize(img, config).await;
+    }
+
+    // Lanczos keeps edges and fine texture closer to the full image than cheaper filters would
+    let preview = img.resize(max_dim.max(1), max_dim.max(1), FilterType::Lanczos3);
+    colorize(&preview, config).await
+}
 
-    pb.set_length((width * height + 2).into());
+/// What `colorize_with_report` measured along with the output
+pub struct ColorizeReport {
+    /// The same image `colorize` returns
+    pub output: RgbImage,
+    /// Structural similarity between the luma of the original and of the output, from -1.0 to
+    /// 1.0, when `compute_ssim` is set. Luminance transfer keeps it high, so a low value points
+    /// at lost detail.
+    pub ssim: Option<f64>,
+}
 
-    // Initialize wgpu
-    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
-    let adapter = instance
-        .request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::HighPerformance,
-            compatible_surface: None,
-            force_fallback_adapter: false,
+/// Same as `colorize`, also measuring how faithful the output is to the original as set in
+/// `config`
+pub async fn colorize_with_report(
+    img: &DynamicImage,
+    config: &AppConfig,
+) -> Result<ColorizeReport, ColorizeError> {
+    let output = colorize(img, config).await?;
+
+    let ssim = config.compute_ssim.then(|| {
+        let started = Instant::now();
+        let output_luma = DynamicImage::ImageRgb8(output.clone()).to_luma8();
+        let score = ssim(
+            &display_range(img, config.tone_map).to_luma8(),
+            &output_luma,
+        );
+        debug!("SSIM took {:?}", started.elapsed());
+        score
+    });
+
+    Ok(ColorizeReport { output, ssim })
+}
+
+/// Images `colorize_stages` captured along the pipeline
+pub struct ColorizeStages {
+    /// Every pixel mapped onto the palette, dithered and blended with the original, before any
+    /// spatial averaging
+    pub first_pass: RgbImage,
+    /// The same image `colorize` returns
+    pub output: RgbImage,
+}
+
+/// Same as `colorize`, also returning the result of the first pass so the pipeline can be
+/// inspected one stage at a time
+pub async fn colorize_stages(
+    img: &DynamicImage,
+    config: &AppConfig,
+) -> Result<ColorizeStages, ColorizeError> {
+    let (width, height) = img.dimensions();
+    let mut first_pass = RgbImage::new(width, height);
+    let output = run_pipeline(
+        img,
+        config,
+        PipelineOptions {
+            first_pass_stage: Some(&mut first_pass),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    Ok(ColorizeStages { first_pass, output })
+}
+
+/// Projected cost of colorizing an image, from `estimate_cost`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CostEstimate {
+    /// Bytes of the buffers the first pass reads and writes for the largest strip
+    pub first_pass_bytes: u64,
+    /// Bytes of the summed area table of the largest strip, 0 when spatial averaging doesn't
+    /// build one
+    pub integral_image_bytes: u64,
+    /// Bytes of the 8 bit image `colorize` returns
+    pub output_bytes: u64,
+    /// All of the above, which are alive at the same time while the largest strip is averaged
+    pub peak_memory_bytes: u64,
+    /// Rough running time, from the pixel count and the palette size, with the CPU share of it
+    /// divided by the number of threads
+    pub duration: std::time::Duration,
+}
+
+/// Projects the peak memory and running time of colorizing `img` with `config` without running
+/// anything, e.g. to decide whether a huge image needs `tile_height`. Strips only keep the first
+/// pass buffers and summed area table of one strip at a time, while the output always covers the
+/// whole image. The time is calibrated on a single thread and only meant as an order of magnitude.
+/// Threads only shorten the stages that run on the CPU, the GPU passes take as long either way.
+pub fn estimate_cost(img: &DynamicImage, config: &AppConfig) -> CostEstimate {
+    let (width, height) = img.dimensions();
+    estimate_cost_for_size(width, height, config)
+}
+
+/// Same as `estimate_cost` for an image of `width` x `height`, so the image doesn't have to be
+/// decoded first
+pub fn estimate_cost_for_size(width: u32, height: u32, config: &AppConfig) -> CostEstimate {
+    let strip_rows = strips(height, config)
+        .iter()
+        .map(|strip| strip.rows().len() as u64)
+        .max()
+        .unwrap_or(0);
+    let (width, height) = (width as u64, height as u64);
+
+    // Input, output and staging buffers on the GPU, plus the spatially averaged entries the
+    // second pass reads
+    let strip_pixels = width * strip_rows;
+    let first_pass_bytes = strip_pixels
+        * (std::mem::size_of::<Pixel>()
+            + 2 * std::mem::size_of::<ColorizedPixel>()
+            + std::mem::size_of::<SatEntry>()) as u64;
+
+    // The same conditions under which averaging takes the summed area table, which has a row and
+    // column of zeros in front
+    let uses_integral = config.spatial_kernel == SpatialKernel::Box
+        && config.spatial_averaging_radius > 0
+        && (config.spatial_impl != SpatialImpl::Separable
+            || config.kernel_shape != KernelShape::Square
+            || config.adaptive_radius
+            || config.multi_scale_weights.is_some());
+    let integral_image_bytes = if uses_integral {
+        (width + 1) * (strip_rows + 1) * std::mem::size_of::<(f64, f64, f64, f64)>() as u64
+    } else {
+        0
+    };
+
+    let output_bytes = width * height * 3;
+
+    // Only the CPU side is split across threads. Spatial averaging runs there for every kernel but
+    // the bilateral, and matching the palette does whenever the first pass can't run on the GPU
+    let threads = config.threads.unwrap_or_else(current_num_threads).max(1) as f64;
+    let averaging = ESTIMATED_NANOS_PER_PIXEL;
+    let matching = ESTIMATED_NANOS_PER_PIXEL_COLOR * config.colors.len() as f64;
+    let cpu_averaging = !matches!(config.spatial_kernel, SpatialKernel::Bilateral { .. });
+    let cpu_matching = config.dither_algorithm.is_error_diffusion()
+        || config.custom_distance.is_some()
+        || config.custom_dither.is_some();
+    let (mut cpu_nanos, mut gpu_nanos) = (0.0, 0.0);
+    for (nanos, on_cpu) in [(averaging, cpu_averaging), (matching, cpu_matching)] {
+        if on_cpu {
+            cpu_nanos += nanos;
+        } else {
+            gpu_nanos += nanos;
+        }
+    }
+    let nanos = (width * height) as f64
+        * (cpu_nanos / threads + gpu_nanos)
+        * config.refine_iterations.max(1) as f64;
+
+    CostEstimate {
+        first_pass_bytes,
+        integral_image_bytes,
+        output_bytes,
+        peak_memory_bytes: first_pass_bytes + integral_image_bytes + output_bytes,
+        duration: std::time::Duration::from_nanos(nanos as u64),
+    }
+}
+
+/// Same as `colorize`, also returning how much every pixel changed as the CIEDE2000 distance
+/// between the original and the output. The distances are scaled so the most changed pixel is
+/// 255, which leaves an unchanged image black.
+pub async fn colorize_with_diff(
+    img: &DynamicImage,
+    config: &AppConfig,
+) -> Result<(RgbImage, GrayImage), ColorizeError> {
+    let output = colorize(img, config).await?;
+    let original = display_range(img, config.tone_map).to_rgb8();
+
+    let distances: Vec<f32> = original
+        .par_chunks_exact(3)
+        .zip(output.par_chunks_exact(3))
+        .map(|(before, after)| {
+            color_distance(
+                &rgb8_to_lab(before[0], before[1], before[2]),
+                &rgb8_to_lab(after[0], after[1], after[2]),
+                DistanceMetric::Ciede2000,
+                1.0,
+            )
+        })
+        .collect();
+
+    let max = distances.iter().copied().fold(0.0, f32::max);
+    let scale = if max > 0.0 { 255.0 / max } else { 0.0 };
+    let levels = distances
+        .iter()
+        .map(|distance| (distance * scale).round() as u8)
+        .collect();
+    let diff = GrayImage::from_raw(output.width(), output.height(), levels)
+        .expect("one distance per pixel");
+
+    Ok((output, diff))
+}
+
+/// Same as `colorize`, but carries the alpha channel through unchanged. Fully transparent pixels
+/// are left out of the spatial averaging so they don't bleed into their neighbors.
+pub async fn colorize_rgba(
+    img: &DynamicImage,
+    config: &AppConfig,
+) -> Result<RgbaImage, ColorizeError> {
+    run_rgba_pipeline(img, config, None).await
+}
+
+/// Same as `colorize_rgba`, reporting progress to `progress` along the way
+pub async fn colorize_rgba_with_progress(
+    img: &DynamicImage,
+    config: &AppConfig,
+    progress: &ProgressCallback<'_>,
+) -> Result<RgbaImage, ColorizeError> {
+    run_rgba_pipeline(img, config, Some(progress)).await
+}
+
+pub(crate) async fn run_rgba_pipeline(
+    img: &DynamicImage,
+    config: &AppConfig,
+    progress: Option<&ProgressCallback<'_>>,
+) -> Result<RgbaImage, ColorizeError> {
+    let rgba = img.to_rgba8();
+    let alpha: Vec<u8> = rgba.pixels().map(|p| p[3]).collect();
+
+    let colorized: RgbImage = run_pipeline(
+        img,
+        config,
+        PipelineOptions {
+            alpha: Some(&alpha),
+            progress,
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    // Colors are copied over the original samples so the alpha channel is already in place
+    let mut output = rgba;
+    for (pixel, color) in output.chunks_exact_mut(4).zip(colorized.chunks_exact(3)) {
+        pixel[..3].copy_from_slice(color);
+    }
+
+    Ok(output)
+}
+
+/// Same as `colorize_rgba`, but keeps 16 bits per channel like `colorize_rgb16`
+pub async fn colorize_rgba16(
+    img: &DynamicImage,
+    config: &AppConfig,
+) -> Result<ImageBuffer<Rgba<u16>, Vec<u16>>, ColorizeError> {
+    run_rgba16_pipeline(img, config, None).await
+}
+
+/// Same as `colorize_rgba16`, reporting progress to `progress` along the way
+pub async fn colorize_rgba16_with_progress(
+    img: &DynamicImage,
+    config: &AppConfig,
+    progress: &ProgressCallback<'_>,
+) -> Result<ImageBuffer<Rgba<u16>, Vec<u16>>, ColorizeError> {
+    run_rgba16_pipeline(img, config, Some(progress)).await
+}
+
+async fn run_rgba16_pipeline(
+    img: &DynamicImage,
+    config: &AppConfig,
+    progress: Option<&ProgressCallback<'_>>,
+) -> Result<ImageBuffer<Rgba<u16>, Vec<u16>>, ColorizeError> {
+    let rgba = img.to_rgba16();
+    // Only full transparency matters to the spatial averaging, so any other alpha stays nonzero
+    let alpha: Vec<u8> = rgba
+        .pixels()
+        .map(|p| {
+            if p[3] == 0 {
+                0
+            } else {
+                (p[3] >> 8).max(1) as u8
+            }
         })
-        .await
-        .context("Failed to find an appropriate adapter")?;
+        .collect();
+
+    let colorized: ImageBuffer<Rgb<u16>, Vec<u16>> = run_pipeline(
+        img,
+        config,
+        PipelineOptions {
+            alpha: Some(&alpha),
+            progress,
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    let mut output = rgba;
+    for (pixel, color) in output.chunks_exact_mut(4).zip(colorized.chunks_exact(3)) {
+        pixel[..3].copy_from_slice(color);
+    }
+
+    Ok(output)
+}
+
+/// Everything `run_pipeline` can take besides the image and config, none of which are needed
+struct PipelineOptions<'a, P: RgbPixel> {
+    /// Alpha of every pixel, whose fully transparent pixels are left out of spatial averaging
+    alpha: Option<&'a [u8]>,
+    /// Coverage of every pixel out of 255, scaling its blend factor
+    mask: Option<&'a [u8]>,
+    progress: Option<&'a ProgressCallback<'a>>,
+    updates: Option<&'a Sender<ProgressUpdate>>,
+    /// Stops the pipeline with `ColorizeError::Cancelled` once set
+    cancel: Option<&'a AtomicBool>,
+    /// Filled with the number of pixels mapped to each palette color
+    usage: Option<&'a mut [u64]>,
+    /// Filled with the result of the first pass
+    first_pass_stage: Option<&'a mut RgbBuffer<P>>,
+    /// Tiled over the image as dithering thresholds in place of the dithering algorithm
+    dither_texture: Option<&'a GrayImage>,
+    /// Label of every pixel and the palettes the labels index, in place of `AppConfig::colors`
+    regions: Option<Regions<'a>>,
+}
+
+impl<P: RgbPixel> Default for PipelineOptions<'_, P> {
+    fn default() -> Self {
+        PipelineOptions {
+            alpha: None,
+            mask: None,
+            progress: None,
+            updates: None,
+            cancel: None,
+            usage: None,
+            first_pass_stage: None,
+            dither_texture: None,
+            regions: None,
+        }
+    }
+}
+
+async fn run_pipeline<P: RgbPixel>(
+    img: &DynamicImage,
+    config: &AppConfig,
+    options: PipelineOptions<'_, P>,
+) -> Result<RgbBuffer<P>, ColorizeError> {
+    // Floating point input is tone mapped first, so everything after only sees displayable range
+    let img = display_range(img, config.tone_map);
+    let input = P::convert(&img);
+    run_buffer_pipeline(as_view(&input), config, options).await
+}
+
+async fn run_buffer_pipeline<P: RgbPixel>(
+    input: RgbView<'_, P>,
+    config: &AppConfig,
+    options: PipelineOptions<'_, P>,
+) -> Result<RgbBuffer<P>, ColorizeError> {
+    let PipelineOptions {
+        alpha,
+        mask,
+        progress,
+        updates,
+        cancel,
+        mut usage,
+        mut first_pass_stage,
+        dither_texture,
+        regions,
+    } = options;
+    // Region palettes replace `config.colors`, which may then be left empty
+    match regions {
+        Some(_) => config.validate_settings()?,
+        None => config.validate()?,
+    }
+
+    let started = Instant::now();
+    let pool = ThreadPool::new(config.threads)?;
+
+    // Pixels away from every key get a mask of zero, which leaves them as they are
+    let key_mask = config
+        .replace_only
+        .as_deref()
+        .map(|keys| pool.install(|| replace_only(&input, mask, keys)));
+    let mask = key_mask.as_deref().or(mask);
+
+    // Skin is protected by scaling down the mask, which is what the blend factor is scaled by
+    let skin_mask = config
+        .protect_skin
+        .then(|| pool.install(|| protect_skin(&input, mask, config)));
+    let mask = skin_mask.as_deref().or(mask);
+
+    // The input is only copied when an adjustment changes it
+    let white_balanced = (config.temperature != 0.0 || config.tint != 0.0)
+        .then(|| pool.install(|| adjust_white_balance(&input, config.temperature, config.tint)));
+    let input = white_balanced.as_ref().map_or(input, as_view);
+    let lightened = (config.brightness != 0.0 || config.contrast != 1.0)
+        .then(|| pool.install(|| adjust_lightness(&input, config.brightness, config.contrast)));
+    let input = lightened.as_ref().map_or(input, as_view);
+
+    let (width, height) = input.dimensions();
+    info!(
+        "Colorizing {}x{} image ({} pixels) with {} colors",
+        width,
+        height,
+        width as u64 * height as u64,
+        config.colors.len()
+    );
+    let finished = || {
+        info!(
+            "Colorized {}x{} image in {:?}",
+            width,
+            height,
+            started.elapsed()
+        )
+    };
+
+    let strips = strips(height, config);
+    let strip_rows: u64 = strips.iter().map(|strip| strip.rows().len() as u64).sum();
+    let iterations = config.refine_iterations as u64;
+    let pb = &Progress::new(
+        progress,
+        (width as u64 * strip_rows + strips.len() as u64) * iterations + strips.len() as u64,
+    )
+    .with_updates(updates)
+    .with_cancel(cancel);
+    check_cancelled(pb)?;
+
+    // Initialize wgpu
+    let adapter = request_adapter().await.ok_or(ColorizeError::NoAdapter)?;
 
     let (device, queue) = adapter
         .request_device(
@@ -60,20 +923,304 @@ pub async fn colorize(
             },
             None,
         )
-        .await
-        .context("Failed to create device")?;
+        .await?;
+
+    let [blend_factor_r, blend_factor_g, blend_factor_b] = config.blend_factor.channels();
+    let (spatial_sigma, range_sigma) = config.spatial_kernel.bilateral_sigmas();
+    let [split_shadow, split_highlight] = match config.split_tone {
+        Some(tone) => {
+            [tone.shadow, tone.highlight].map(|lab| lab_to_working(&lab, config.working_space))
+        }
+        None => [[0.0; 3]; 2],
+    };
+    let params = Params {
+        width,
+        height,
+        blend_factor_r,
+        blend_factor_g,
+        blend_factor_b,
+        dither_amount: config.dither_amount,
+        // Without any dithering the noise would only add nothing, so it isn't computed at all
+        dither_mode: if config.dither_amount == 0.0 {
+            DitherAlgorithm::None.shader_mode()
+        } else if dither_texture.is_some() {
+            3
+        } else {
+            config.dither_algorithm.shader_mode()
+        },
+        bayer_size: config.dither_algorithm.bayer_size(),
+        distance_metric: config.distance_metric.shader_mode(),
+        working_space: config.working_space.shader_mode(),
+        luminance_weight: config.luminance_weight,
+        spatial_kernel: config.spatial_kernel.shader_mode(),
+        spatial_sigma,
+        range_sigma,
+        blend_space: config.blend_space.shader_mode(),
+        row_offset: 0,
+        transfer_luminance: config.transfer_luminance as u32,
+        sharpen_amount: config.sharpen_amount,
+        posterize_levels: config.posterize_levels.unwrap_or(0) as u32,
+        mapping_mode: config.mapping_mode.shader_mode(),
+        soft_k: config.soft_k as u32,
+        preserve_extremes: config.preserve_extremes as u32,
+        extreme_threshold: config.extreme_threshold as f32 / 255.0,
+        use_mask: mask.is_some() as u32,
+        collect_usage: usage.is_some() as u32,
+        owned_start: 0,
+        owned_end: height,
+        cvd_type: config.simulate_cvd.map_or(0, |cvd| cvd.shader_mode()),
+        seed_low: config.dither_seed as u32,
+        seed_high: (config.dither_seed >> 32) as u32,
+        gamut_mapping: config.gamut_mapping.shader_mode(),
+        chroma_scale: config.chroma_scale,
+        dither_texture_width: dither_texture.map_or(0, |texture| texture.width()),
+        dither_texture_height: dither_texture.map_or(0, |texture| texture.height()),
+        split_tone: config.split_tone.is_some() as u32,
+        split_shadow_a: split_shadow[1],
+        split_shadow_b: split_shadow[2],
+        split_highlight_a: split_highlight[1],
+        split_highlight_b: split_highlight[2],
+        split_balance: config.split_tone.map_or(0.5, |tone| tone.balance),
+        invert_luminance: config.invert_luminance as u32,
+        stochastic_assignment: config.stochastic_assignment as u32,
+    };
+
+    pb.set_stage(Stage::FirstPass);
+
+    // Error diffusion has to walk the image in scanline order, so it can't run on the GPU. It also
+    // carries error across strip boundaries, so it always covers the whole image. Custom distances
+    // and dithering only exist on the CPU, so they take the same path, and so do region palettes.
+    // A dither texture replaces error diffusion
+    let diffused = if (config.dither_algorithm.is_error_diffusion() && dither_texture.is_none())
+        || config.custom_distance.is_some()
+        || config.custom_dither.is_some()
+        || regions.is_some()
+    {
+        let started = Instant::now();
+        let diffused = diffuse(
+            &input,
+            mask,
+            dither_texture,
+            regions,
+            usage.as_deref_mut(),
+            config,
+            true,
+        );
+        debug!("First pass on the CPU took {:?}", started.elapsed());
+        Some(diffused)
+    } else {
+        None
+    };
+
+    // Without tiling the image is used as is, rather than being copied into a single strip
+    if strips.len() == 1 {
+        let params_buffer = create_params_buffer(&device, &params);
+        let first_pass = match diffused {
+            Some(diffused) => diffused,
+            None => {
+                run_first_pass(
+                    &device,
+                    &queue,
+                    &input,
+                    mask,
+                    dither_texture,
+                    config,
+                    &params_buffer,
+                    &pool,
+                    usage,
+                )
+                .await?
+            }
+        };
+        pb.inc(1);
+        check_cancelled(pb)?;
+        if let Some(stage) = first_pass_stage {
+            stage.clone_from(&first_pass);
+        }
+
+        let output = refine(
+            &device,
+            &queue,
+            first_pass,
+            alpha,
+            mask,
+            config,
+            &params,
+            params_buffer,
+            &pool,
+            pb,
+        )
+        .await?;
+        finished();
+        return Ok(output);
+    }
+
+    let mut output = RgbBuffer::<P>::new(width, height);
+
+    for (i, strip) in strips.iter().enumerate() {
+        if i > 0 {
+            pb.set_stage(Stage::FirstPass);
+        }
 
+        let rows = strip.rows();
+        let strip_height = rows.len() as u32;
+        let strip_params = Params {
+            height: strip_height,
+            row_offset: rows.start,
+            owned_start: strip.start - rows.start,
+            owned_end: strip.end - rows.start,
+            ..params
+        };
+        let params_buffer = create_params_buffer(&device, &strip_params);
+
+        let strip_pixels = rows.start as usize * width as usize..rows.end as usize * width as usize;
+        let strip_mask = mask.map(|mask| &mask[strip_pixels.clone()]);
+
+        let first_pass = match &diffused {
+            Some(diffused) => diffused.view(0, rows.start, width, strip_height).to_image(),
+            None => {
+                // The rows of a strip are already next to each other in the input
+                let samples = strip_pixels.start * 3..strip_pixels.end * 3;
+                let strip_img =
+                    RgbView::<P>::from_raw(width, strip_height, &input.as_raw()[samples]).unwrap();
+                run_first_pass(
+                    &device,
+                    &queue,
+                    &strip_img,
+                    strip_mask,
+                    dither_texture,
+                    config,
+                    &params_buffer,
+                    &pool,
+                    usage.as_deref_mut(),
+                )
+                .await?
+            }
+        };
+        pb.inc(1);
+        check_cancelled(pb)?;
+        if let Some(stage) = first_pass_stage.as_deref_mut() {
+            copy_owned_rows(stage, &first_pass, strip);
+        }
+
+        let colorized = refine(
+            &device,
+            &queue,
+            first_pass,
+            alpha.map(|alpha| &alpha[strip_pixels.clone()]),
+            strip_mask,
+            config,
+            &strip_params,
+            params_buffer,
+            &pool,
+            pb,
+        )
+        .await?;
+
+        copy_owned_rows(&mut output, &colorized, strip);
+    }
+
+    finished();
+    Ok(output)
+}
+
+// Only the rows a strip owns are kept, the overlap was just there as context
+fn copy_owned_rows<P: RgbPixel>(
+    output: &mut RgbBuffer<P>,
+    strip_img: &RgbBuffer<P>,
+    strip: &Strip,
+) {
+    let row_length = output.width() as usize * 3;
+    let offset = strip.rows().start;
+    let owned =
+        (strip.start - offset) as usize * row_length..(strip.end - offset) as usize * row_length;
+    output.as_mut()[strip.start as usize * row_length..strip.end as usize * row_length]
+        .copy_from_slice(&strip_img.as_raw()[owned]);
+}
+
+/// Rows `start..end` of the output that a strip is responsible for, along with the `overlap` rows
+/// on either side that its spatial averaging reads from
+struct Strip {
+    start: u32,
+    end: u32,
+    overlap: u32,
+    height: u32,
+}
+
+impl Strip {
+    fn rows(&self) -> std::ops::Range<u32> {
+        self.start.saturating_sub(self.overlap)..(self.end + self.overlap).min(self.height)
+    }
+}
+
+// Every kernel ignores rows past the image borders, so as long as a strip carries every row its
+// kernel can reach, the owned rows see exactly the neighbors they would in the full image. Every
+// refining iteration reaches that far again
+fn strips(height: u32, config: &AppConfig) -> Vec<Strip> {
+    let tile_height = config.tile_height.unwrap_or(height).max(1);
+    let overlap = match config.spatial_kernel {
+        SpatialKernel::Gaussian { sigma }
+        | SpatialKernel::Bilateral {
+            spatial_sigma: sigma,
+            ..
+        } => (sigma * 3.0).ceil() as u32,
+        // The last level of multi scale averaging has the largest radius
+        _ => match &config.multi_scale_weights {
+            Some(weights) => config.spatial_averaging_radius << weights.len().saturating_sub(1),
+            None => config.spatial_averaging_radius,
+        },
+    } * config.refine_iterations.max(1) as u32;
+
+    (0..height.max(1))
+        .step_by(tile_height as usize)
+        .map(|start| Strip {
+            start,
+            end: (start + tile_height).min(height),
+            overlap,
+            height,
+        })
+        .collect()
+}
+
+fn create_params_buffer(device: &wgpu::Device, params: &Params) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Params Buffer"),
+        contents: bytemuck::cast_slice(&[*params]),
+        usage: wgpu::BufferUsages::UNIFORM,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_first_pass<P: RgbPixel>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    img: &RgbView<'_, P>,
+    mask: Option<&[u8]>,
+    dither_texture: Option<&GrayImage>,
+    config: &AppConfig,
+    params_buffer: &wgpu::Buffer,
+    pool: &ThreadPool,
+    usage: Option<&mut [u64]>,
+) -> Result<RgbBuffer<P>, ColorizeError> {
+    let started = Instant::now();
+    let (width, height) = img.dimensions();
     let buffer_size = (std::mem::size_of::<ColorizedPixel>() * width as usize * height as usize)
         as wgpu::BufferAddress;
 
-    let input_buffer = create_input_buffer(&device, img);
-    let output_buffer1 = create_output_buffer(&device, width, height);
-    let staging_buffer = create_staging_buffer(&device, width, height);
+    let input_buffer = create_input_buffer(device, img);
+    let mask_buffer = create_mask_buffer(device, mask);
+    let dither_texture_buffer = create_dither_texture_buffer(device, dither_texture);
+    let output_buffer1 = create_output_buffer(device, width, height);
+    let staging_buffer = create_staging_buffer(device, width, height);
 
-    let color_palette: Vec<[f32; 3]> = config
+    // array<vec3<f32>> has a 16 byte stride in WGSL, so every color gets padded
+    let color_palette: Vec<[f32; 4]> = config
         .colors
         .iter()
-        .map(|lab| [lab.l as f32, lab.a as f32, lab.b as f32])
+        .map(|lab| {
+            let [l, a, b] = lab_to_working(lab, config.working_space);
+            [l, a, b, 0.0]
+        })
         .collect();
     let color_palette_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: Some("Color Palette Buffer"),
@@ -81,18 +1228,18 @@ pub async fn colorize(
         usage: wgpu::BufferUsages::STORAGE,
     });
 
-    let params = Params {
-        width,
-        height,
-        blend_factor: config.blend_factor,
-        dither_amount: config.dither_amount,
-        spatial_radius: config.spatial_averaging_radius,
-    };
-
-    let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("Params Buffer"),
-        contents: bytemuck::cast_slice(&[params]),
-        usage: wgpu::BufferUsages::UNIFORM,
+    // One counter per palette color, only written to when usage is collected
+    let usage_size = (std::mem::size_of::<u32>() * color_palette.len()) as wgpu::BufferAddress;
+    let usage_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Usage Buffer"),
+        contents: bytemuck::cast_slice(&vec![0u32; color_palette.len()]),
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+    });
+    let usage_staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Usage Staging Buffer"),
+        size: usage_size,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
     });
 
     // Load and compile the shaders
@@ -130,6 +1277,18 @@ pub async fn colorize(
                 binding: 3,
                 resource: params_buffer.as_entire_binding(),
             },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: mask_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: usage_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: dither_texture_buffer.as_entire_binding(),
+            },
         ],
     });
 
@@ -142,28 +1301,54 @@ pub async fn colorize(
                 encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
             compute_pass.set_pipeline(&compute_pipeline1);
             compute_pass.set_bind_group(0, &bind_group1, &[]);
-            compute_pass.dispatch_workgroups((width + 15) / 16, (height + 15) / 16, 1);
+            compute_pass.dispatch_workgroups(width.div_ceil(16), height.div_ceil(16), 1);
         }
         encoder.copy_buffer_to_buffer(&output_buffer1, 0, &staging_buffer, 0, buffer_size);
+        if usage.is_some() {
+            encoder.copy_buffer_to_buffer(&usage_buffer, 0, &usage_staging_buffer, 0, usage_size);
+        }
         queue.submit(Some(encoder.finish()));
     }
 
-    pb.inc(1);
+    if let Some(usage) = usage {
+        let usage_slice = usage_staging_buffer.slice(..);
+        let (sender, receiver) = futures::channel::oneshot::channel();
+        usage_slice.map_async(wgpu::MapMode::Read, move |v| {
+            let _ = sender.send(v);
+        });
+        device.poll(wgpu::Maintain::Wait);
+
+        receiver.await??;
+
+        let counts: Vec<u32> = bytemuck::cast_slice(&usage_slice.get_mapped_range()).to_vec();
+        for (total, count) in usage.iter_mut().zip(counts) {
+            *total += count as u64;
+        }
+        usage_staging_buffer.unmap();
+    }
 
     // Read back the result of the first pass
     let buffer_slice = staging_buffer.slice(..);
     let (sender, receiver) = futures::channel::oneshot::channel();
-    buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
+    buffer_slice.map_async(wgpu::MapMode::Read, move |v| {
+        // The receiver is only gone if colorize itself was dropped
+        let _ = sender.send(v);
+    });
     device.poll(wgpu::Maintain::Wait);
 
-    if let Ok(()) = receiver.await? {
-        let result = read_buffer(&buffer_slice);
-        staging_buffer.unmap();
+    receiver.await??;
 
-        process_result(&device, &queue, result, width, height, params_buffer, &pb).await
-    } else {
-        Err(anyhow::anyhow!("Failed to run compute on GPU!"))
-    }
+    let result = read_buffer(&buffer_slice);
+    staging_buffer.unmap();
+
+    let output = pool.install(|| pixels_to_image(&result, width, height));
+    debug!(
+        "First pass on the GPU took {:?} for {}x{} pixels",
+        started.elapsed(),
+        width,
+        height
+    );
+    Ok(output)
 }
 
 fn read_buffer(buffer_slice: &wgpu::BufferSlice) -> Vec<Pixel> {
@@ -171,15 +1356,85 @@ fn read_buffer(buffer_slice: &wgpu::BufferSlice) -> Vec<Pixel> {
     bytemuck::cast_slice(&data).to_vec()
 }
 
-async fn process_result(
+/// Runs the second pass `config.refine_iterations` times, each time on the output of the one
+/// before. Sharpening, chroma scaling, split toning, inverting the lightness, posterizing and the
+/// CVD simulation only apply to the last iteration, so they don't compound
+#[allow(clippy::too_many_arguments)]
+async fn refine<P: RgbPixel>(
     device: &wgpu::Device,
     queue: &wgpu::Queue,
-    result: Vec<Pixel>,
-    width: u32,
-    height: u32,
+    mut img: RgbBuffer<P>,
+    alpha: Option<&[u8]>,
+    mask: Option<&[u8]>,
+    config: &AppConfig,
+    params: &Params,
     params_buffer: wgpu::Buffer,
-    pb: &ProgressBar,
-) -> Result<RgbImage> {
+    pool: &ThreadPool,
+    pb: &Progress<'_>,
+) -> Result<RgbBuffer<P>, ColorizeError> {
+    pb.set_stage(Stage::SecondPass);
+
+    for _ in 1..config.refine_iterations {
+        let intermediate_params = Params {
+            sharpen_amount: 0.0,
+            chroma_scale: 1.0,
+            split_tone: 0,
+            invert_luminance: 0,
+            posterize_levels: 0,
+            cvd_type: 0,
+            ..*params
+        };
+        check_cancelled(pb)?;
+        img = process_result(
+            device,
+            queue,
+            img,
+            alpha,
+            mask,
+            config,
+            create_params_buffer(device, &intermediate_params),
+            pool,
+            pb,
+        )
+        .await?;
+    }
+
+    check_cancelled(pb)?;
+    process_result(
+        device,
+        queue,
+        img,
+        alpha,
+        mask,
+        config,
+        params_buffer,
+        pool,
+        pb,
+    )
+    .await
+}
+
+fn check_cancelled(pb: &Progress<'_>) -> Result<(), ColorizeError> {
+    if pb.is_cancelled() {
+        Err(ColorizeError::Cancelled)
+    } else {
+        Ok(())
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn process_result<P: RgbPixel>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    img: RgbBuffer<P>,
+    alpha: Option<&[u8]>,
+    mask: Option<&[u8]>,
+    config: &AppConfig,
+    params_buffer: wgpu::Buffer,
+    pool: &ThreadPool,
+    pb: &Progress<'_>,
+) -> Result<RgbBuffer<P>, ColorizeError> {
+    let (width, height) = img.dimensions();
     let buffer_size = (std::mem::size_of::<ColorizedPixel>() * width as usize * height as usize)
         as wgpu::BufferAddress;
     let shader2 = device.create_shader_module(wgpu::ShaderModuleDescriptor {
@@ -192,38 +1447,87 @@ async fn process_result(
         module: &shader2,
         entry_point: "main",
     });
-    let output_buffer2 = create_output_buffer(&device, width, height);
-    let staging_buffer = create_staging_buffer(&device, width, height);
-    // Convert to image for CPU processing
-    let mut img = ImageBuffer::new(width, height);
-    for (i, pixel) in result.iter().enumerate() {
-        let x = i as u32 % width;
-        let y = i as u32 / width;
-        img.put_pixel(
-            x,
-            y,
-            Rgb([
-                (pixel.r * 255.0) as u8,
-                (pixel.g * 255.0) as u8,
-                (pixel.b * 255.0) as u8,
-            ]),
-        );
-    }
+    let output_buffer2 = create_output_buffer(device, width, height);
+    let staging_buffer = create_staging_buffer(device, width, height);
+    let input_buffer = create_input_buffer(device, &as_view(&img));
+    let mask_buffer = create_mask_buffer(device, mask);
 
-    let input_buffer = create_input_buffer(&device, &img.clone().into());
+    // Perform CPU-based spatial averaging. The box and gaussian kernels are averaged per pixel up
+    // front, the box one through a summed area table, and the bilateral kernel is averaged on the
+    // GPU from the per pixel working space colors. A box of radius 0 only covers the pixel itself,
+    // so the summed area table is skipped for it
+    let space = config.working_space;
+    let averaging_started = Instant::now();
+    let input_data: Vec<SatEntry> = pool.install(|| match config.spatial_kernel {
+        SpatialKernel::Box if config.spatial_averaging_radius == 0 => {
+            let entries = weighted_pixels(&img, alpha, space)
+                .iter()
+                .map(|&[l, a, b, weight]| SatEntry { l, a, b, weight })
+                .collect();
+            pb.inc(width as u64 * height as u64);
+            entries
+        }
+        SpatialKernel::Box
+            if config.spatial_impl == SpatialImpl::Separable
+                && config.kernel_shape == KernelShape::Square
+                && !config.adaptive_radius
+                && config.multi_scale_weights.is_none() =>
+        {
+            separable_box_average(&img, alpha, space, config.spatial_averaging_radius, pb)
+                .iter()
+                .map(|&[l, a, b, weight]| SatEntry { l, a, b, weight })
+                .collect()
+        }
+        SpatialKernel::Box => {
+            let started = Instant::now();
+            let integral = compute_integral_image(&img, alpha, space, pb);
+            debug!("Summed area table took {:?}", started.elapsed());
 
-    // Perform CPU-based spatial averaging
-    let spatially_averaged = compute_integral_image(&img, pb);
+            let averages = if let Some(weights) = &config.multi_scale_weights {
+                multi_scale_box_average(
+                    &integral,
+                    space,
+                    config.spatial_averaging_radius,
+                    weights,
+                    config.kernel_shape,
+                )
+            } else if config.adaptive_radius {
+                let radii = adaptive_radii(&img, space, config.spatial_averaging_radius);
+                adaptive_box_average(&integral, &radii, config.kernel_shape)
+            } else {
+                box_average(
+                    &integral,
+                    config.spatial_averaging_radius,
+                    config.kernel_shape,
+                )
+            };
+            averages
+                .iter()
+                .map(|&[l, a, b, weight]| SatEntry { l, a, b, weight })
+                .collect()
+        }
+        SpatialKernel::Gaussian { sigma } => gaussian_average(&img, alpha, space, sigma, pb)
+            .iter()
+            .map(|&[l, a, b, weight]| SatEntry { l, a, b, weight })
+            .collect(),
+        SpatialKernel::Bilateral { .. } => {
+            let entries = weighted_pixels(&img, alpha, space)
+                .iter()
+                .map(|&[l, a, b, weight]| SatEntry { l, a, b, weight })
+                .collect();
+            pb.inc(width as u64 * height as u64);
+            entries
+        }
+    });
 
-    let input_data: Vec<ColorizedPixel> = spatially_averaged
-        .iter()
-        .flatten()
-        .map(|&p| ColorizedPixel {
-            r: p.0 as f32,
-            g: p.1 as f32,
-            b: p.2 as f32,
-        })
-        .collect();
+    // The averaging stops early once cancelled, leaving nothing worth sending to the GPU
+    check_cancelled(pb)?;
+    debug!(
+        "Spatial averaging with the {:?} kernel took {:?}",
+        config.spatial_kernel,
+        averaging_started.elapsed()
+    );
+    let gpu_started = Instant::now();
 
     // Create a new buffer with the spatially averaged result
     let spatially_averaged_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -252,6 +1556,10 @@ async fn process_result(
                 binding: 3,
                 resource: params_buffer.as_entire_binding(),
             },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: mask_buffer.as_entire_binding(),
+            },
         ],
     });
 
@@ -264,7 +1572,7 @@ async fn process_result(
                 encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
             compute_pass.set_pipeline(&compute_pipeline2);
             compute_pass.set_bind_group(0, &bind_group2, &[]);
-            compute_pass.dispatch_workgroups((width + 15) / 16, (height + 15) / 16, 1);
+            compute_pass.dispatch_workgroups(width.div_ceil(16), height.div_ceil(16), 1);
         }
         encoder.copy_buffer_to_buffer(&output_buffer2, 0, &staging_buffer, 0, buffer_size);
         queue.submit(Some(encoder.finish()));
@@ -273,45 +1581,108 @@ async fn process_result(
     // Read back the final result
     let buffer_slice = staging_buffer.slice(..);
     let (sender, receiver) = futures::channel::oneshot::channel();
-    buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
+    buffer_slice.map_async(wgpu::MapMode::Read, move |v| {
+        // The receiver is only gone if colorize itself was dropped
+        let _ = sender.send(v);
+    });
     device.poll(wgpu::Maintain::Wait);
 
-    if let Ok(()) = receiver.await? {
-        let result = read_buffer(&buffer_slice);
-        staging_buffer.unmap();
-
-        let mut output_image = ImageBuffer::new(width, height);
-        for (i, pixel) in result.iter().enumerate() {
-            let x = i as u32 % width;
-            let y = i as u32 / width;
-
-            output_image.put_pixel(
-                x,
-                y,
-                Rgb([
-                    (pixel.r * 255.0) as u8,
-                    (pixel.g * 255.0) as u8,
-                    (pixel.b * 255.0) as u8,
-                ]),
-            );
+    receiver.await??;
+
+    let result = read_buffer(&buffer_slice);
+    staging_buffer.unmap();
+
+    let output_image = pool.install(|| pixels_to_image(&result, width, height));
+    debug!(
+        "Second pass on the GPU took {:?} for {}x{} pixels",
+        gpu_started.elapsed(),
+        width,
+        height
+    );
+
+    pb.inc(1);
+
+    Ok(output_image)
+}
+
+/// Rayon pool limited to `AppConfig::threads`. Without a limit, when the global pool is already
+/// the right size, or when running on a pool within the limit like the ones `colorize_dir` splits
+/// the threads into, the current pool is used instead.
+#[cfg(feature = "parallel")]
+pub(crate) struct ThreadPool(Option<rayon::ThreadPool>);
+
+#[cfg(feature = "parallel")]
+impl ThreadPool {
+    pub(crate) fn new(threads: Option<usize>) -> Result<Self, ColorizeError> {
+        let fits = |threads| match rayon::current_thread_index() {
+            Some(_) => rayon::current_num_threads() <= threads,
+            None => rayon::current_num_threads() == threads,
+        };
+        match threads {
+            Some(threads) if !fits(threads) => Self::with_threads(threads),
+            _ => Ok(ThreadPool(None)),
         }
+    }
 
-        pb.finish_with_message("Processing complete!");
+    /// Pool of exactly `threads` threads, even when the current one has as many
+    pub(crate) fn with_threads(threads: usize) -> Result<Self, ColorizeError> {
+        Ok(ThreadPool(Some(
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()?,
+        )))
+    }
 
-        Ok(output_image)
-    } else {
-        Err(anyhow::anyhow!("Failed to run compute on GPU!"))
+    pub(crate) fn install<T: Send>(&self, op: impl FnOnce() -> T + Send) -> T {
+        match &self.0 {
+            Some(pool) => pool.install(op),
+            None => op(),
+        }
+    }
+}
+
+// Without the `parallel` feature there's only the calling thread, whatever `threads` asks for
+#[cfg(not(feature = "parallel"))]
+pub(crate) struct ThreadPool;
+
+#[cfg(not(feature = "parallel"))]
+impl ThreadPool {
+    pub(crate) fn new(_threads: Option<usize>) -> Result<Self, ColorizeError> {
+        Ok(ThreadPool)
     }
+
+    pub(crate) fn with_threads(_threads: usize) -> Result<Self, ColorizeError> {
+        Ok(ThreadPool)
+    }
+
+    pub(crate) fn install<T: Send>(&self, op: impl FnOnce() -> T + Send) -> T {
+        op()
+    }
+}
+
+fn pixels_to_image<P: RgbPixel>(pixels: &[Pixel], width: u32, height: u32) -> RgbBuffer<P> {
+    let mut img = RgbBuffer::<P>::new(width, height);
+    let row_length = width as usize * 3;
+
+    // Each row is written by whichever thread picks it up, so no locking is needed
+    img.par_chunks_mut(row_length)
+        .zip(pixels.par_chunks(width as usize))
+        .for_each(|(row, row_pixels)| {
+            for (output, pixel) in row.chunks_exact_mut(3).zip(row_pixels) {
+                *P::from_slice_mut(output) =
+                    P::from_scaled([pixel.r, pixel.g, pixel.b].map(|c| c * P::MAX));
+            }
+        });
+
+    img
 }
 
-fn create_input_buffer(device: &wgpu::Device, img: &DynamicImage) -> wgpu::Buffer {
+fn create_input_buffer<P: RgbPixel>(device: &wgpu::Device, img: &RgbView<'_, P>) -> wgpu::Buffer {
     let input_data: Vec<ColorizedPixel> = img
-        .to_rgb8()
         .pixels()
-        .map(|p| ColorizedPixel {
-            r: p[0] as f32 / 255.0,
-            g: p[1] as f32 / 255.0,
-            b: p[2] as f32 / 255.0,
+        .map(|p| {
+            let [r, g, b] = p.to_srgb();
+            ColorizedPixel { r, g, b }
         })
         .collect();
     device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -321,6 +1692,84 @@ fn create_input_buffer(device: &wgpu::Device, img: &DynamicImage) -> wgpu::Buffe
     })
 }
 
+// Without a mask the shaders never read the buffer, but it still has to be bound
+fn create_mask_buffer(device: &wgpu::Device, mask: Option<&[u8]>) -> wgpu::Buffer {
+    let mask_data: Vec<f32> = match mask {
+        Some(mask) => mask.iter().map(|&m| m as f32 / 255.0).collect(),
+        None => vec![1.0],
+    };
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Mask Buffer"),
+        contents: bytemuck::cast_slice(&mask_data),
+        usage: wgpu::BufferUsages::STORAGE,
+    })
+}
+
+// Thresholds are converted up front so the shader reads them the same way `texture_threshold`
+// computes them
+fn create_dither_texture_buffer(
+    device: &wgpu::Device,
+    texture: Option<&GrayImage>,
+) -> wgpu::Buffer {
+    let thresholds: Vec<f32> = match texture {
+        Some(texture) => texture
+            .enumerate_pixels()
+            .map(|(x, y, _)| texture_threshold(texture, x, y))
+            .collect(),
+        None => vec![0.5],
+    };
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Dither Texture Buffer"),
+        contents: bytemuck::cast_slice(&thresholds),
+        usage: wgpu::BufferUsages::STORAGE,
+    })
+}
+
+// Coverage of every pixel after lowering it by how skin colored the pixel is, starting from
+// `mask` or full coverage
+fn protect_skin<P: RgbPixel>(
+    input: &RgbView<'_, P>,
+    mask: Option<&[u8]>,
+    config: &AppConfig,
+) -> Vec<u8> {
+    let pixels: Vec<&P> = input.pixels().collect();
+    pixels
+        .par_iter()
+        .enumerate()
+        .map(|(i, pixel)| {
+            let lab = working_to_lab(pixel.to_working(WorkingSpace::Lab), WorkingSpace::Lab);
+            let coverage = mask.map_or(255.0, |mask| mask[i] as f32);
+            (coverage * (1.0 - config.skin_tone.protection(&lab))).round() as u8
+        })
+        .collect()
+}
+
+fn replace_only<P: RgbPixel>(
+    input: &RgbView<'_, P>,
+    mask: Option<&[u8]>,
+    keys: &[ColorKey],
+) -> Vec<u8> {
+    let pixels: Vec<&P> = input.pixels().collect();
+    pixels
+        .par_iter()
+        .enumerate()
+        .map(|(i, pixel)| {
+            // 16 bit pixels are compared at 8 bits, which is what the tolerance is given in
+            let rgb = pixel.to_srgb().map(|c| (c * 255.0).round() as u8);
+            let matches = keys.iter().any(|(key, tolerance)| {
+                rgb.iter()
+                    .zip(key.0)
+                    .all(|(c, k)| c.abs_diff(k) <= *tolerance)
+            });
+            match (matches, mask) {
+                (false, _) => 0,
+                (true, Some(mask)) => mask[i],
+                (true, None) => 255,
+            }
+        })
+        .collect()
+}
+
 fn create_output_buffer(device: &wgpu::Device, width: u32, height: u32) -> wgpu::Buffer {
     let buffer_size = (std::mem::size_of::<ColorizedPixel>() * width as usize * height as usize)
         as wgpu::BufferAddress;
@@ -103,3 +103,114 @@ pub const KANAGAWA: [&str; 103] = [
     "#5a7785", // lotusTeal3
     "#d7e3d8", // lotusCyan
 ];
+
+pub const NORD: [&str; 16] = [
+    "#2e3440", // nord0
+    "#3b4252", // nord1
+    "#434c5e", // nord2
+    "#4c566a", // nord3
+    "#d8dee9", // nord4
+    "#e5e9f0", // nord5
+    "#eceff4", // nord6
+    "#8fbcbb", // nord7
+    "#88c0d0", // nord8
+    "#81a1c1", // nord9
+    "#5e81ac", // nord10
+    "#bf616a", // nord11
+    "#d08770", // nord12
+    "#ebcb8b", // nord13
+    "#a3be8c", // nord14
+    "#b48ead", // nord15
+];
+
+pub const GRUVBOX: [&str; 27] = [
+    "#1d2021", // bg0_h
+    "#282828", // bg0
+    "#32302f", // bg0_s
+    "#3c3836", // bg1
+    "#504945", // bg2
+    "#665c54", // bg3
+    "#7c6f64", // bg4
+    "#928374", // gray
+    "#a89984", // fg4
+    "#bdae93", // fg3
+    "#d5c4a1", // fg2
+    "#ebdbb2", // fg1
+    "#fbf1c7", // fg0
+    "#cc241d", // red
+    "#98971a", // green
+    "#d79921", // yellow
+    "#458588", // blue
+    "#b16286", // purple
+    "#689d6a", // aqua
+    "#d65d0e", // orange
+    "#fb4934", // bright_red
+    "#b8bb26", // bright_green
+    "#fabd2f", // bright_yellow
+    "#83a598", // bright_blue
+    "#d3869b", // bright_purple
+    "#8ec07c", // bright_aqua
+    "#fe8019", // bright_orange
+];
+
+pub const SOLARIZED: [&str; 16] = [
+    "#002b36", // base03
+    "#073642", // base02
+    "#586e75", // base01
+    "#657b83", // base00
+    "#839496", // base0
+    "#93a1a1", // base1
+    "#eee8d5", // base2
+    "#fdf6e3", // base3
+    "#b58900", // yellow
+    "#cb4b16", // orange
+    "#dc322f", // red
+    "#d33682", // magenta
+    "#6c71c4", // violet
+    "#268bd2", // blue
+    "#2aa198", // cyan
+    "#859900", // green
+];
+
+pub const DRACULA: [&str; 11] = [
+    "#282a36", // background
+    "#44475a", // current line
+    "#f8f8f2", // foreground
+    "#6272a4", // comment
+    "#8be9fd", // cyan
+    "#50fa7b", // green
+    "#ffb86c", // orange
+    "#ff79c6", // pink
+    "#bd93f9", // purple
+    "#ff5555", // red
+    "#f1fa8c", // yellow
+];
+
+pub const CATPPUCCIN: [&str; 26] = [
+    "#f5e0dc", // rosewater
+    "#f2cdcd", // flamingo
+    "#f5c2e7", // pink
+    "#cba6f7", // mauve
+    "#f38ba8", // red
+    "#eba0ac", // maroon
+    "#fab387", // peach
+    "#f9e2af", // yellow
+    "#a6e3a1", // green
+    "#94e2d5", // teal
+    "#89dceb", // sky
+    "#74c7ec", // sapphire
+    "#89b4fa", // blue
+    "#b4befe", // lavender
+    "#cdd6f4", // text
+    "#bac2de", // subtext1
+    "#a6adc8", // subtext0
+    "#9399b2", // overlay2
+    "#7f849c", // overlay1
+    "#6c7086", // overlay0
+    "#585b70", // surface2
+    "#45475a", // surface1
+    "#313244", // surface0
+    "#1e1e2e", // base
+    "#181825", // mantle
+    "#11111b", // crust
+];
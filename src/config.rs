@@ -1,7 +1,15 @@
-use crate::colors::KANAGAWA;
-use crate::constants::VERSION;
-use crate::types::AppConfig;
-use crate::utils::{hex_to_rgb, interpolate_color};
+use image_colorizer::colorize::ColorizeError;
+use image_colorizer::constants::{KMEANS_ITERATIONS, KMEANS_SEED, MAX_SOFT_K, VERSION};
+use image_colorizer::palette::{
+    builtin, extract_kmeans, load_gpl, load_json, parse_color_keys, parse_hex_list, save_gpl,
+    save_json, Palette, PaletteError,
+};
+use image_colorizer::types::{
+    AppConfig, BlendFactor, BlendSpace, CvdType, DistanceMetric, DitherAlgorithm, GamutMapping,
+    KernelShape, MappingMode, SkinTone, SpatialImpl, SpatialKernel, SplitTone, ToneMap,
+    WorkingSpace,
+};
+use image_colorizer::utils::{hex_to_rgb, interpolate_color};
 
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -13,9 +21,7 @@ use config::{ConfigBuilder, ConfigError, File};
 use futures::StreamExt;
 use indicatif::{ProgressBar, ProgressStyle};
 use palette::{color_difference::ImprovedCiede2000, FromColor, Lab};
-use reqwest;
 use serde_derive::Deserialize;
-use toml;
 
 #[derive(Debug)]
 pub enum AppError {
@@ -23,6 +29,8 @@ pub enum AppError {
     Image(image::ImageError),
     Config(ConfigError),
     Toml(toml::de::Error),
+    Palette(PaletteError),
+    Colorize(ColorizeError),
     DownloadError(String),
     Other(String),
 }
@@ -34,6 +42,8 @@ impl std::fmt::Display for AppError {
             AppError::Image(err) => write!(f, "Image error: {}", err),
             AppError::Config(err) => write!(f, "Config error: {}", err),
             AppError::Toml(err) => write!(f, "TOML error: {}", err),
+            AppError::Palette(err) => write!(f, "Palette error: {}", err),
+            AppError::Colorize(err) => write!(f, "Colorize error: {}", err),
             AppError::DownloadError(err) => write!(f, "Download error: {}", err),
             AppError::Other(err) => write!(f, "Error: {}", err),
         }
@@ -66,6 +76,18 @@ impl From<toml::de::Error> for AppError {
     }
 }
 
+impl From<PaletteError> for AppError {
+    fn from(err: PaletteError) -> AppError {
+        AppError::Palette(err)
+    }
+}
+
+impl From<ColorizeError> for AppError {
+    fn from(err: ColorizeError) -> AppError {
+        AppError::Colorize(err)
+    }
+}
+
 impl From<String> for AppError {
     fn from(err: String) -> AppError {
         AppError::Other(err)
@@ -81,11 +103,51 @@ impl From<reqwest::Error> for AppError {
 #[derive(Debug, Deserialize)]
 struct SerializedAppConfig {
     blend_factor: String,
+    blend_space: String,
+    transfer_luminance: bool,
+    invert_luminance: bool,
+    gamut_mapping: String,
+    tone_map: String,
     colorscheme: String,
     interpolate_colors: bool,
+    show_progress: bool,
+    force_progress: bool,
+    jpeg_quality: String,
+    threads: Option<String>,
+    tile_height: Option<String>,
+    posterize_levels: Option<String>,
+    simulate_cvd: Option<String>,
+    split_tone: Option<String>,
+    replace_only: Option<String>,
     interpolation_threshold: String,
     dither_amount: String,
+    dither_seed: String,
+    dither_algorithm: String,
+    serpentine: bool,
+    temporal_coherence: bool,
+    mapping_mode: String,
+    distance_metric: String,
+    soft_k: String,
+    stochastic_assignment: bool,
+    luminance_weight: String,
+    preserve_extremes: bool,
+    extreme_threshold: String,
+    protect_skin: bool,
+    skin_tone: String,
+    temperature: String,
+    tint: String,
+    brightness: String,
+    contrast: String,
     spatial_averaging_radius: String,
+    adaptive_radius: bool,
+    multi_scale_weights: Option<String>,
+    spatial_kernel: String,
+    refine_iterations: String,
+    sharpen_amount: String,
+    chroma_scale: String,
+    kernel_shape: String,
+    spatial_impl: String,
+    working_space: String,
 }
 
 #[derive(Debug)]
@@ -99,11 +161,44 @@ fn load_config(config_path: Option<&str>) -> Result<ConfigInfo, AppError> {
 
     builder = builder
         .set_default("blend_factor", "0.9")?
+        .set_default("blend_space", "linear-rgb")?
+        .set_default("transfer_luminance", true)?
+        .set_default("invert_luminance", false)?
+        .set_default("gamut_mapping", "clip")?
+        .set_default("tone_map", "none")?
         .set_default("colorscheme", "kanagawa")?
         .set_default("interpolate_colors", true)?
+        .set_default("show_progress", true)?
+        .set_default("force_progress", false)?
+        .set_default("jpeg_quality", "90")?
         .set_default("interpolation_threshold", "2.5")?
         .set_default("dither_amount", "0.1")?
-        .set_default("spatial_averaging_radius", "10")?;
+        .set_default("dither_seed", "0")?
+        .set_default("dither_algorithm", "random")?
+        .set_default("serpentine", false)?
+        .set_default("temporal_coherence", false)?
+        .set_default("mapping_mode", "nearest")?
+        .set_default("distance_metric", "cie76")?
+        .set_default("soft_k", "1")?
+        .set_default("stochastic_assignment", false)?
+        .set_default("luminance_weight", "1.0")?
+        .set_default("preserve_extremes", false)?
+        .set_default("extreme_threshold", "0")?
+        .set_default("protect_skin", false)?
+        .set_default("skin_tone", "14,26,10,20")?
+        .set_default("temperature", "0.0")?
+        .set_default("tint", "0.0")?
+        .set_default("brightness", "0.0")?
+        .set_default("contrast", "1.0")?
+        .set_default("spatial_averaging_radius", "10")?
+        .set_default("adaptive_radius", false)?
+        .set_default("spatial_kernel", "box")?
+        .set_default("refine_iterations", "1")?
+        .set_default("sharpen_amount", "0.0")?
+        .set_default("chroma_scale", "1.0")?
+        .set_default("kernel_shape", "square")?
+        .set_default("spatial_impl", "integral")?
+        .set_default("working_space", "lab")?;
 
     let default_config_dir = dirs::home_dir()
         .unwrap_or_else(|| PathBuf::from(""))
@@ -136,22 +231,28 @@ fn load_config(config_path: Option<&str>) -> Result<ConfigInfo, AppError> {
     })
 }
 
-async fn load_colorscheme(name: &str, config_dir: &Path) -> Result<Vec<String>, AppError> {
+async fn load_colorscheme(
+    name: &str,
+    config_dir: &Path,
+    quiet: bool,
+) -> Result<Vec<Lab>, AppError> {
     let colorscheme_path = config_dir.join(format!("{}.txt", name));
 
     if colorscheme_path.exists() {
         // Load from local file
         let colorscheme_str = fs::read_to_string(&colorscheme_path)?;
         parse_and_validate_colorscheme(&colorscheme_str, name)
-    } else if name == "kanagawa" {
+    } else if let Some(colors) = builtin(name) {
         // Built-in colorscheme
-        Ok(KANAGAWA.iter().map(|&s| s.to_string()).collect())
+        Ok(colors)
     } else {
         // Show warning
-        eprintln!(
-            "Warning: Colorscheme '{}' not found locally. Attempting to download from GitHub...",
-            name
-        );
+        if !quiet {
+            eprintln!(
+                "Warning: Colorscheme '{}' not found locally. Attempting to download from GitHub...",
+                name
+            );
+        }
 
         // Attempt to download from GitHub
         match download_colorscheme_from_github(name).await {
@@ -159,8 +260,11 @@ async fn load_colorscheme(name: &str, config_dir: &Path) -> Result<Vec<String>,
                 let colorscheme = parse_and_validate_colorscheme(&colorscheme_str, name)?;
 
                 // Save the downloaded scheme
-                if let Err(e) = save_colorscheme(&colorscheme_path, &colorscheme_str) {
-                    eprintln!("Warning: Failed to save downloaded colorscheme: {}", e);
+                match save_colorscheme(&colorscheme_path, &colorscheme_str) {
+                    Err(e) if !quiet => {
+                        eprintln!("Warning: Failed to save downloaded colorscheme: {}", e)
+                    }
+                    _ => {}
                 }
 
                 Ok(colorscheme)
@@ -170,12 +274,15 @@ async fn load_colorscheme(name: &str, config_dir: &Path) -> Result<Vec<String>,
     }
 }
 
-fn parse_and_validate_colorscheme(content: &str, name: &str) -> Result<Vec<String>, AppError> {
+fn parse_and_validate_colorscheme(content: &str, name: &str) -> Result<Vec<Lab>, AppError> {
     let colorscheme = parse_colorscheme(content);
     if colorscheme.is_empty() {
         Err(AppError::Other(format!("Colorscheme '{}' is empty", name)))
     } else {
-        Ok(colorscheme)
+        colorscheme
+            .iter()
+            .map(|hex| hex_to_rgb(hex).map(Lab::from_color).map_err(AppError::from))
+            .collect()
     }
 }
 
@@ -240,7 +347,7 @@ fn parse_colorscheme(content: &str) -> Vec<String> {
 }
 
 fn interpolate_colors(mut colors: Vec<Lab>, threshold: f32) -> Vec<Lab> {
-    colors.sort_by(|a, b| a.l.partial_cmp(&b.l).unwrap());
+    colors.sort_by(|a, b| a.l.total_cmp(&b.l));
 
     let mut interpolated = Vec::new();
     for window in colors.windows(2) {
@@ -268,13 +375,13 @@ pub async fn init() -> Result<Arc<AppConfig>, AppError> {
         .version(VERSION)
         .author("Taylor Beeston")
         .about("Applies color schemes to images")
-        .after_help("Colorscheme is a string that should be the name of a colorscheme txt file (minus the extension) in the same directory as the config file. For example if 'kanagawa' is used as the name of the colorscheme string, there should be a 'kanagawa.txt' file in the same directory as the config file. If the file is not found, a colorscheme with that name will attempt to be downloaded into your config directory from github.\n\nColorscheme files are simple files with one hex code per line and may optionally have comments using double slashes, e.g.\n\n// Grayscale\n#fff\n#000")
+        .after_help("Colorscheme is a string that should be the name of a colorscheme txt file (minus the extension) in the same directory as the config file. For example if 'kanagawa' is used as the name of the colorscheme string, there should be a 'kanagawa.txt' file in the same directory as the config file. If the file is not found, one of the built-in colorschemes (kanagawa, nord, gruvbox, solarized, dracula, catppuccin) is used, and otherwise a colorscheme with that name will attempt to be downloaded into your config directory from github. A GIMP palette or JSON palette named e.g. 'kanagawa.gpl' or 'kanagawa.json' in the same directory will be used instead of the txt file if it exists.\n\nColorscheme files are simple files with one hex code per line and may optionally have comments using double slashes, e.g.\n\n// Grayscale\n#fff\n#000")
         .arg(
             Arg::with_name("Blend Factor")
                 .short('b')
                 .long("blend-factor")
                 .value_name("FACTOR")
-                .help("[0.0-1.0] (Default: 0.9) Sets the blend factor, which allows part of the original image to come through. 0 = Just use the original image, 1 = Use only the colorized image")
+                .help("[0.0-1.0] (Default: 0.9) Sets the blend factor, which allows part of the original image to come through. 0 = Just use the original image, 1 = Use only the colorized image. Three comma separated values, e.g. 0.5,0.9,1.0, set the red, green and blue factors separately")
                 .takes_value(true),
         )
         .arg(
@@ -290,6 +397,93 @@ pub async fn init() -> Result<Arc<AppConfig>, AppError> {
                 .takes_value(false)
                 .help("Disables color interpolation. Setting this causes interpolation threshold to do nothing")
         )
+        .arg(
+            Arg::with_name("No Linear Blend")
+                .long("no-linear-blend")
+                .takes_value(false)
+                .help("Same as --blend-space srgb")
+        )
+        .arg(
+            Arg::with_name("Blend Space")
+                .long("blend-space")
+                .value_name("SPACE")
+                .help("[srgb, linear-rgb, lab] (Default: linear-rgb) Sets the color space the colorized image is blended with the original in. srgb mixes the gamma encoded values like older versions did, which darkens midtones and muddies gradients between saturated colors. lab tends to keep saturated colors cleaner, with per channel blend factors applying to L, a and b in order")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("No Luminance Transfer")
+                .long("no-luminance-transfer")
+                .takes_value(false)
+                .help("Uses the lightness of the palette colors instead of keeping the lightness of the original image, for a flatter, poster-like result")
+        )
+        .arg(
+            Arg::with_name("Invert Luminance")
+                .long("invert-luminance")
+                .takes_value(false)
+                .help("Flips the lightness of the result, so dark areas come out light and light areas dark while keeping the colors of the colorscheme. For stylized, negative-like effects")
+        )
+        .arg(
+            Arg::with_name("Gamut Mapping")
+                .long("gamut-mapping")
+                .value_name("MAPPING")
+                .help("[clip, desaturate] (Default: clip) Sets how colors that end up outside of sRGB after luminance transfer are brought back. clip clamps each channel, which can shift the hue of saturated colors into neon artifacts. desaturate lowers their saturation until they fit, keeping hue and lightness")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Tone Map")
+                .long("tone-map")
+                .value_name("OPERATOR")
+                .help("[none, reinhard, aces] (Default: none) Sets how floating point images such as EXR and HDR renders are brought into displayable range before colorizing. none clamps everything above 1.0 to white. reinhard compresses highlights gently and keeps their hue, aces gives a punchier filmic look")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Threads")
+                .long("threads")
+                .value_name("THREADS")
+                .help("(Default: all cores) Sets how many threads the CPU side of colorizing may use, which keeps it from taking over shared machines")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Tile Height")
+                .long("tile-height")
+                .value_name("ROWS")
+                .help("(Default: off) Colorizes the image in horizontal strips of this many rows, so memory use is bounded by the strip instead of the whole image. Useful for very large scans")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Dry Run")
+                .long("dry-run")
+                .takes_value(false)
+                .help("Prints the estimated peak memory and running time of every input instead of colorizing it, e.g. to decide whether a huge image needs --tile-height. Nothing is written")
+        )
+        .arg(
+            Arg::with_name("JPEG Quality")
+                .long("jpeg-quality")
+                .value_name("QUALITY")
+                .help("[1-100] (Default: 90) Sets the quality JPEG output is saved with. Lower values give smaller files with more compression artifacts")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Serial")
+                .long("serial")
+                .takes_value(false)
+                .conflicts_with("Threads")
+                .help("Runs the CPU side of colorizing on a single thread, one image and one row after the other. Same as --threads 1, meant for debugging and profiling. The output is identical to a parallel run")
+        )
+        .arg(
+            Arg::with_name("Quiet")
+                .short('q')
+                .long("quiet")
+                .takes_value(false)
+                .help("Hides the progress bars and warnings, so nothing is written to stderr unless processing fails. Useful for scripts and CI logs")
+        )
+        .arg(
+            Arg::with_name("Progress")
+                .long("progress")
+                .takes_value(false)
+                .conflicts_with("Quiet")
+                .help("Draws the progress bars even when stderr isn't a terminal. They're hidden automatically when it's piped or redirected, since their control sequences garble logs")
+        )
         .arg(
             Arg::with_name("Dither Amount")
                 .short('d')
@@ -298,6 +492,130 @@ pub async fn init() -> Result<Arc<AppConfig>, AppError> {
                 .help("[0.0-1.0] (Default: 0.1) Sets the amount of dithering, which helps reduce artifacting by adding some randomness to the colorization process")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("Dither Seed")
+                .long("dither-seed")
+                .value_name("SEED")
+                .help("[0-18446744073709551615] (Default: 0) Seeds the noise of random dithering. The same seed always gives the same output, and changing it changes the noise pattern")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Dither Algorithm")
+                .long("dither-algorithm")
+                .value_name("ALGORITHM")
+                .help("[none, random, ordered, ordered-2, ordered-4, ordered-8, floyd-steinberg, atkinson] (Default: random) Sets the dithering algorithm. None only maps each pixel to the colorscheme, random adds noise to each pixel independently on the GPU, ordered offsets each pixel by a tileable Bayer matrix (4x4 unless a size is given), while floyd-steinberg and atkinson diffuse the quantization error of each pixel to its neighbors on the CPU. Dither amount does not affect error diffusion")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Serpentine")
+                .long("serpentine")
+                .takes_value(false)
+                .help("Diffuses the error of every other row from right to left, which avoids the rightward streaks of plain scanline order. Only affects floyd-steinberg and atkinson")
+        )
+        .arg(
+            Arg::with_name("Temporal Coherence")
+                .long("temporal-coherence")
+                .takes_value(false)
+                .help("Keeps the colorized pixels of the previous frame of an animated GIF wherever the source hasn't changed, so the dither pattern of static regions doesn't shimmer and only moving content changes. Works with every dithering algorithm, including error diffusion")
+        )
+        .arg(
+            Arg::with_name("Mapping Mode")
+                .long("mapping-mode")
+                .value_name("MODE")
+                .help("[nearest, gradient-map] (Default: nearest) Sets how each pixel picks its color. nearest uses the closest color in the colorscheme, while gradient-map treats the colorscheme as a gradient from shadows to highlights in the given order and picks the point along it by the lightness of the pixel, for duotone and tritone effects. Colorschemes are not interpolated for gradient-map")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Distance Metric")
+                .long("distance-metric")
+                .value_name("METRIC")
+                .help("[cie76, cie94, ciede2000] (Default: cie76) Sets how the distance between colors is measured when picking the closest color in the colorscheme. cie76 is the fastest, ciede2000 is the most perceptually accurate")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Soft K")
+                .long("soft-k")
+                .value_name("K")
+                .help("[1-8] (Default: 1) Blends this many of the closest colors in the colorscheme for each pixel, weighted by how close they are, instead of snapping to the single closest one. Smooths banding on gradients without dithering, at the cost of colors that aren't exactly in the colorscheme")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Stochastic Assignment")
+                .long("stochastic-assignment")
+                .takes_value(false)
+                .help("Picks one of the 3 closest colors in the colorscheme for each pixel at random, the closer ones more likely, so areas between two colors split between them in a grainy, uncorrelated texture. The pattern follows --dither-seed. Ignored when --soft-k is above 1")
+        )
+        .arg(
+            Arg::with_name("Luminance Weight")
+                .long("luminance-weight")
+                .value_name("WEIGHT")
+                .help("[0.0-10.0] (Default: 1.0) Scales how much differences in brightness count when picking the closest color in the colorscheme. Values above 1.0 keep dark images from picking colors with the wrong brightness")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Preserve Extremes")
+                .long("preserve-extremes")
+                .takes_value(false)
+                .help("Leaves black and white pixels as they are instead of mapping them onto the colorscheme, which keeps the edges of line art and screenshots crisp")
+        )
+        .arg(
+            Arg::with_name("Extreme Threshold")
+                .long("extreme-threshold")
+                .value_name("THRESHOLD")
+                .help("[0-255] (Default: 0) Sets how far every channel may be from 0 or 255 for a pixel to still count as black or white. Must set --preserve-extremes for this to have an effect")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Protect Skin")
+                .long("protect-skin")
+                .takes_value(false)
+                .help("Lowers the blend factor of skin colored pixels, down to keeping them as they are, so faces in portraits don't take on unflattering colors")
+        )
+        .arg(
+            Arg::with_name("Skin Tone")
+                .long("skin-tone")
+                .value_name("A,B,RADIUS_A,RADIUS_B")
+                .help("(Default: 14,26,10,20) Sets the ellipse in the a/b plane of CIELAB that counts as skin. Pixels within half of it are kept as they are and the protection fades out towards its edge. Must set --protect-skin for this to have an effect")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Replace Only")
+                .long("replace-only")
+                .value_name("HEX[:TOLERANCE],...")
+                .help("[hex:0-255] (Default: off) Only colorizes pixels within the tolerance of one of these source colors on every RGB channel, e.g. '#e02020:40,#ffffff'. Every other pixel is left as it is, which is handy for recoloring logos and sprites. The tolerance is 0 when left out")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Temperature")
+                .long("temperature")
+                .value_name("AMOUNT")
+                .allow_hyphen_values(true)
+                .help("[-100.0-100.0] (Default: 0.0) Warms the image towards amber when positive or cools it towards blue when negative before colorizing, in Lab b. Corrects color casts without editing the source image")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Tint")
+                .long("tint")
+                .value_name("AMOUNT")
+                .allow_hyphen_values(true)
+                .help("[-100.0-100.0] (Default: 0.0) Shifts the image towards magenta when positive or green when negative before colorizing, in Lab a")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Brightness")
+                .long("brightness")
+                .value_name("AMOUNT")
+                .allow_hyphen_values(true)
+                .help("[-100.0-100.0] (Default: 0.0) Brightens or darkens the image before colorizing, in Lab lightness. Keeps underexposed photos from mapping onto the darkest colors of the colorscheme")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Contrast")
+                .long("contrast")
+                .value_name("FACTOR")
+                .help("[0.0-5.0] (Default: 1.0) Scales the contrast of the image before colorizing. Values above 1.0 spread the image over more of the colorscheme")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("Spatial Averaging Radius")
                 .long("spatial-averaging-radius")
@@ -305,6 +623,89 @@ pub async fn init() -> Result<Arc<AppConfig>, AppError> {
                 .help("[0-100] (Default: 10) Sets the Spatial Averaging Radius to use when performing spatial averaging. Spatial Averaging has each pixel use the colors of the pixels around it to get it's final color, reducing artifacting")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("Adaptive Radius")
+                .long("adaptive-radius")
+                .takes_value(false)
+                .help("Shrinks the Spatial Averaging Radius where the brightness of the image changes sharply, so flat areas are smoothed fully while edges and fine detail aren't smeared. Only affects the box spatial kernel")
+        )
+        .arg(
+            Arg::with_name("Multi Scale Weights")
+                .long("multi-scale-weights")
+                .value_name("WEIGHTS")
+                .help("[1-4 comma separated weights] (Default: off) Averages at the Spatial Averaging Radius and at 2, 4 and 8 times it, one level per weight, and blends the levels by these weights, e.g. 1,0.5,0.25. The larger radii only count fully in flat areas, so large gradients come out smoother while edges keep their detail. Replaces --adaptive-radius and only affects the box spatial kernel")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Spatial Kernel")
+                .long("spatial-kernel")
+                .value_name("KERNEL")
+                .help("[box, gaussian, gaussian-<sigma>, bilateral, bilateral-<spatial sigma>-<range sigma>] (Default: box) Sets how neighboring pixels are weighted during spatial averaging. box weights every pixel within the Spatial Averaging Radius equally and is the fastest, gaussian weights nearby pixels more heavily (sigma 5 unless given) and ignores the radius, which bleeds less across edges. bilateral also weights neighbors by how similar their color is (sigmas 5 and 10 unless given, the range sigma is in working space a/b units), which preserves sharp edges but is the slowest")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Refine Iterations")
+                .long("refine-iterations")
+                .value_name("ITERATIONS")
+                .help("[1-] (Default: 1) Sets how many times spatial averaging and luminance transfer run, each time on the result of the one before. 2 or 3 settle the colors of very grainy scans further, at the cost of time per iteration")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Sharpen Amount")
+                .long("sharpen")
+                .value_name("AMOUNT")
+                .help("[0.0-5.0] (Default: 0.0) Sharpens the lightness of the result after spatial averaging with an unsharp mask, bringing back fine detail. 0.0 disables it")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Chroma Scale")
+                .long("chroma-scale")
+                .value_name("SCALE")
+                .help("[0.0-] (Default: 1.0) Scales the saturation of the result after spatial averaging. Above 1.0 makes up for the saturation averaging takes away, below 1.0 mutes the colors")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Posterize Levels")
+                .long("posterize")
+                .value_name("LEVELS")
+                .help("[2-255] (Default: off) Rounds the red, green and blue channels of the result to this many evenly spaced levels, for a poster-like effect. 2 gives a hard two-tone per channel")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Split Tone")
+                .long("split-tone")
+                .value_name("SHADOW,HIGHLIGHT[,BALANCE]")
+                .help("[hex,hex,0.0-1.0] (Default: off) Tints the shadows towards the first color and the highlights towards the second, e.g. '#2a7f7f,#f0a050' for teal and orange. The balance sets the lightness the two meet at, 0.5 when left out")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Simulate CVD")
+                .long("simulate-cvd")
+                .value_name("TYPE")
+                .help("[protanopia, deuteranopia, tritanopia] (Default: off) Shows the result the way it looks with a color vision deficiency, for checking how accessible a colorscheme is. Applied after everything else")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Kernel Shape")
+                .long("kernel-shape")
+                .value_name("SHAPE")
+                .help("[square, circle] (Default: square) Sets the shape of the region averaged by the box spatial kernel. circle gives less blocky smoothing, but costs time proportional to the Spatial Averaging Radius for every pixel while square takes the same time for any radius")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Spatial Impl")
+                .long("spatial-impl")
+                .value_name("IMPL")
+                .help("[integral, separable] (Default: integral) Sets how the square box spatial kernel is averaged. separable runs a horizontal and then a vertical running sum, which needs far less memory than the summed area table and is faster for small radii. Circles always use the summed area table")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Working Space")
+                .long("working-space")
+                .value_name("SPACE")
+                .help("[lab, oklab] (Default: lab) Sets the color space used to match, dither and average colors. oklab gives more even hue transitions, particularly for blues. Distance metrics other than cie76 only apply to lab")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("Colorscheme")
                 .short('s')
@@ -313,6 +714,34 @@ pub async fn init() -> Result<Arc<AppConfig>, AppError> {
                 .help("(Default: kanagawa) Sets the colorscheme to use")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("Colors")
+                .long("colors")
+                .value_name("HEX_LIST")
+                .help("Comma or newline separated list of hex colors to use instead of a colorscheme, e.g. '#1d2021,#fbf1c7,#cc241d'")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Reference Image")
+                .long("reference-image")
+                .value_name("/path/to/image")
+                .help("Uses the dominant colors of another image instead of a colorscheme")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Reference Colors")
+                .long("reference-colors")
+                .value_name("COUNT")
+                .help("(Default: 16) Sets how many colors are extracted from the reference image")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("Export Palette")
+                .long("export-palette")
+                .value_name("/path/to/palette.json")
+                .help("Saves the colors used for colorizing, after interpolation, to a JSON palette, or to a GIMP palette if the path ends in .gpl")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("Config")
                 .short('c')
@@ -331,7 +760,7 @@ pub async fn init() -> Result<Arc<AppConfig>, AppError> {
         )
         .arg(
             Arg::with_name("Image Paths")
-                .help("Paths to the images you'd like to colorize")
+                .help("Paths to the images you'd like to colorize. Every image inside a directory is colorized, keeping its file name")
                 .required(true)
                 .multiple(true)
                 .index(1),
@@ -341,10 +770,15 @@ pub async fn init() -> Result<Arc<AppConfig>, AppError> {
     let ConfigInfo { config, config_dir } = load_config(matches.value_of("Config"))?;
 
     let input_paths: Vec<&str> = matches.values_of("Image Paths").unwrap().collect();
-    let output_dir = matches.value_of("output").map(PathBuf::from);
+    let output_dir = matches.value_of("Output").map(PathBuf::from);
 
+    let (input_dirs, input_paths): (Vec<&str>, Vec<&str>) = input_paths
+        .into_iter()
+        .partition(|path| Path::new(path).is_dir());
     let input_output_pairs =
-        generate_input_output_pairs(&input_paths, output_dir, &config.colorscheme)?;
+        generate_input_output_pairs(&input_paths, output_dir.clone(), &config.colorscheme)?;
+    let input_output_dirs =
+        generate_input_output_dirs(&input_dirs, output_dir, &config.colorscheme);
 
     let colorscheme = matches
         .value_of("Colorscheme")
@@ -354,10 +788,64 @@ pub async fn init() -> Result<Arc<AppConfig>, AppError> {
         .value_of("Blend Factor")
         .unwrap_or(&config.blend_factor);
 
-    let blend_factor: f32 = blend_factor
+    let blend_factor: BlendFactor = blend_factor
         .parse()
         .map_err(|e| format!("Failed to parse blend_factor: {}", e))?;
 
+    let blend_space: BlendSpace = if matches.is_present("No Linear Blend") {
+        BlendSpace::Srgb
+    } else {
+        matches
+            .value_of("Blend Space")
+            .unwrap_or(&config.blend_space)
+            .parse()
+            .map_err(|e| format!("Failed to parse blend_space: {}", e))?
+    };
+
+    let transfer_luminance =
+        !matches.is_present("No Luminance Transfer") && config.transfer_luminance;
+
+    let invert_luminance = matches.is_present("Invert Luminance") || config.invert_luminance;
+
+    let gamut_mapping: GamutMapping = matches
+        .value_of("Gamut Mapping")
+        .unwrap_or(&config.gamut_mapping)
+        .parse()
+        .map_err(|e| format!("Failed to parse gamut_mapping: {}", e))?;
+
+    let tone_map: ToneMap = matches
+        .value_of("Tone Map")
+        .unwrap_or(&config.tone_map)
+        .parse()
+        .map_err(|e| format!("Failed to parse tone_map: {}", e))?;
+
+    let threads: Option<usize> = matches
+        .is_present("Serial")
+        .then_some("1")
+        .or(matches.value_of("Threads"))
+        .or(config.threads.as_deref())
+        .map(str::parse)
+        .transpose()
+        .map_err(|e| format!("Failed to parse threads: {}", e))?;
+
+    let tile_height: Option<u32> = matches
+        .value_of("Tile Height")
+        .or(config.tile_height.as_deref())
+        .map(str::parse)
+        .transpose()
+        .map_err(|e| format!("Failed to parse tile height: {}", e))?;
+
+    let show_progress = !matches.is_present("Quiet") && config.show_progress;
+    let force_progress = matches.is_present("Progress") || config.force_progress;
+
+    let dry_run = matches.is_present("Dry Run");
+
+    let jpeg_quality: u8 = matches
+        .value_of("JPEG Quality")
+        .unwrap_or(&config.jpeg_quality)
+        .parse()
+        .map_err(|e| format!("Failed to parse jpeg_quality: {}", e))?;
+
     let should_interpolate_colors = if matches.is_present("No Interpolation") {
         false
     } else {
@@ -380,6 +868,102 @@ pub async fn init() -> Result<Arc<AppConfig>, AppError> {
         .parse()
         .map_err(|e| format!("Failed to parse dither_amount: {}", e))?;
 
+    let dither_seed: u64 = matches
+        .value_of("Dither Seed")
+        .unwrap_or(&config.dither_seed)
+        .parse()
+        .map_err(|e| format!("Failed to parse dither_seed: {}", e))?;
+
+    let dither_algorithm: DitherAlgorithm = matches
+        .value_of("Dither Algorithm")
+        .unwrap_or(&config.dither_algorithm)
+        .parse()
+        .map_err(|e| format!("Failed to parse dither_algorithm: {}", e))?;
+
+    let serpentine = matches.is_present("Serpentine") || config.serpentine;
+
+    let temporal_coherence = matches.is_present("Temporal Coherence") || config.temporal_coherence;
+
+    let mapping_mode: MappingMode = matches
+        .value_of("Mapping Mode")
+        .unwrap_or(&config.mapping_mode)
+        .parse()
+        .map_err(|e| format!("Failed to parse mapping_mode: {}", e))?;
+
+    let distance_metric: DistanceMetric = matches
+        .value_of("Distance Metric")
+        .unwrap_or(&config.distance_metric)
+        .parse()
+        .map_err(|e| format!("Failed to parse distance_metric: {}", e))?;
+
+    let soft_k: usize = matches
+        .value_of("Soft K")
+        .unwrap_or(&config.soft_k)
+        .parse()
+        .map_err(|e| format!("Failed to parse soft_k: {}", e))?;
+    if !(1..=MAX_SOFT_K).contains(&soft_k) {
+        return Err(AppError::Other(format!(
+            "Failed to parse soft_k: Expected a value from 1 to {}, got {}",
+            MAX_SOFT_K, soft_k
+        )));
+    }
+
+    let stochastic_assignment =
+        matches.is_present("Stochastic Assignment") || config.stochastic_assignment;
+
+    let luminance_weight: f32 = matches
+        .value_of("Luminance Weight")
+        .unwrap_or(&config.luminance_weight)
+        .parse()
+        .map_err(|e| format!("Failed to parse luminance_weight: {}", e))?;
+
+    let preserve_extremes = matches.is_present("Preserve Extremes") || config.preserve_extremes;
+
+    let extreme_threshold: u8 = matches
+        .value_of("Extreme Threshold")
+        .unwrap_or(&config.extreme_threshold)
+        .parse()
+        .map_err(|e| format!("Failed to parse extreme_threshold: {}", e))?;
+
+    let protect_skin = matches.is_present("Protect Skin") || config.protect_skin;
+
+    let skin_tone: SkinTone = matches
+        .value_of("Skin Tone")
+        .unwrap_or(&config.skin_tone)
+        .parse()
+        .map_err(|e| format!("Failed to parse skin_tone: {}", e))?;
+
+    let replace_only = matches
+        .value_of("Replace Only")
+        .or(config.replace_only.as_deref())
+        .map(parse_color_keys)
+        .transpose()
+        .map_err(|e| format!("Failed to parse replace_only: {}", e))?;
+
+    let temperature: f32 = matches
+        .value_of("Temperature")
+        .unwrap_or(&config.temperature)
+        .parse()
+        .map_err(|e| format!("Failed to parse temperature: {}", e))?;
+
+    let tint: f32 = matches
+        .value_of("Tint")
+        .unwrap_or(&config.tint)
+        .parse()
+        .map_err(|e| format!("Failed to parse tint: {}", e))?;
+
+    let brightness: f32 = matches
+        .value_of("Brightness")
+        .unwrap_or(&config.brightness)
+        .parse()
+        .map_err(|e| format!("Failed to parse brightness: {}", e))?;
+
+    let contrast: f32 = matches
+        .value_of("Contrast")
+        .unwrap_or(&config.contrast)
+        .parse()
+        .map_err(|e| format!("Failed to parse contrast: {}", e))?;
+
     let spatial_averaging_radius = matches
         .value_of("Spatial Averaging Radius")
         .unwrap_or(&config.spatial_averaging_radius);
@@ -388,27 +972,200 @@ pub async fn init() -> Result<Arc<AppConfig>, AppError> {
         .parse()
         .map_err(|e| format!("Failed to parse spatial_averaging_radius: {}", e))?;
 
-    let colors = load_colorscheme(colorscheme, &config_dir).await?;
-    let colors: Vec<Lab> = colors
-        .iter()
-        .map(|hex| Lab::from_color(hex_to_rgb(hex).unwrap()))
-        .collect();
+    let adaptive_radius = matches.is_present("Adaptive Radius") || config.adaptive_radius;
+
+    let multi_scale_weights = matches
+        .value_of("Multi Scale Weights")
+        .or(config.multi_scale_weights.as_deref())
+        .map(|weights| {
+            weights
+                .split(',')
+                .map(|weight| weight.trim().parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()
+        .map_err(|e| format!("Failed to parse multi_scale_weights: {}", e))?;
+
+    let spatial_kernel: SpatialKernel = matches
+        .value_of("Spatial Kernel")
+        .unwrap_or(&config.spatial_kernel)
+        .parse()
+        .map_err(|e| format!("Failed to parse spatial_kernel: {}", e))?;
+
+    let refine_iterations: usize = matches
+        .value_of("Refine Iterations")
+        .unwrap_or(&config.refine_iterations)
+        .parse()
+        .map_err(|e| format!("Failed to parse refine_iterations: {}", e))?;
+
+    let sharpen_amount: f32 = matches
+        .value_of("Sharpen Amount")
+        .unwrap_or(&config.sharpen_amount)
+        .parse()
+        .map_err(|e| format!("Failed to parse sharpen_amount: {}", e))?;
+
+    let chroma_scale: f32 = matches
+        .value_of("Chroma Scale")
+        .unwrap_or(&config.chroma_scale)
+        .parse()
+        .map_err(|e| format!("Failed to parse chroma_scale: {}", e))?;
+
+    let posterize_levels: Option<u8> = matches
+        .value_of("Posterize Levels")
+        .or(config.posterize_levels.as_deref())
+        .map(parse_posterize_levels)
+        .transpose()
+        .map_err(|e| format!("Failed to parse posterize_levels: {}", e))?;
+
+    let simulate_cvd: Option<CvdType> = matches
+        .value_of("Simulate CVD")
+        .or(config.simulate_cvd.as_deref())
+        .map(str::parse)
+        .transpose()
+        .map_err(|e| format!("Failed to parse simulate_cvd: {}", e))?;
+
+    let split_tone: Option<SplitTone> = matches
+        .value_of("Split Tone")
+        .or(config.split_tone.as_deref())
+        .map(str::parse)
+        .transpose()
+        .map_err(|e| format!("Failed to parse split_tone: {}", e))?;
+
+    let kernel_shape: KernelShape = matches
+        .value_of("Kernel Shape")
+        .unwrap_or(&config.kernel_shape)
+        .parse()
+        .map_err(|e| format!("Failed to parse kernel_shape: {}", e))?;
 
-    let colors = if should_interpolate_colors {
+    let spatial_impl: SpatialImpl = matches
+        .value_of("Spatial Impl")
+        .unwrap_or(&config.spatial_impl)
+        .parse()
+        .map_err(|e| format!("Failed to parse spatial_impl: {}", e))?;
+
+    let working_space: WorkingSpace = matches
+        .value_of("Working Space")
+        .unwrap_or(&config.working_space)
+        .parse()
+        .map_err(|e| format!("Failed to parse working_space: {}", e))?;
+
+    let gpl_path = config_dir.join(format!("{}.gpl", colorscheme));
+    let json_path = config_dir.join(format!("{}.json", colorscheme));
+    let colors: Vec<Lab> = if let Some(hex_list) = matches.value_of("Colors") {
+        ensure_not_empty(parse_hex_list(hex_list)?, "--colors")?
+    } else if let Some(reference_path) = matches.value_of("Reference Image") {
+        let reference_colors: usize = matches
+            .value_of("Reference Colors")
+            .unwrap_or("16")
+            .parse()
+            .map_err(|e| format!("Failed to parse reference_colors: {}", e))?;
+
+        let reference = image::open(reference_path)?;
+        ensure_not_empty(
+            extract_kmeans(&reference, reference_colors, KMEANS_ITERATIONS, KMEANS_SEED),
+            reference_path,
+        )?
+    } else if gpl_path.exists() {
+        // Palette files take precedence over a txt colorscheme with the same name
+        ensure_not_empty(load_gpl(&gpl_path)?, colorscheme)?
+    } else if json_path.exists() {
+        ensure_not_empty(load_json(&json_path)?.to_lab(), colorscheme)?
+    } else {
+        load_colorscheme(colorscheme, &config_dir, !show_progress).await?
+    };
+
+    // Interpolating sorts the colors by lightness, which would reorder the gradient stops, and the
+    // gradient is already interpolated between them
+    let colors = if should_interpolate_colors && mapping_mode == MappingMode::Nearest {
         interpolate_colors(colors, interpolation_threshold)
     } else {
         colors
     };
 
+    if let Some(path) = matches.value_of("Export Palette").map(Path::new) {
+        if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("gpl"))
+        {
+            save_gpl(path, &colors, None)?;
+        } else {
+            save_json(path, &Palette::from_lab(&colors))?;
+        }
+    }
+
     Ok(Arc::new(AppConfig {
         input_output_pairs,
+        input_output_dirs,
         blend_factor,
+        blend_space,
         colors,
+        mapping_mode,
+        distance_metric,
+        custom_distance: None,
+        soft_k,
+        stochastic_assignment,
+        luminance_weight,
+        preserve_extremes,
+        extreme_threshold,
+        protect_skin,
+        skin_tone,
+        replace_only,
+        transfer_luminance,
+        invert_luminance,
+        gamut_mapping,
+        tone_map,
+        temperature,
+        tint,
+        brightness,
+        contrast,
         dither_amount,
+        dither_seed,
+        dither_algorithm,
+        custom_dither: None,
+        serpentine,
+        temporal_coherence,
         spatial_averaging_radius,
+        adaptive_radius,
+        multi_scale_weights,
+        spatial_kernel,
+        refine_iterations,
+        sharpen_amount,
+        chroma_scale,
+        split_tone,
+        posterize_levels,
+        simulate_cvd,
+        kernel_shape,
+        spatial_impl,
+        working_space,
+        show_progress,
+        force_progress,
+        jpeg_quality,
+        compute_ssim: false,
+        threads,
+        tile_height,
+        dry_run,
     }))
 }
 
+// A single level would map every pixel to black
+fn parse_posterize_levels(levels: &str) -> Result<u8, String> {
+    match levels.parse::<u8>() {
+        Ok(levels) if levels >= 2 => Ok(levels),
+        _ => Err(format!(
+            "Expected a number of levels from 2 to 255, got '{}'",
+            levels
+        )),
+    }
+}
+
+fn ensure_not_empty(colors: Vec<Lab>, name: &str) -> Result<Vec<Lab>, AppError> {
+    if colors.is_empty() {
+        Err(AppError::Other(format!("Colorscheme '{}' is empty", name)))
+    } else {
+        Ok(colors)
+    }
+}
+
 fn generate_input_output_pairs(
     input_paths: &[&str],
     output_dir: Option<PathBuf>,
@@ -435,3 +1192,32 @@ fn generate_input_output_pairs(
 
     Ok(pairs)
 }
+
+// Images in a directory keep their file names, so the results go into a directory of their own
+fn generate_input_output_dirs(
+    input_dirs: &[&str],
+    output_dir: Option<PathBuf>,
+    colorscheme: &str,
+) -> Vec<(String, String)> {
+    input_dirs
+        .iter()
+        .map(|input_dir| {
+            let input_dir = Path::new(input_dir);
+            let dir_name = format!(
+                "{}_{}",
+                input_dir.file_name().unwrap_or_default().to_string_lossy(),
+                colorscheme
+            );
+
+            let output_dir = match output_dir {
+                Some(ref dir) => dir.join(dir_name),
+                None => input_dir.with_file_name(dir_name),
+            };
+
+            (
+                input_dir.to_string_lossy().into_owned(),
+                output_dir.to_string_lossy().into_owned(),
+            )
+        })
+        .collect()
+}
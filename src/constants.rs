@@ -1 +1,23 @@
 pub static VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const KMEANS_ITERATIONS: usize = 20;
+pub const KMEANS_SEED: u64 = 0;
+/// Upper bound on `AppConfig::soft_k`, since the first pass shader keeps the closest colors in a
+/// fixed size array
+pub const MAX_SOFT_K: usize = 8;
+/// Number of closest palette colors `AppConfig::stochastic_assignment` picks between
+pub const STOCHASTIC_CANDIDATES: usize = 3;
+/// Upper bound on `AppConfig::spatial_averaging_radius`
+pub const MAX_SPATIAL_AVERAGING_RADIUS: u32 = 100;
+/// Lightness gradient, in percent of the lightness range per pixel, at which
+/// `AppConfig::adaptive_radius` halves the spatial averaging radius
+pub const ADAPTIVE_RADIUS_GRADIENT: f32 = 4.0;
+/// Upper bound on the number of `AppConfig::multi_scale_weights`. Every level doubles the radius
+/// of the one before
+pub const MAX_MULTI_SCALE_LEVELS: usize = 4;
+/// Difference from the first level's lightness, in percent of the lightness range, at which a
+/// level of `AppConfig::multi_scale_weights` counts half
+pub const MULTI_SCALE_LIGHTNESS_DIFFERENCE: f32 = 2.0;
+/// Time `estimate_cost` expects every pixel to take on one thread, whatever the palette size
+pub const ESTIMATED_NANOS_PER_PIXEL: f64 = 1000.0;
+/// Time `estimate_cost` expects every palette color to add to each pixel on one thread
+pub const ESTIMATED_NANOS_PER_PIXEL_COLOR: f64 = 35.0;
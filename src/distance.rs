@@ -0,0 +1,19 @@
+use crate::{types::DistanceMetric, utils::color_distance};
+
+use palette::Lab;
+
+/// Distance between two colors used to pick the closest palette color for each pixel. Smaller is
+/// closer, and a NaN distance is treated as infinitely far away.
+///
+/// A custom distance set through `AppConfig::custom_distance` can't run in a shader, so the first
+/// pass runs on the CPU when one is set.
+pub trait ColorDistance: Send + Sync + std::fmt::Debug {
+    fn distance(&self, a: &Lab, b: &Lab) -> f32;
+}
+
+/// The built in metrics, with lightness weighted the same as the other channels
+impl ColorDistance for DistanceMetric {
+    fn distance(&self, a: &Lab, b: &Lab) -> f32 {
+        color_distance(a, b, *self, 1.0)
+    }
+}
@@ -0,0 +1,338 @@
+use crate::{
+    kdtree::ColorMatcher,
+    types::{AppConfig, DitherAlgorithm, MappingMode},
+    utils::{
+        as_view, assignment_noise, bayer_threshold, blend_colors, find_soft_closest_color,
+        find_stochastic_index, gradient_map, is_extreme, lab_to_working, pixel_noise,
+        texture_threshold, working_to_lab, working_to_srgb_in_gamut, RgbBuffer, RgbPixel, RgbView,
+    },
+};
+
+use image::{DynamicImage, GrayImage, ImageBuffer, RgbImage};
+use palette::{Lab, Srgb};
+
+/// Label of every pixel and the palettes the labels index, as given to `colorize_with_regions`
+pub(crate) type Regions<'a> = (&'a [u8], &'a [Vec<Lab>]);
+
+/// (dx, dy, weight) offsets used to push quantization error onto neighboring pixels
+pub type DiffusionKernel = [(i64, i64, f32)];
+
+const FLOYD_STEINBERG: &DiffusionKernel = &[
+    (1, 0, 7.0 / 16.0),
+    (-1, 1, 3.0 / 16.0),
+    (0, 1, 5.0 / 16.0),
+    (1, 1, 1.0 / 16.0),
+];
+
+// Only 6/8 of the error is passed on, which keeps flat regions cleaner on small palettes
+const ATKINSON: &DiffusionKernel = &[
+    (1, 0, 1.0 / 8.0),
+    (2, 0, 1.0 / 8.0),
+    (-1, 1, 1.0 / 8.0),
+    (0, 1, 1.0 / 8.0),
+    (1, 1, 1.0 / 8.0),
+    (0, 2, 1.0 / 8.0),
+];
+
+/// Dithering that moves each pixel on its own before it's mapped to the palette. `value` is the
+/// CIELAB color of the pixel at `x`, `y` of the whole image and `amount` is the dither amount.
+pub trait Ditherer: Send + Sync + std::fmt::Debug {
+    fn dither(&self, value: Lab, x: u32, y: u32, amount: f32) -> Lab;
+}
+
+/// Dithering that spreads the quantization error of each pixel onto the pixels after it in
+/// scanline order
+pub trait ErrorDiffuser: Send + Sync + std::fmt::Debug {
+    fn kernel(&self) -> &DiffusionKernel;
+}
+
+/// Dithering set through `AppConfig::custom_dither` in place of `dither_algorithm`. Custom
+/// dithering can't run in a shader, so the first pass runs on the CPU when one is set.
+#[derive(Debug)]
+pub enum CustomDither {
+    PerPixel(Box<dyn Ditherer>),
+    Diffusion(Box<dyn ErrorDiffuser>),
+}
+
+/// Ordered dithering with a `size` x `size` Bayer matrix, where `size` is a power of two. Same as
+/// `DitherAlgorithm::Ordered` in Lab
+#[derive(Debug, Clone, Copy)]
+pub struct Ordered {
+    pub size: u32,
+}
+
+impl Ditherer for Ordered {
+    fn dither(&self, value: Lab, x: u32, y: u32, amount: f32) -> Lab {
+        let offset = (bayer_threshold(x, y, self.size) - 0.5) * amount * 100.0;
+        Lab::new(value.l + offset, value.a + offset, value.b + offset)
+    }
+}
+
+/// Stochastic counterpart of `Ordered`, offsetting each pixel by seeded noise instead of a Bayer
+/// matrix. `DitherAlgorithm::Random` differs in pulling the mapped color back towards the pixel
+/// instead, which needs the mapped color and so can't be a `Ditherer`
+#[derive(Debug, Clone, Copy)]
+pub struct WhiteNoise {
+    pub seed: u64,
+}
+
+impl Ditherer for WhiteNoise {
+    fn dither(&self, value: Lab, x: u32, y: u32, amount: f32) -> Lab {
+        let offset = (pixel_noise(x, y, self.seed) - 0.5) * amount * 100.0;
+        Lab::new(value.l + offset, value.a + offset, value.b + offset)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FloydSteinberg;
+
+impl ErrorDiffuser for FloydSteinberg {
+    fn kernel(&self) -> &DiffusionKernel {
+        FLOYD_STEINBERG
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Atkinson;
+
+impl ErrorDiffuser for Atkinson {
+    fn kernel(&self) -> &DiffusionKernel {
+        ATKINSON
+    }
+}
+
+/// CPU replacement for the first GPU pass that maps each pixel to the palette in scanline order,
+/// carrying the quantization error of every pixel over to the pixels that haven't been visited
+/// yet.
+pub fn error_diffusion(img: &DynamicImage, config: &AppConfig) -> RgbImage {
+    diffuse(
+        &as_view(&img.to_rgb8()),
+        None,
+        None,
+        None,
+        None,
+        config,
+        true,
+    )
+}
+
+/// `error_diffusion` for images of any channel type. Every `mask` value out of 255 scales the
+/// blend factor of its pixel, a dither `texture` takes the place of the dithering algorithm the
+/// same way it does in the first pass shader, `regions` picks the palette of every pixel in place
+/// of `config.colors`, and `usage` counts the pixels mapped to each palette color the same way
+/// the first pass shader does.
+///
+/// This also stands in for the whole first pass when a custom distance or custom dithering is set,
+/// so random and ordered dithering are applied the way the shader does, without diffusing any
+/// error. Without `dither` every pixel is mapped on its own, whatever dithering `config` sets.
+pub(crate) fn diffuse<P: RgbPixel>(
+    input: &RgbView<'_, P>,
+    mask: Option<&[u8]>,
+    texture: Option<&GrayImage>,
+    regions: Option<Regions<'_>>,
+    mut usage: Option<&mut [u64]>,
+    config: &AppConfig,
+    dither: bool,
+) -> RgbBuffer<P> {
+    // Custom dithering replaces the built in algorithm entirely, and so does a dither texture
+    let custom_dither = config.custom_dither.as_ref().filter(|_| dither);
+    let texture = texture.filter(|_| dither && custom_dither.is_none());
+    let algorithm = match custom_dither {
+        Some(_) => DitherAlgorithm::None,
+        None if !dither || texture.is_some() => DitherAlgorithm::None,
+        None => config.dither_algorithm,
+    };
+    let ditherer = match custom_dither {
+        Some(CustomDither::PerPixel(ditherer)) => Some(ditherer),
+        _ => None,
+    };
+    let kernel = match (custom_dither, algorithm) {
+        (Some(CustomDither::Diffusion(diffuser)), _) => diffuser.kernel(),
+        (_, DitherAlgorithm::Atkinson) => Atkinson.kernel(),
+        (_, DitherAlgorithm::FloydSteinberg) => FloydSteinberg.kernel(),
+        _ => &[],
+    };
+
+    let space = config.working_space;
+    let palettes: Vec<Vec<[f32; 3]>> = match regions {
+        Some((_, palettes)) => palettes.iter().map(Vec::as_slice).collect(),
+        None => vec![config.colors.as_slice()],
+    }
+    .into_iter()
+    .map(|palette| {
+        palette
+            .iter()
+            .map(|lab| lab_to_working(lab, space))
+            .collect()
+    })
+    .collect();
+    let matchers: Vec<ColorMatcher> = palettes
+        .iter()
+        .map(|colors| match &config.custom_distance {
+            Some(distance) => ColorMatcher::custom(colors, distance.as_ref(), space),
+            None => ColorMatcher::new(
+                colors,
+                config.distance_metric,
+                space,
+                config.luminance_weight,
+            ),
+        })
+        .collect();
+
+    let (width, height) = input.dimensions();
+    let mut errors = vec![(0.0f32, 0.0f32, 0.0f32); width as usize * height as usize];
+    let mut output: RgbBuffer<P> = ImageBuffer::new(width, height);
+
+    // Samples are read and written straight out of the buffers row by row, which skips the bounds
+    // checks and coordinate math `get_pixel` and `put_pixel` do on every call
+    let channels = P::CHANNEL_COUNT as usize;
+    let row_length = width as usize * channels;
+    let rows = input
+        .as_raw()
+        .chunks_exact(row_length.max(1))
+        .zip(output.chunks_exact_mut(row_length.max(1)));
+
+    for (y, (input_row, output_row)) in (0..height).zip(rows) {
+        // Serpentine scanning walks every other row backwards, mirroring the kernel with it, so
+        // the error doesn't keep drifting in the same direction
+        let reversed = config.serpentine && y % 2 == 1;
+        let direction = if reversed { -1 } else { 1 };
+
+        for step in 0..width {
+            let x = if reversed { width - 1 - step } else { step };
+            let index = (y * width + x) as usize;
+            let samples = x as usize * channels..(x as usize + 1) * channels;
+            let pixel = P::from_slice(&input_row[samples.clone()]);
+            let output_pixel = P::from_slice_mut(&mut output_row[samples]);
+            let [r, g, b] = pixel.to_srgb();
+
+            // Error pushed onto preserved pixels is dropped rather than passed on
+            if config.preserve_extremes && is_extreme([r, g, b], config.extreme_threshold) {
+                *output_pixel = *pixel;
+                continue;
+            }
+
+            let input_rgb = Srgb::new(r, g, b);
+            let [l, a, b] = pixel.to_working(space);
+            let region = regions.map_or(0, |(labels, _)| labels[index] as usize);
+            let (colors, matcher) = (&palettes[region], &matchers[region]);
+
+            // Ordered dithering offsets every channel by a fraction of the lightness range
+            let threshold = match (texture, algorithm) {
+                (Some(texture), _) => Some(texture_threshold(texture, x, y)),
+                (None, DitherAlgorithm::Ordered { size }) => {
+                    Some(bayer_threshold(x, y, size as u32))
+                }
+                _ => None,
+            };
+            let offset = threshold.map_or(0.0, |threshold| {
+                (threshold - 0.5) * config.dither_amount * space.lightness_range()
+            });
+            let (error_l, error_a, error_b) = errors[index];
+            let mut adjusted = [
+                l + error_l + offset,
+                a + error_a + offset,
+                b + error_b + offset,
+            ];
+            if let Some(ditherer) = ditherer {
+                let dithered =
+                    ditherer.dither(working_to_lab(adjusted, space), x, y, config.dither_amount);
+                adjusted = lab_to_working(&dithered, space);
+            }
+            // Soft mapping blends colors, so there's no single color to pick at random
+            let stochastic = (config.mapping_mode == MappingMode::Nearest
+                && config.soft_k <= 1
+                && config.stochastic_assignment)
+                .then(|| {
+                    find_stochastic_index(
+                        &adjusted,
+                        colors,
+                        config.distance_metric,
+                        space,
+                        config.luminance_weight,
+                        assignment_noise(x, y, config.dither_seed),
+                    )
+                });
+            let closest_color = match (config.mapping_mode, stochastic) {
+                (_, Some(index)) => colors[index],
+                (MappingMode::Nearest, None) if config.soft_k > 1 => find_soft_closest_color(
+                    &adjusted,
+                    colors,
+                    config.distance_metric,
+                    space,
+                    config.luminance_weight,
+                    config.soft_k,
+                ),
+                (MappingMode::Nearest, None) => matcher.find(&adjusted),
+                (MappingMode::GradientMap, None) => gradient_map(&adjusted, colors, space),
+            };
+            if let Some(usage) = usage.as_deref_mut() {
+                let index = match config.mapping_mode {
+                    MappingMode::Nearest => {
+                        stochastic.unwrap_or_else(|| matcher.find_index(&adjusted))
+                    }
+                    MappingMode::GradientMap => {
+                        let position = (adjusted[0] / space.lightness_range()).clamp(0.0, 1.0);
+                        (position * (colors.len() - 1) as f32).round() as usize
+                    }
+                };
+                usage[index] += 1;
+            }
+
+            let final_color = if config.transfer_luminance {
+                [l, closest_color[1], closest_color[2]]
+            } else {
+                closest_color
+            };
+            // Random dithering pulls the mapped color back towards the pixel by a random amount
+            let final_color = match algorithm {
+                DitherAlgorithm::Random => {
+                    let amount = config.dither_amount * pixel_noise(x, y, config.dither_seed);
+                    let input = [l, a, b];
+                    std::array::from_fn(|i| final_color[i] + (input[i] - final_color[i]) * amount)
+                }
+                _ => final_color,
+            };
+
+            // Transferred lightness is the pixel's own, so only the palette's lightness is off
+            let residual = (
+                if config.transfer_luminance {
+                    0.0
+                } else {
+                    adjusted[0] - final_color[0]
+                },
+                adjusted[1] - final_color[1],
+                adjusted[2] - final_color[2],
+            );
+
+            for &(dx, dy, weight) in kernel {
+                let nx = x as i64 + dx * direction;
+                let ny = y as i64 + dy;
+                if nx < 0 || nx >= width as i64 || ny >= height as i64 {
+                    continue;
+                }
+
+                let neighbor = &mut errors[(ny * width as i64 + nx) as usize];
+                neighbor.0 += residual.0 * weight;
+                neighbor.1 += residual.1 * weight;
+                neighbor.2 += residual.2 * weight;
+            }
+
+            let coverage = mask.map_or(1.0, |mask| mask[index] as f32 / 255.0);
+            let blended = blend_colors(
+                input_rgb,
+                working_to_srgb_in_gamut(final_color, space, config.gamut_mapping),
+                config
+                    .blend_factor
+                    .channels()
+                    .map(|factor| factor * coverage),
+                config.blend_space,
+            );
+
+            *output_pixel =
+                P::from_scaled([blended.red, blended.green, blended.blue].map(|c| c * P::MAX));
+        }
+    }
+
+    output
+}
@@ -0,0 +1,119 @@
+use image::DynamicImage;
+
+/// EXIF orientation of an encoded JPEG, PNG, WebP or TIFF file, from 1 to 8. Files without EXIF
+/// data, or with an orientation outside of that range, give `None`.
+pub fn read_orientation(bytes: &[u8]) -> Option<u8> {
+    let tiff = if bytes.starts_with(&[0xFF, 0xD8]) {
+        jpeg_exif(bytes)?
+    } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        png_exif(bytes)?
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        webp_exif(bytes)?
+    } else {
+        bytes
+    };
+
+    tiff_orientation(tiff).filter(|orientation| (1..=8).contains(orientation))
+}
+
+/// Rotates and flips `img` so it's upright, undoing EXIF `orientation`. Unknown orientations
+/// leave the image as it is.
+pub fn apply_orientation(img: DynamicImage, orientation: u8) -> DynamicImage {
+    match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
+
+// The EXIF data of a JPEG is in an APP1 segment before the image data
+fn jpeg_exif(bytes: &[u8]) -> Option<&[u8]> {
+    let mut offset = 2;
+    loop {
+        let marker = *bytes.get(offset + 1)?;
+        if bytes[offset] != 0xFF || marker == 0xDA || marker == 0xD9 {
+            return None;
+        }
+
+        let length = u16::from_be_bytes([*bytes.get(offset + 2)?, *bytes.get(offset + 3)?]);
+        let segment = bytes.get(offset + 4..offset + 2 + length as usize)?;
+        if marker == 0xE1 {
+            if let Some(tiff) = segment.strip_prefix(b"Exif\0\0") {
+                return Some(tiff);
+            }
+        }
+        offset += 2 + length as usize;
+    }
+}
+
+// PNG keeps it in an eXIf chunk, which holds the TIFF structure directly
+fn png_exif(bytes: &[u8]) -> Option<&[u8]> {
+    let mut offset = 8;
+    loop {
+        let length = u32::from_be_bytes(bytes.get(offset..offset + 4)?.try_into().ok()?) as usize;
+        let kind = bytes.get(offset + 4..offset + 8)?;
+        let data = bytes.get(offset + 8..offset + 8 + length)?;
+        match kind {
+            b"eXIf" => return Some(data),
+            b"IEND" => return None,
+            _ => offset += 12 + length,
+        }
+    }
+}
+
+// WebP keeps it in an EXIF chunk, which some encoders start with the same header as JPEG
+fn webp_exif(bytes: &[u8]) -> Option<&[u8]> {
+    let mut offset = 12;
+    loop {
+        let kind = bytes.get(offset..offset + 4)?;
+        let length =
+            u32::from_le_bytes(bytes.get(offset + 4..offset + 8)?.try_into().ok()?) as usize;
+        let data = bytes.get(offset + 8..offset + 8 + length)?;
+        if kind == b"EXIF" {
+            return Some(data.strip_prefix(b"Exif\0\0").unwrap_or(data));
+        }
+        // Chunks are padded to an even length
+        offset += 8 + length + length % 2;
+    }
+}
+
+// Orientation is tag 0x0112 of the first IFD, stored as a single SHORT
+fn tiff_orientation(tiff: &[u8]) -> Option<u8> {
+    let little_endian = match tiff.get(..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    let read_u16 = |offset: usize| {
+        let bytes = tiff.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    };
+    let read_u32 = |offset: usize| {
+        let bytes = tiff.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    };
+
+    if read_u16(2)? != 42 {
+        return None;
+    }
+
+    let ifd = read_u32(4)? as usize;
+    (0..read_u16(ifd)? as usize)
+        .map(|i| ifd + 2 + i * 12)
+        .find(|&entry| read_u16(entry) == Some(0x0112))
+        .and_then(|entry| read_u16(entry + 8))
+        .and_then(|orientation| u8::try_from(orientation).ok())
+}
@@ -0,0 +1,58 @@
+use crate::types::ToneMap;
+
+use image::{DynamicImage, ImageBuffer, Rgb, Rgb32FImage};
+use palette::{LinSrgb, Srgb};
+use std::borrow::Cow;
+
+/// Whether `img` holds floating point samples, which `colorize` tone maps before anything else
+pub fn is_hdr(img: &DynamicImage) -> bool {
+    matches!(
+        img,
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    )
+}
+
+/// Compresses the linear samples of `img` into displayable range with `operator` and encodes them
+/// as 16 bit sRGB, so the detail the operator pulls out of the highlights isn't lost to 8 bit
+/// rounding.
+pub fn tone_map(img: &Rgb32FImage, operator: ToneMap) -> ImageBuffer<Rgb<u16>, Vec<u16>> {
+    let (width, height) = img.dimensions();
+    ImageBuffer::from_fn(width, height, |x, y| {
+        // Negative and NaN samples have no meaning as light, so they count as black
+        let [r, g, b] = img.get_pixel(x, y).0.map(|c| c.max(0.0));
+        let [r, g, b] = match operator {
+            ToneMap::None => [r, g, b],
+            ToneMap::Reinhard => {
+                let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+                let scale = if luminance > 0.0 {
+                    1.0 / (1.0 + luminance)
+                } else {
+                    1.0
+                };
+                [r * scale, g * scale, b * scale]
+            }
+            ToneMap::Aces => [r, g, b].map(aces),
+        };
+
+        let [r, g, b] = [r, g, b].map(|c| c.min(1.0));
+        let encoded: Srgb = Srgb::from_linear(LinSrgb::new(r, g, b));
+        Rgb([encoded.red, encoded.green, encoded.blue].map(|c| (c * 65535.0).round() as u16))
+    })
+}
+
+// Krzysztof Narkowicz's fit of the ACES reference rendering transform
+fn aces(x: f32) -> f32 {
+    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+}
+
+/// `img` as the pipeline sees it, which is `img` itself unless it needs tone mapping
+pub(crate) fn display_range(img: &DynamicImage, operator: ToneMap) -> Cow<'_, DynamicImage> {
+    if is_hdr(img) {
+        Cow::Owned(DynamicImage::ImageRgb16(tone_map(
+            &img.to_rgb32f(),
+            operator,
+        )))
+    } else {
+        Cow::Borrowed(img)
+    }
+}
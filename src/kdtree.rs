@@ -0,0 +1,207 @@
+use crate::{
+    distance::ColorDistance,
+    types::{DistanceMetric, WorkingSpace},
+    utils::{find_closest_color, weighted_euclidean, working_to_lab},
+};
+
+use palette::Lab;
+
+type Entry = (usize, [f32; 3]);
+
+/// Balanced kd-tree over palette colors for nearest neighbor queries under the Euclidean distance,
+/// with the lightness difference scaled by `luminance_weight` like everywhere else.
+pub struct KdTree {
+    // Stored as an implicit tree: the middle entry of every range is the root of that range and
+    // splits it on the axis given by its depth
+    entries: Vec<Entry>,
+    luminance_weight: f32,
+}
+
+impl KdTree {
+    pub fn new(colors: &[[f32; 3]], luminance_weight: f32) -> Self {
+        // A NaN color can never be the closest one, so it's left out of the tree entirely
+        let mut entries: Vec<Entry> = colors
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, color)| !color.iter().any(|c| c.is_nan()))
+            .collect();
+        build(&mut entries, 0);
+
+        KdTree {
+            entries,
+            luminance_weight,
+        }
+    }
+
+    /// Index and value of the palette color closest to `color`, or `None` when the palette has
+    /// no usable colors. Ties go to the lowest index, so the result matches a linear scan.
+    pub fn nearest(&self, color: &[f32; 3]) -> Option<(usize, [f32; 3])> {
+        let mut best = None;
+        self.search(&self.entries, 0, color, &mut best);
+        best.map(|(index, closest, _)| (index, closest))
+    }
+
+    fn search(
+        &self,
+        entries: &[Entry],
+        depth: usize,
+        color: &[f32; 3],
+        best: &mut Option<(usize, [f32; 3], f32)>,
+    ) {
+        if entries.is_empty() {
+            return;
+        }
+
+        let middle = entries.len() / 2;
+        let (index, point) = entries[middle];
+        let distance = weighted_euclidean(color, &point, self.luminance_weight);
+        let is_better = best.is_none_or(|(best_index, _, best_distance)| {
+            distance < best_distance || (distance == best_distance && index < best_index)
+        });
+        if is_better {
+            *best = Some((index, point, distance));
+        }
+
+        let axis = depth % 3;
+        let scale = if axis == 0 {
+            self.luminance_weight.abs()
+        } else {
+            1.0
+        };
+        let offset = (color[axis] - point[axis]) * scale;
+        let (near, far) = if offset < 0.0 {
+            (&entries[..middle], &entries[middle + 1..])
+        } else {
+            (&entries[middle + 1..], &entries[..middle])
+        };
+
+        self.search(near, depth + 1, color, best);
+
+        // Everything on the far side is at least `offset` away along this axis alone
+        if best.is_none_or(|(_, _, best_distance)| offset.abs() <= best_distance) {
+            self.search(far, depth + 1, color, best);
+        }
+    }
+}
+
+fn build(entries: &mut [Entry], depth: usize) {
+    if entries.len() <= 1 {
+        return;
+    }
+
+    let axis = depth % 3;
+    let middle = entries.len() / 2;
+    entries.select_nth_unstable_by(middle, |a, b| a.1[axis].total_cmp(&b.1[axis]));
+
+    let (left, right) = entries.split_at_mut(middle);
+    build(left, depth + 1);
+    build(&mut right[1..], depth + 1);
+}
+
+/// Finds the closest palette colors for many pixels against the same palette, giving the same
+/// results as `find_closest_color`. Euclidean distances go through a `KdTree` built once up
+/// front. CIE94 and CIEDE2000 break the triangle inequality the tree relies on for pruning, so
+/// those fall back to a linear scan, as does a custom `ColorDistance`.
+pub enum ColorMatcher<'a> {
+    Tree(KdTree),
+    Linear {
+        colors: &'a [[f32; 3]],
+        metric: DistanceMetric,
+        space: WorkingSpace,
+        luminance_weight: f32,
+    },
+    Custom {
+        colors: &'a [[f32; 3]],
+        // The palette converted once up front, since custom distances compare Lab colors
+        labs: Vec<Lab>,
+        distance: &'a dyn ColorDistance,
+        space: WorkingSpace,
+    },
+}
+
+impl<'a> ColorMatcher<'a> {
+    pub fn new(
+        colors: &'a [[f32; 3]],
+        metric: DistanceMetric,
+        space: WorkingSpace,
+        luminance_weight: f32,
+    ) -> Self {
+        let is_euclidean = metric == DistanceMetric::Cie76 || space == WorkingSpace::Oklab;
+        if is_euclidean && colors.iter().any(|color| !color.iter().any(|c| c.is_nan())) {
+            ColorMatcher::Tree(KdTree::new(colors, luminance_weight))
+        } else {
+            ColorMatcher::Linear {
+                colors,
+                metric,
+                space,
+                luminance_weight,
+            }
+        }
+    }
+
+    /// Matches with `distance` instead of a built in metric. `colors` are in `space`
+    pub fn custom(
+        colors: &'a [[f32; 3]],
+        distance: &'a dyn ColorDistance,
+        space: WorkingSpace,
+    ) -> Self {
+        ColorMatcher::Custom {
+            colors,
+            labs: colors
+                .iter()
+                .map(|&color| working_to_lab(color, space))
+                .collect(),
+            distance,
+            space,
+        }
+    }
+
+    pub fn find(&self, color: &[f32; 3]) -> [f32; 3] {
+        match self {
+            ColorMatcher::Tree(tree) => tree.nearest(color).unwrap().1,
+            ColorMatcher::Linear {
+                colors,
+                metric,
+                space,
+                luminance_weight,
+            } => find_closest_color(color, colors, *metric, *space, *luminance_weight),
+            ColorMatcher::Custom { colors, .. } => colors[self.find_index(color)],
+        }
+    }
+
+    /// Palette index of the color `find` picks. Duplicate colors give the lowest index
+    pub fn find_index(&self, color: &[f32; 3]) -> usize {
+        match self {
+            ColorMatcher::Tree(tree) => tree.nearest(color).unwrap().0,
+            // NaN distances are mapped to infinity like in find_closest_color
+            ColorMatcher::Custom {
+                labs,
+                distance,
+                space,
+                ..
+            } => {
+                let lab = working_to_lab(*color, *space);
+                let distance = |other: &Lab| {
+                    let distance = distance.distance(&lab, other);
+                    if distance.is_nan() {
+                        f32::INFINITY
+                    } else {
+                        distance
+                    }
+                };
+                labs.iter()
+                    .enumerate()
+                    .min_by(|(_, a), (_, b)| distance(a).total_cmp(&distance(b)))
+                    .map_or(0, |(index, _)| index)
+            }
+            ColorMatcher::Linear { colors, .. } => {
+                let closest = self.find(color);
+                colors
+                    .iter()
+                    .position(|color| *color == closest)
+                    .unwrap_or(0)
+            }
+        }
+    }
+}
@@ -0,0 +1,22 @@
+pub mod adjust;
+pub mod animation;
+pub mod batch;
+pub mod builder;
+pub mod colorize;
+pub mod colors;
+pub mod constants;
+pub mod distance;
+pub mod dither;
+pub mod exif;
+pub mod hdr;
+pub mod kdtree;
+pub mod lut;
+pub mod metrics;
+pub mod palette;
+mod platform;
+pub mod progress;
+#[cfg(feature = "cli")]
+pub mod progress_bars;
+pub mod spatial;
+pub mod types;
+pub mod utils;
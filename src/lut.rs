@@ -0,0 +1,83 @@
+use crate::{
+    adjust::{adjust_lightness, adjust_white_balance},
+    dither::diffuse,
+    types::AppConfig,
+    utils::{as_view, RgbBuffer, RgbPixel},
+};
+
+use std::fmt::Write;
+
+use image::{ImageBuffer, Rgb, RgbImage};
+
+/// Hald CLUT of `level` that leaves every color as it is. It's `level`³ pixels wide and high and
+/// holds `level`² steps per channel, with red changing fastest and blue slowest. `level` is
+/// clamped to 2..=16.
+pub fn identity_hald_clut(level: u8) -> RgbImage {
+    let level = level.clamp(2, 16) as u32;
+    let size = level * level * level;
+    let steps = level * level;
+
+    RgbImage::from_fn(size, size, |x, y| {
+        let index = y * size + x;
+        let step = |channel: u32| (channel * 255 + (steps - 1) / 2) / (steps - 1);
+        Rgb([
+            step(index % steps) as u8,
+            step(index / steps % steps) as u8,
+            step(index / (steps * steps)) as u8,
+        ])
+    })
+}
+
+/// Bakes the per pixel part of colorizing with `config` into a Hald CLUT of `level`, so it can be
+/// applied by video editors and tools like ffmpeg's haldclut filter.
+///
+/// Only the first pass is captured: white balance, brightness and contrast, palette matching, luminance transfer
+/// and blending with the original. Dithering and spatial averaging depend on the neighbors of each
+/// pixel, which a lookup table can't express, so they're left out and applying the LUT gives a
+/// harder edged approximation of `colorize`.
+pub fn export_hald_clut(config: &AppConfig, level: u8) -> RgbImage {
+    map_pixels(identity_hald_clut(level), config)
+}
+
+/// Same as `export_hald_clut`, as the text of an Adobe `.cube` 3D LUT with `size` samples per
+/// channel. `size` is clamped to 2..=256, the range the format allows.
+pub fn export_cube_lut(config: &AppConfig, size: usize) -> String {
+    let size = size.clamp(2, 256) as u32;
+    let samples = size * size * size;
+
+    // Sampled at 16 bits so the table isn't limited to 256 levels per channel. Red changes
+    // fastest and blue slowest, which is the order .cube files list their entries in
+    let step = |channel: u32| ((channel * 65535 + (size - 1) / 2) / (size - 1)) as u16;
+    let grid: RgbBuffer<Rgb<u16>> = ImageBuffer::from_fn(samples, 1, |x, _| {
+        Rgb([
+            step(x % size),
+            step(x / size % size),
+            step(x / (size * size)),
+        ])
+    });
+    let mapped = map_pixels(grid, config);
+
+    let mut cube = format!(
+        "TITLE \"image-colorizer\"\nLUT_3D_SIZE {}\nDOMAIN_MIN 0.0 0.0 0.0\nDOMAIN_MAX 1.0 1.0 1.0\n",
+        size
+    );
+    for pixel in mapped.pixels() {
+        let [r, g, b] = pixel.to_srgb();
+        // Writing to a String can't fail
+        let _ = writeln!(cube, "{:.6} {:.6} {:.6}", r, g, b);
+    }
+
+    cube
+}
+
+// The first pass for every pixel on its own, without any dithering
+fn map_pixels<P: RgbPixel>(mut pixels: RgbBuffer<P>, config: &AppConfig) -> RgbBuffer<P> {
+    if config.temperature != 0.0 || config.tint != 0.0 {
+        pixels = adjust_white_balance(&pixels, config.temperature, config.tint);
+    }
+    if config.brightness != 0.0 || config.contrast != 1.0 {
+        pixels = adjust_lightness(&pixels, config.brightness, config.contrast);
+    }
+
+    diffuse(&as_view(&pixels), None, None, None, None, config, false)
+}
@@ -1,23 +1,33 @@
-mod colorize;
-mod colors;
 mod config;
-mod constants;
-mod types;
-mod utils;
 
-use crate::colorize::colorize;
 use crate::config::{init, AppError};
-use crate::types::AppConfig;
 
-use std::sync::Arc;
+use image_colorizer::batch::{colorize_dir_with_events, process_file_with_progress, BatchEvent};
+use image_colorizer::colorize::estimate_cost_for_size;
+use image_colorizer::progress_bars::{create_progress_bar, draw_target, skip_warnings};
+use image_colorizer::types::AppConfig;
 
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::collections::HashMap;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use indicatif::{MultiProgress, ProgressBar};
 use tokio::task;
 
 #[tokio::main]
 async fn main() -> Result<(), AppError> {
     let config = init().await?;
-    let multi_progress = Arc::new(MultiProgress::new());
+    if config.dry_run {
+        return print_estimates(&config);
+    }
+
+    // Every bar draws through the MultiProgress, so its target decides for all of them
+    let multi_progress = Arc::new(MultiProgress::with_draw_target(draw_target(
+        config.force_progress,
+        std::io::stderr().is_terminal(),
+    )));
 
     let mut handles = Vec::new();
 
@@ -28,11 +38,7 @@ async fn main() -> Result<(), AppError> {
         let output_path = output_path.clone();
 
         let handle = task::spawn(async move {
-            let pb = multi_progress.add(ProgressBar::new(100));
-            pb.set_style(ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {percent_precise}% ({eta}) {msg}")
-                .unwrap()
-                .progress_chars("#>-"));
+            let pb = create_progress_bar(&multi_progress, config.show_progress);
             pb.set_message(format!("Processing: {}", input_path));
 
             let result = process_image(&input_path, &output_path, config, &pb).await;
@@ -56,6 +62,130 @@ async fn main() -> Result<(), AppError> {
         handle.await.unwrap().unwrap();
     }
 
+    // Each directory already keeps the rayon pool busy, so they run one after another once the
+    // individual images are done
+    for (input_dir, output_dir) in &config.input_output_dirs {
+        let config = Arc::clone(&config);
+        let pb = create_progress_bar(&multi_progress, config.show_progress);
+        let input_dir = input_dir.clone();
+        let output_dir = output_dir.clone();
+
+        let multi_progress = Arc::clone(&multi_progress);
+
+        let result: Result<(), AppError> = task::spawn_blocking(move || {
+            pb.set_message(format!("Processing: {}", input_dir));
+
+            // The directory's bar counts finished files, and every file being colorized gets a
+            // bar of its own under it, so a slow or stuck file stands out
+            let file_bars = Mutex::new(HashMap::new());
+            let events = |event: BatchEvent<'_>| match event {
+                BatchEvent::Listed { images } => {
+                    pb.set_length(images as u64);
+                    pb.set_position(0);
+                }
+                BatchEvent::Started { path } => {
+                    let file_pb = create_progress_bar(&multi_progress, config.show_progress);
+                    file_pb.set_message(format!("Processing: {}", path.display()));
+                    file_bars
+                        .lock()
+                        .unwrap()
+                        .insert(path.to_path_buf(), file_pb);
+                }
+                BatchEvent::Progress { path, done, total } => {
+                    if let Some(file_pb) = file_bars.lock().unwrap().get(path) {
+                        file_pb.set_length(total);
+                        file_pb.set_position(done);
+                    }
+                }
+                BatchEvent::Finished { path, error } => {
+                    // Finished files make room for the next ones, while failed ones stay in view
+                    let file_pb = file_bars.lock().unwrap().remove(path);
+                    match (file_pb, error) {
+                        (Some(file_pb), None) => {
+                            file_pb.finish_and_clear();
+                            multi_progress.remove(&file_pb);
+                        }
+                        (Some(file_pb), Some(reason)) => file_pb.abandon_with_message(format!(
+                            "Failed: {} ({})",
+                            path.display(),
+                            reason
+                        )),
+                        (None, _) => {}
+                    }
+                    pb.inc(1);
+                }
+            };
+            let summary = match colorize_dir_with_events(
+                Path::new(&input_dir),
+                Path::new(&output_dir),
+                &config,
+                &events,
+            ) {
+                Ok(summary) => summary,
+                Err(e) => {
+                    pb.abandon_with_message(format!("Failed: {}", input_dir));
+                    return Err(e.into());
+                }
+            };
+
+            pb.finish_with_message(format!(
+                "Finished: {} ({} images saved to: {})",
+                input_dir, summary.colorized, output_dir
+            ));
+            for warning in skip_warnings(&summary, config.show_progress) {
+                eprintln!("{}", warning);
+            }
+
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        result.unwrap();
+    }
+
+    Ok(())
+}
+
+// Only the dimensions are read, so even huge images are estimated without decoding them. Files in
+// the input directories that aren't images are skipped the same way colorizing them would
+fn print_estimates(config: &AppConfig) -> Result<(), AppError> {
+    let mut inputs: Vec<PathBuf> = config
+        .input_output_pairs
+        .iter()
+        .map(|(input_path, _)| PathBuf::from(input_path))
+        .collect();
+    for (input_dir, _) in &config.input_output_dirs {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(input_dir)? {
+            let path = entry?.path();
+            if path.is_file() {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        inputs.extend(paths);
+    }
+
+    const MIB: f64 = 1024.0 * 1024.0;
+    for path in inputs {
+        let Ok((width, height)) = image::image_dimensions(&path) else {
+            continue;
+        };
+        let estimate = estimate_cost_for_size(width, height, config);
+        println!(
+            "{}: {}x{}, ~{:.1} MiB peak memory (first pass {:.1} MiB, summed area table {:.1} MiB, output {:.1} MiB), ~{:.1}s",
+            path.display(),
+            width,
+            height,
+            estimate.peak_memory_bytes as f64 / MIB,
+            estimate.first_pass_bytes as f64 / MIB,
+            estimate.integral_image_bytes as f64 / MIB,
+            estimate.output_bytes as f64 / MIB,
+            estimate.duration.as_secs_f64()
+        );
+    }
+
     Ok(())
 }
 
@@ -65,8 +195,23 @@ async fn process_image(
     config: Arc<AppConfig>,
     pb: &ProgressBar,
 ) -> Result<(), AppError> {
-    let img = image::open(input_path)?;
-    let final_output = colorize(&img, &config, &pb).await.unwrap();
-    final_output.save(output_path)?;
+    if let Some(parent) = Path::new(output_path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let pb = pb.clone();
+    let progress = move |done, total| {
+        pb.set_length(total);
+        pb.set_position(done);
+    };
+
+    let input_path = PathBuf::from(input_path);
+    let output_path = PathBuf::from(output_path);
+    task::spawn_blocking(move || {
+        process_file_with_progress(&input_path, &output_path, &config, &progress)
+    })
+    .await
+    .unwrap()?;
+
     Ok(())
 }
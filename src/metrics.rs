@@ -0,0 +1,93 @@
+use crate::platform::prelude::*;
+
+use image::GrayImage;
+
+// Constants of Wang et al. for 8 bit images
+const K1: f64 = 0.01;
+const K2: f64 = 0.03;
+const WINDOW_RADIUS: i64 = 5;
+const WINDOW_SIGMA: f64 = 1.5;
+
+/// Mean structural similarity of two grayscale images as described by Wang et al., over an 11x11
+/// gaussian window of sigma 1.5 around every pixel. Windows are cut off at the borders and
+/// reweighted, so images smaller than the window still get a score. Identical images give 1.0.
+///
+/// Panics if the images aren't the same size.
+pub fn ssim(a: &GrayImage, b: &GrayImage) -> f64 {
+    assert_eq!(
+        a.dimensions(),
+        b.dimensions(),
+        "SSIM needs images of the same size"
+    );
+    let (width, height) = a.dimensions();
+    let (width, height) = (width as usize, height as usize);
+    if width * height == 0 {
+        return 1.0;
+    }
+
+    let c1 = (K1 * 255.0).powi(2);
+    let c2 = (K2 * 255.0).powi(2);
+    let kernel: Vec<f64> = (-WINDOW_RADIUS..=WINDOW_RADIUS)
+        .map(|d| (-((d * d) as f64) / (2.0 * WINDOW_SIGMA * WINDOW_SIGMA)).exp())
+        .collect();
+
+    // x, y, x², y², xy and the weight, blurred horizontally and then vertically
+    let moments: Vec<[f64; 6]> = a
+        .pixels()
+        .zip(b.pixels())
+        .map(|(x, y)| {
+            let (x, y) = (x[0] as f64, y[0] as f64);
+            [x, y, x * x, y * y, x * y, 1.0]
+        })
+        .collect();
+
+    let horizontal: Vec<[f64; 6]> = moments
+        .par_chunks(width)
+        .flat_map_iter(|row| {
+            let kernel = &kernel;
+            (0..width).map(move |x| window_sum(kernel, x, width, |sx| &row[sx]))
+        })
+        .collect();
+
+    let total: f64 = (0..height)
+        .into_par_iter()
+        .map(|y| {
+            (0..width)
+                .map(|x| {
+                    let [x_sum, y_sum, xx_sum, yy_sum, xy_sum, weight] =
+                        window_sum(&kernel, y, height, |sy| &horizontal[sy * width + x]);
+                    let (mean_x, mean_y) = (x_sum / weight, y_sum / weight);
+                    let variance_x = xx_sum / weight - mean_x * mean_x;
+                    let variance_y = yy_sum / weight - mean_y * mean_y;
+                    let covariance = xy_sum / weight - mean_x * mean_y;
+
+                    ((2.0 * mean_x * mean_y + c1) * (2.0 * covariance + c2))
+                        / ((mean_x * mean_x + mean_y * mean_y + c1)
+                            * (variance_x + variance_y + c2))
+                })
+                .sum::<f64>()
+        })
+        .sum();
+
+    total / (width * height) as f64
+}
+
+// Kernel weighted sum of the entries around `center`, skipping the ones past either end
+fn window_sum<'a>(
+    kernel: &[f64],
+    center: usize,
+    len: usize,
+    entry: impl Fn(usize) -> &'a [f64; 6],
+) -> [f64; 6] {
+    let mut sum = [0.0; 6];
+    for (k, &weight) in kernel.iter().enumerate() {
+        let i = center as i64 + k as i64 - WINDOW_RADIUS;
+        if i < 0 || i >= len as i64 {
+            continue;
+        }
+        for (s, v) in sum.iter_mut().zip(entry(i as usize)) {
+            *s += v * weight;
+        }
+    }
+    sum
+}
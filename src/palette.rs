@@ -0,0 +1,606 @@
+use crate::colors::{CATPPUCCIN, DRACULA, GRUVBOX, KANAGAWA, NORD, SOLARIZED};
+use crate::platform::prelude::*;
+use crate::types::{DistanceMetric, WorkingSpace};
+use crate::utils::{
+    color_distance, find_closest_color, hex_to_rgb, interpolate_color, rgb8_to_lab,
+    srgb_to_linear_table,
+};
+
+use std::fs;
+use std::path::Path;
+
+use image::{DynamicImage, GenericImageView, Rgb};
+use palette::{FromColor, Lab, LinSrgb, Srgb};
+use rand::{rngs::StdRng, seq::index::sample, SeedableRng};
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Debug)]
+pub enum PaletteError {
+    Io(std::io::Error),
+    MissingHeader,
+    InvalidLine { line: usize, message: String },
+    InvalidHex(String),
+    Json(serde_json::Error),
+}
+
+impl std::fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PaletteError::Io(err) => write!(f, "I/O error: {}", err),
+            PaletteError::MissingHeader => write!(f, "Missing 'GIMP Palette' header"),
+            PaletteError::InvalidLine { line, message } => {
+                write!(f, "Invalid palette on line {}: {}", line, message)
+            }
+            PaletteError::InvalidHex(token) => write!(f, "Invalid hex color '{}'", token),
+            PaletteError::Json(err) => write!(f, "JSON error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+impl From<std::io::Error> for PaletteError {
+    fn from(err: std::io::Error) -> PaletteError {
+        PaletteError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for PaletteError {
+    fn from(err: serde_json::Error) -> PaletteError {
+        PaletteError::Json(err)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NamedColor {
+    pub name: String,
+    pub hex: String,
+}
+
+/// Human-editable palette that keeps the sRGB source of each color around so it can be written
+/// back out, unlike the `Lab` values used by the colorizer
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Palette {
+    pub colors: Vec<NamedColor>,
+}
+
+impl Palette {
+    pub fn from_lab(colors: &[Lab]) -> Palette {
+        Palette {
+            colors: colors
+                .iter()
+                .enumerate()
+                .map(|(i, lab)| NamedColor {
+                    name: format!("color{}", i),
+                    hex: lab_to_hex(lab),
+                })
+                .collect(),
+        }
+    }
+
+    /// Colors with an invalid hex code are skipped, `load_json` rejects them up front
+    pub fn to_lab(&self) -> Vec<Lab> {
+        self.colors
+            .iter()
+            .filter_map(|color| hex_to_rgb(&color.hex).ok().map(Lab::from_color))
+            .collect()
+    }
+}
+
+pub fn load_json(path: &Path) -> Result<Palette, PaletteError> {
+    let palette: Palette = serde_json::from_str(&fs::read_to_string(path)?)?;
+
+    if let Some(color) = palette
+        .colors
+        .iter()
+        .find(|color| hex_to_rgb(&color.hex).is_err())
+    {
+        return Err(PaletteError::InvalidHex(color.hex.clone()));
+    }
+
+    Ok(palette)
+}
+
+pub fn save_json(path: &Path, palette: &Palette) -> Result<(), PaletteError> {
+    fs::write(path, serde_json::to_string_pretty(palette)?)?;
+    Ok(())
+}
+
+/// Serde representation of a palette as a list of sRGB hex codes, used for `AppConfig::colors`
+pub(crate) mod hex_colors {
+    use super::lab_to_hex;
+    use crate::utils::hex_to_rgb;
+
+    use palette::{FromColor, Lab};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(colors: &[Lab], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(colors.iter().map(lab_to_hex))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Lab>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|hex| {
+                hex_to_rgb(hex)
+                    .map(Lab::from_color)
+                    .map_err(D::Error::custom)
+            })
+            .collect()
+    }
+}
+
+/// Serde representation of `AppConfig::replace_only` as a list of `parse_color_keys` tokens
+pub(crate) mod color_keys {
+    use super::{format_color_key, parse_color_keys, ColorKey};
+
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        keys: &Option<Vec<ColorKey>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        keys.as_ref()
+            .map(|keys| keys.iter().map(format_color_key).collect::<Vec<_>>())
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<ColorKey>>, D::Error> {
+        Option::<Vec<String>>::deserialize(deserializer)?
+            .map(|tokens| parse_color_keys(&tokens.join(",")).map_err(D::Error::custom))
+            .transpose()
+    }
+}
+
+pub(crate) fn lab_to_hex(lab: &Lab) -> String {
+    let rgb: Srgb<u8> = Srgb::from_color(*lab).into_format();
+    format!("#{:02x}{:02x}{:02x}", rgb.red, rgb.green, rgb.blue)
+}
+
+pub fn load_gpl(path: &Path) -> Result<Vec<Lab>, PaletteError> {
+    parse_gpl(&fs::read_to_string(path)?)
+}
+
+pub fn parse_gpl(content: &str) -> Result<Vec<Lab>, PaletteError> {
+    let mut lines = content.lines().enumerate();
+
+    match lines.find(|(_, line)| !line.trim().is_empty()) {
+        Some((_, header)) if header.trim() == "GIMP Palette" => {}
+        _ => return Err(PaletteError::MissingHeader),
+    }
+
+    let mut colors = Vec::new();
+
+    for (index, line) in lines {
+        let trimmed = line.trim();
+        if trimmed.is_empty()
+            || trimmed.starts_with('#')
+            || trimmed.starts_with("Name:")
+            || trimmed.starts_with("Columns:")
+        {
+            continue;
+        }
+
+        // Anything after the three channels is the color's name
+        let channels: Vec<&str> = trimmed.split_whitespace().take(3).collect();
+        if channels.len() < 3 {
+            return Err(PaletteError::InvalidLine {
+                line: index + 1,
+                message: format!("Expected 'R G B', found '{}'", trimmed),
+            });
+        }
+
+        let mut rgb = [0u8; 3];
+        for (channel, value) in rgb.iter_mut().zip(&channels) {
+            *channel = value.parse().map_err(|_| PaletteError::InvalidLine {
+                line: index + 1,
+                message: format!("'{}' is not a channel value between 0 and 255", value),
+            })?;
+        }
+
+        colors.push(rgb8_to_lab(rgb[0], rgb[1], rgb[2]));
+    }
+
+    Ok(colors)
+}
+
+/// Writes `colors` to a GIMP palette that `load_gpl` reads back. Colors are named after `names`
+/// where given, and `color<index>` otherwise.
+pub fn save_gpl(path: &Path, colors: &[Lab], names: Option<&[String]>) -> Result<(), PaletteError> {
+    fs::write(path, format_gpl(colors, names))?;
+    Ok(())
+}
+
+pub fn format_gpl(colors: &[Lab], names: Option<&[String]>) -> String {
+    let mut content = String::from("GIMP Palette\nName: image-colorizer\nColumns: 0\n#\n");
+
+    for (i, lab) in colors.iter().enumerate() {
+        let rgb: Srgb<u8> = Srgb::from_color(*lab).into_format();
+        let name = match names.and_then(|names| names.get(i)) {
+            Some(name) => name.clone(),
+            None => format!("color{}", i),
+        };
+        content.push_str(&format!(
+            "{:3} {:3} {:3}\t{}\n",
+            rgb.red, rgb.green, rgb.blue, name
+        ));
+    }
+
+    content
+}
+
+pub fn parse_hex_list(s: &str) -> Result<Vec<Lab>, PaletteError> {
+    s.split([',', '\n'])
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .map(|token| {
+            hex_to_rgb(token)
+                .map(Lab::from_color)
+                .map_err(|_| PaletteError::InvalidHex(token.to_string()))
+        })
+        .collect()
+}
+
+/// Source color and tolerance of `AppConfig::replace_only`
+pub type ColorKey = (Rgb<u8>, u8);
+
+/// Source colors for `AppConfig::replace_only`, written as comma separated `hex:tolerance` pairs
+/// such as `#ff0000:40,#00ff00`. The tolerance is 0 when left out, matching the color exactly.
+pub fn parse_color_keys(s: &str) -> Result<Vec<ColorKey>, PaletteError> {
+    s.split([',', '\n'])
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .map(|token| {
+            let invalid = || PaletteError::InvalidHex(token.to_string());
+            let (hex, tolerance) = token.split_once(':').unwrap_or((token, "0"));
+            let rgb: Srgb<u8> = hex_to_rgb(hex.trim()).map_err(|_| invalid())?.into_format();
+            let tolerance = tolerance.trim().parse().map_err(|_| invalid())?;
+            Ok((Rgb([rgb.red, rgb.green, rgb.blue]), tolerance))
+        })
+        .collect()
+}
+
+fn format_color_key((color, tolerance): &ColorKey) -> String {
+    let [r, g, b] = color.0;
+    format!("#{:02x}{:02x}{:02x}:{}", r, g, b, tolerance)
+}
+
+/// Gradient stops for `MappingMode::GradientMap` going from `shadow` to `highlight` in `steps`
+/// evenly spaced colors, always including both ends. Interpolating in Lab keeps the midtones
+/// perceptually even.
+pub fn duotone(shadow: Lab, highlight: Lab, steps: usize) -> Vec<Lab> {
+    let steps = steps.max(2);
+    (0..steps)
+        .map(|i| interpolate_color(&shadow, &highlight, i as f32 / (steps - 1) as f32))
+        .collect()
+}
+
+/// Number of pixels mapped to each palette color, as counted by `colorize_with_stats`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaletteStats {
+    /// Every palette color with its count, in palette order
+    pub counts: Vec<(Lab, u64)>,
+}
+
+impl PaletteStats {
+    pub fn new(colors: &[Lab], counts: &[u64]) -> PaletteStats {
+        PaletteStats {
+            counts: colors.iter().copied().zip(counts.iter().copied()).collect(),
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().map(|(_, count)| count).sum()
+    }
+}
+
+/// One line per color with its hex code, count and share of all counted pixels
+impl std::fmt::Display for PaletteStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let total = self.total().max(1) as f64;
+        for (color, count) in &self.counts {
+            writeln!(
+                f,
+                "{} {:>10} {:>6.2}%",
+                lab_to_hex(color),
+                count,
+                *count as f64 / total * 100.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Upper bound on how many pixels are clustered, so large images don't slow extraction down
+const KMEANS_MAX_SAMPLES: usize = 10_000;
+
+// Upper bound on how many pixels every candidate of `choose_best_palette` is scored on
+const BEST_PALETTE_MAX_SAMPLES: usize = 10_000;
+
+/// Index of the palette in `palettes` that reproduces `img` with the least error, summed as the
+/// CIEDE2000 distance between every pixel and its closest palette color. Only evenly spaced
+/// pixels are scored, so trying many palettes stays fast. Empty palettes never win, and 0 is
+/// returned when no palette has any colors.
+pub fn choose_best_palette(img: &DynamicImage, palettes: &[Vec<Lab>]) -> usize {
+    let (width, height) = img.dimensions();
+    let pixel_count = width as usize * height as usize;
+    let step = (pixel_count / BEST_PALETTE_MAX_SAMPLES).max(1);
+
+    let samples: Vec<Lab> = img
+        .to_rgb8()
+        .pixels()
+        .step_by(step)
+        .map(|p| rgb8_to_lab(p[0], p[1], p[2]))
+        .collect();
+
+    let errors: Vec<f64> = palettes
+        .par_iter()
+        .map(|palette| {
+            if palette.is_empty() {
+                return f64::INFINITY;
+            }
+
+            let colors: Vec<[f32; 3]> = palette.iter().map(|lab| [lab.l, lab.a, lab.b]).collect();
+            samples
+                .iter()
+                .map(|sample| {
+                    let [l, a, b] = find_closest_color(
+                        &[sample.l, sample.a, sample.b],
+                        &colors,
+                        DistanceMetric::Ciede2000,
+                        WorkingSpace::Lab,
+                        1.0,
+                    );
+                    color_distance(sample, &Lab::new(l, a, b), DistanceMetric::Ciede2000, 1.0)
+                        as f64
+                })
+                .sum()
+        })
+        .collect();
+
+    errors
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map_or(0, |(index, _)| index)
+}
+
+/// Picks `k` colors representative of `img` by clustering its pixels in Lab space. The same seed
+/// always produces the same colors for the same image.
+pub fn extract_kmeans(img: &DynamicImage, k: usize, iterations: usize, seed: u64) -> Vec<Lab> {
+    let (width, height) = img.dimensions();
+    let pixel_count = width as usize * height as usize;
+    let step = (pixel_count / KMEANS_MAX_SAMPLES).max(1);
+
+    let samples: Vec<Lab> = img
+        .to_rgb8()
+        .pixels()
+        .step_by(step)
+        .map(|p| rgb8_to_lab(p[0], p[1], p[2]))
+        .collect();
+
+    let k = k.min(samples.len());
+    if k == 0 {
+        return Vec::new();
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut centroids: Vec<Lab> = sample(&mut rng, samples.len(), k)
+        .iter()
+        .map(|i| samples[i])
+        .collect();
+
+    let distance =
+        |a: &Lab, b: &Lab| (a.l - b.l).powi(2) + (a.a - b.a).powi(2) + (a.b - b.b).powi(2);
+
+    for _ in 0..iterations {
+        let mut sums = vec![(0.0f64, 0.0f64, 0.0f64, 0usize); k];
+
+        for sample in &samples {
+            let (closest, _) = centroids
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| distance(sample, a).total_cmp(&distance(sample, b)))
+                .unwrap();
+
+            let sum = &mut sums[closest];
+            sum.0 += sample.l as f64;
+            sum.1 += sample.a as f64;
+            sum.2 += sample.b as f64;
+            sum.3 += 1;
+        }
+
+        let mut changed = false;
+        for (centroid, &(l, a, b, count)) in centroids.iter_mut().zip(&sums) {
+            // Empty clusters keep their previous centroid
+            if count == 0 {
+                continue;
+            }
+
+            let count = count as f64;
+            let updated = Lab::new((l / count) as f32, (a / count) as f32, (b / count) as f32);
+            changed |= updated != *centroid;
+            *centroid = updated;
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    centroids
+}
+
+// Bits per channel of the median cut histogram, as in Heckbert's original
+const MEDIAN_CUT_BITS: u32 = 5;
+
+/// Picks up to `k` colors representative of `img` by median cut: the image's color histogram is
+/// split at the median of its widest channel until there are `k` boxes, each giving the average
+/// of its pixels. Unlike `extract_kmeans` it involves no randomness and looks at every pixel.
+pub fn extract_median_cut(img: &DynamicImage, k: usize) -> Vec<Lab> {
+    let table = srgb_to_linear_table();
+    let shift = 8 - MEDIAN_CUT_BITS;
+    let bin = |color: [u8; 3]| color.map(|channel| channel >> shift);
+    let index = |[r, g, b]: [u8; 3]| {
+        ((r as usize) << (2 * MEDIAN_CUT_BITS)) | ((g as usize) << MEDIAN_CUT_BITS) | b as usize
+    };
+
+    // Pixels are binned by their upper bits, but each bin sums their exact linear colors
+    let mut bins = vec![HistogramBin::default(); 1 << (3 * MEDIAN_CUT_BITS)];
+    for pixel in img.to_rgb8().pixels() {
+        let entry = &mut bins[index(bin(pixel.0))];
+        entry.bin = bin(pixel.0);
+        entry.count += 1;
+        for (sum, &channel) in entry.sum.iter_mut().zip(&pixel.0) {
+            *sum += table[channel as usize] as f64;
+        }
+    }
+
+    let histogram: Vec<HistogramBin> = bins.into_iter().filter(|bin| bin.count > 0).collect();
+    if k == 0 || histogram.is_empty() {
+        return Vec::new();
+    }
+
+    let mut boxes = vec![ColorBox::new(histogram)];
+    while boxes.len() < k {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, color_box)| color_box.bins.len() > 1)
+            .max_by_key(|&(i, color_box)| (color_box.range, std::cmp::Reverse(i)))
+            .map(|(i, _)| i);
+
+        match widest {
+            Some(index) => {
+                let upper = boxes[index].split();
+                boxes.push(upper);
+            }
+            // Every box holds a single bin
+            None => break,
+        }
+    }
+
+    boxes.iter().map(ColorBox::average).collect()
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct HistogramBin {
+    bin: [u8; 3],
+    count: u64,
+    sum: [f64; 3],
+}
+
+// Histogram bins of a median cut box, and the channel they're most spread over
+struct ColorBox {
+    bins: Vec<HistogramBin>,
+    channel: usize,
+    range: u8,
+}
+
+impl ColorBox {
+    fn new(bins: Vec<HistogramBin>) -> ColorBox {
+        let (channel, range) = (0..3)
+            .map(|channel| {
+                let (min, max) = bins.iter().fold((u8::MAX, u8::MIN), |(min, max), entry| {
+                    (min.min(entry.bin[channel]), max.max(entry.bin[channel]))
+                });
+                (channel, max - min)
+            })
+            .max_by_key(|&(_, range)| range)
+            .unwrap();
+
+        ColorBox {
+            bins,
+            channel,
+            range,
+        }
+    }
+
+    // Splits off the upper half at the median of the widest channel, keeping both halves
+    // non-empty
+    fn split(&mut self) -> ColorBox {
+        let channel = self.channel;
+        let mut bins = std::mem::take(&mut self.bins);
+        bins.sort_unstable_by_key(|entry| (entry.bin[channel], entry.bin));
+
+        let half = bins.iter().map(|entry| entry.count).sum::<u64>() / 2;
+        let mut seen = 0;
+        let split = bins
+            .iter()
+            .position(|entry| {
+                seen += entry.count;
+                seen > half
+            })
+            .unwrap()
+            .clamp(1, bins.len() - 1);
+
+        let upper = bins.split_off(split);
+        *self = ColorBox::new(bins);
+        ColorBox::new(upper)
+    }
+
+    // Averaged in linear RGB, so only one color per box goes through the Lab conversion
+    fn average(&self) -> Lab {
+        let (sum, count) = self.bins.iter().fold(([0.0; 3], 0), |(sum, count), entry| {
+            (
+                [
+                    sum[0] + entry.sum[0],
+                    sum[1] + entry.sum[1],
+                    sum[2] + entry.sum[2],
+                ],
+                count + entry.count,
+            )
+        });
+
+        let [r, g, b] = sum.map(|sum: f64| (sum / count as f64) as f32);
+        Lab::from_color(LinSrgb::new(r, g, b))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BuiltinPalette {
+    Kanagawa,
+    Nord,
+    Gruvbox,
+    Solarized,
+    Dracula,
+    Catppuccin,
+}
+
+impl BuiltinPalette {
+    pub fn from_name(name: &str) -> Option<BuiltinPalette> {
+        match name.to_lowercase().as_str() {
+            "kanagawa" => Some(BuiltinPalette::Kanagawa),
+            "nord" => Some(BuiltinPalette::Nord),
+            "gruvbox" => Some(BuiltinPalette::Gruvbox),
+            "solarized" => Some(BuiltinPalette::Solarized),
+            "dracula" => Some(BuiltinPalette::Dracula),
+            "catppuccin" => Some(BuiltinPalette::Catppuccin),
+            _ => None,
+        }
+    }
+
+    pub fn hex_colors(self) -> &'static [&'static str] {
+        match self {
+            BuiltinPalette::Kanagawa => &KANAGAWA,
+            BuiltinPalette::Nord => &NORD,
+            BuiltinPalette::Gruvbox => &GRUVBOX,
+            BuiltinPalette::Solarized => &SOLARIZED,
+            BuiltinPalette::Dracula => &DRACULA,
+            BuiltinPalette::Catppuccin => &CATPPUCCIN,
+        }
+    }
+
+    pub fn to_lab(self) -> Vec<Lab> {
+        self.hex_colors()
+            .iter()
+            .map(|hex| Lab::from_color(hex_to_rgb(hex).unwrap()))
+            .collect()
+    }
+}
+
+pub fn builtin(name: &str) -> Option<Vec<Lab>> {
+    BuiltinPalette::from_name(name).map(|palette| palette.to_lab())
+}
@@ -0,0 +1,148 @@
+//! What the pipeline needs from the platform, with stand-ins for builds that don't have it. With
+//! the `parallel` feature off the `par_*` methods below hand out ordinary iterators, so the same
+//! code runs on the calling thread, which is all wasm32-unknown-unknown offers.
+
+#[cfg(not(target_arch = "wasm32"))]
+use futures::FutureExt;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::OnceLock;
+
+#[cfg(feature = "parallel")]
+pub(crate) use rayon::{current_num_threads, prelude};
+
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn current_num_threads() -> usize {
+    1
+}
+
+#[cfg(not(feature = "parallel"))]
+pub(crate) mod prelude {
+    use std::{
+        iter::FlatMap,
+        slice::{Chunks, ChunksExact, ChunksMut},
+    };
+
+    pub(crate) trait ParallelSlice<T> {
+        fn par_chunks(&self, chunk_size: usize) -> Chunks<'_, T>;
+        fn par_chunks_exact(&self, chunk_size: usize) -> ChunksExact<'_, T>;
+    }
+
+    impl<T> ParallelSlice<T> for [T] {
+        fn par_chunks(&self, chunk_size: usize) -> Chunks<'_, T> {
+            self.chunks(chunk_size)
+        }
+
+        fn par_chunks_exact(&self, chunk_size: usize) -> ChunksExact<'_, T> {
+            self.chunks_exact(chunk_size)
+        }
+    }
+
+    pub(crate) trait ParallelSliceMut<T> {
+        fn par_chunks_mut(&mut self, chunk_size: usize) -> ChunksMut<'_, T>;
+    }
+
+    impl<T> ParallelSliceMut<T> for [T] {
+        fn par_chunks_mut(&mut self, chunk_size: usize) -> ChunksMut<'_, T> {
+            self.chunks_mut(chunk_size)
+        }
+    }
+
+    pub(crate) trait IntoParallelIterator: IntoIterator + Sized {
+        fn into_par_iter(self) -> Self::IntoIter {
+            self.into_iter()
+        }
+    }
+
+    impl<I: IntoIterator> IntoParallelIterator for I {}
+
+    pub(crate) trait IntoParallelRefIterator<'a> {
+        type Iter: Iterator;
+        fn par_iter(&'a self) -> Self::Iter;
+    }
+
+    impl<'a, I: ?Sized + 'a> IntoParallelRefIterator<'a> for I
+    where
+        &'a I: IntoIterator,
+    {
+        type Iter = <&'a I as IntoIterator>::IntoIter;
+
+        fn par_iter(&'a self) -> Self::Iter {
+            self.into_iter()
+        }
+    }
+
+    pub(crate) trait IntoParallelRefMutIterator<'a> {
+        type Iter: Iterator;
+        fn par_iter_mut(&'a mut self) -> Self::Iter;
+    }
+
+    impl<'a, I: ?Sized + 'a> IntoParallelRefMutIterator<'a> for I
+    where
+        &'a mut I: IntoIterator,
+    {
+        type Iter = <&'a mut I as IntoIterator>::IntoIter;
+
+        fn par_iter_mut(&'a mut self) -> Self::Iter {
+            self.into_iter()
+        }
+    }
+
+    pub(crate) trait ParallelIterator: Iterator + Sized {
+        fn flat_map_iter<U: IntoIterator, F: FnMut(Self::Item) -> U>(
+            self,
+            f: F,
+        ) -> FlatMap<Self, U, F> {
+            self.flat_map(f)
+        }
+    }
+
+    impl<I: Iterator> ParallelIterator for I {}
+}
+
+// Dropping an adapter of the GL backend terminates the EGL display every adapter of the process
+// shares (see `Inner::drop` in wgpu-hal's gles/egl.rs), which fails pipelines still running on
+// other threads with `BadDisplay`. A single adapter is kept for the whole process instead
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn request_adapter() -> Option<&'static wgpu::Adapter> {
+    static ADAPTER: OnceLock<Option<wgpu::Adapter>> = OnceLock::new();
+    // Native backends hand out adapters as soon as they're requested, so there's nothing to wait
+    // for. Blocking on it instead would fail inside the executors `batch` already blocks on
+    ADAPTER
+        .get_or_init(|| new_adapter().now_or_never().flatten())
+        .as_ref()
+}
+
+// Browsers only hand out adapters asynchronously and there's a single thread anyway
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn request_adapter() -> Option<wgpu::Adapter> {
+    new_adapter().await
+}
+
+async fn new_adapter() -> Option<wgpu::Adapter> {
+    wgpu::Instance::new(wgpu::InstanceDescriptor::default())
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: None,
+            force_fallback_adapter: false,
+        })
+        .await
+}
+
+// std::time::Instant panics on wasm32-unknown-unknown, so there every logged timing reads zero
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use std::time::Instant;
+
+#[cfg(target_arch = "wasm32")]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Instant;
+
+#[cfg(target_arch = "wasm32")]
+impl Instant {
+    pub(crate) fn now() -> Instant {
+        Instant
+    }
+
+    pub(crate) fn elapsed(&self) -> std::time::Duration {
+        std::time::Duration::ZERO
+    }
+}
@@ -0,0 +1,108 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering},
+    mpsc::Sender,
+};
+
+/// Called with (done, total) as an image is colorized. It can be called from several threads at
+/// once, so `done` isn't guaranteed to increase between calls.
+pub type ProgressCallback<'a> = dyn Fn(u64, u64) + Send + Sync + 'a;
+
+/// Pass of the pipeline a `ProgressUpdate` was sent from. With tiling every strip goes through
+/// both passes, so the stage goes back and forth
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Mapping every pixel to the palette
+    FirstPass,
+    /// Spatial averaging, luminance transfer and everything after
+    SecondPass,
+}
+
+/// Progress sent over a channel as an image is colorized
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProgressUpdate {
+    pub stage: Stage,
+    /// Fraction of the whole image that is done, from 0.0 to 1.0
+    pub fraction: f32,
+}
+
+/// Counts the work finished by the pipeline and forwards it to an optional callback and channel.
+/// Also carries the flag that cancels the pipeline, since everything reporting progress is a good
+/// place to check it
+pub struct Progress<'a> {
+    callback: Option<&'a ProgressCallback<'a>>,
+    updates: Option<&'a Sender<ProgressUpdate>>,
+    cancel: Option<&'a AtomicBool>,
+    stage: AtomicU8,
+    // Updates are only sent once per percent, which keeps the channel from flooding
+    sent_percent: AtomicU64,
+    done: AtomicU64,
+    total: u64,
+}
+
+impl<'a> Progress<'a> {
+    pub fn new(callback: Option<&'a ProgressCallback<'a>>, total: u64) -> Self {
+        Progress {
+            callback,
+            updates: None,
+            cancel: None,
+            stage: AtomicU8::new(Stage::FirstPass as u8),
+            sent_percent: AtomicU64::new(0),
+            done: AtomicU64::new(0),
+            total,
+        }
+    }
+
+    /// Also sends updates to `updates`, when given
+    pub fn with_updates(mut self, updates: Option<&'a Sender<ProgressUpdate>>) -> Self {
+        self.updates = updates;
+        self
+    }
+
+    /// Makes `is_cancelled` follow `cancel`, when given
+    pub fn with_cancel(mut self, cancel: Option<&'a AtomicBool>) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel
+            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+    }
+
+    /// Marks the start of `stage`, which is always sent on
+    pub fn set_stage(&self, stage: Stage) {
+        self.stage.store(stage as u8, Ordering::Relaxed);
+        self.send(stage, self.done.load(Ordering::Relaxed));
+    }
+
+    pub fn inc(&self, delta: u64) {
+        let done = self.done.fetch_add(delta, Ordering::Relaxed) + delta;
+        if let Some(callback) = self.callback {
+            callback(done.min(self.total), self.total);
+        }
+
+        if self.updates.is_some() {
+            let percent = done.min(self.total) * 100 / self.total.max(1);
+            if self.sent_percent.fetch_max(percent, Ordering::Relaxed) < percent {
+                self.send(self.stage(), done);
+            }
+        }
+    }
+
+    fn stage(&self) -> Stage {
+        match self.stage.load(Ordering::Relaxed) {
+            0 => Stage::FirstPass,
+            _ => Stage::SecondPass,
+        }
+    }
+
+    fn send(&self, stage: Stage, done: u64) {
+        if let Some(updates) = self.updates {
+            // Nobody listening anymore isn't a reason to stop colorizing
+            let _ = updates.send(ProgressUpdate {
+                stage,
+                fraction: done.min(self.total) as f32 / self.total.max(1) as f32,
+            });
+        }
+    }
+}
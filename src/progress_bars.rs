@@ -0,0 +1,46 @@
+use crate::batch::BatchSummary;
+
+use console::Term;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+/// Where the bars of the command line draw, given whether stderr is a terminal. Piped or
+/// redirected stderr gets nothing, since the control sequences would end up in the log. Forcing
+/// progress draws through a plain terminal handle, which doesn't check where stderr goes.
+pub fn draw_target(force_progress: bool, stderr_is_terminal: bool) -> ProgressDrawTarget {
+    if force_progress {
+        ProgressDrawTarget::term_like(Box::new(Term::stderr()))
+    } else if stderr_is_terminal {
+        ProgressDrawTarget::stderr()
+    } else {
+        ProgressDrawTarget::hidden()
+    }
+}
+
+/// Bar for one input of the command line, drawn through `multi_progress`. Without
+/// `show_progress` the bar is hidden, which still tracks progress but never draws.
+pub fn create_progress_bar(multi_progress: &MultiProgress, show_progress: bool) -> ProgressBar {
+    if !show_progress {
+        return ProgressBar::hidden();
+    }
+
+    let pb = multi_progress.add(ProgressBar::new(100));
+    pb.set_style(ProgressStyle::default_bar()
+        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {percent_precise}% ({eta}) {msg}")
+        .unwrap()
+        .progress_chars("#>-"));
+    pb
+}
+
+/// Warning for every file a batch skipped, or none at all without `show_progress` so quiet runs
+/// write nothing to stderr
+pub fn skip_warnings(summary: &BatchSummary, show_progress: bool) -> Vec<String> {
+    if !show_progress {
+        return Vec::new();
+    }
+
+    summary
+        .skipped
+        .iter()
+        .map(|(path, reason)| format!("Warning: Skipped {}: {}", path.display(), reason))
+        .collect()
+}
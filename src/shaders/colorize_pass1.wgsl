@@ -9,20 +9,99 @@ struct ColorizedPixel {
 struct Params {
   width: u32,
           height: u32,
-                   blend_factor: f32,
-                                  dither_amount: f32,
-                                                  spatial_radius: u32,
+                   blend_factor_r: f32,
+                                    blend_factor_g: f32,
+                                                     blend_factor_b: f32,
+                                                                      dither_amount: f32,
+                                                                                      dither_mode: u32,
+                                                                                                    bayer_size: u32,
+                                                                                                                 distance_metric: u32,
+                                                                                                                                   working_space: u32,
+                                                                                                                                                   luminance_weight: f32,
+                                                                                                                                                                      spatial_kernel: u32,
+                                                                                                                                                                                       spatial_sigma: f32,
+                                                                                                                                                                                                       range_sigma: f32,
+                                                                                                                                                                                                                     blend_space: u32,
+                                                                                                                                                                                                                                       row_offset: u32,
+                                                                                                                                                                                                                                                    transfer_luminance: u32,
+                                                                                                                                                                                                                                                                         sharpen_amount: f32,
+                                                                                                                                                                                                                                                                                              posterize_levels: u32,
+                                                                                                                                                                                                                                                                                                                     mapping_mode: u32,
+                                                                                                                                                                                                                                                                                                                                        soft_k: u32,
+                                                                                                                                                                                                                                                                                                                                                     preserve_extremes: u32,
+                                                                                                                                                                                                                                                                                                                                                                             extreme_threshold: f32,
+                                                                                                                                                                                                                                                                                                                                                                                                     use_mask: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                    collect_usage: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                        owned_start: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                          owned_end: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                          cvd_type: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                          seed_low: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                          seed_high: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                          gamut_mapping: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                              chroma_scale: f32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                  dither_texture_width: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                      dither_texture_height: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                          split_tone: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                              split_shadow_a: f32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                  split_shadow_b: f32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                      split_highlight_a: f32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                          split_highlight_b: f32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                              split_balance: f32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                  invert_luminance: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                      stochastic_assignment: u32,
 }
 
 @group(0) @binding(0) var<storage, read> input : array<Pixel>;
 @group(0) @binding(1) var<storage, write> output : array<ColorizedPixel>;
 @group(0) @binding(2) var<storage, read> color_palette : array<vec3<f32>>;
 @group(0) @binding(3) var<uniform> params : Params;
+@group(0) @binding(4) var<storage, read> mask : array<f32>;
+@group(0) @binding(5) var<storage, read_write> usage : array<atomic<u32>>;
+@group(0) @binding(6) var<storage, read> dither_texture : array<f32>;
+
+// Palette index the current pixel was mapped to, counted in usage when params.collect_usage is set
+var<private> mapped_index: u32;
 
 fn clamp_color(color: vec3<f32>) -> vec3<f32> {
     return clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
 }
 
+// Black and white pixels, within params.extreme_threshold, are left alone when
+// params.preserve_extremes is set so line art keeps its crisp edges
+fn is_preserved_extreme(rgb: vec3<f32>) -> bool {
+    if params.preserve_extremes == 0u { return false; }
+    return all(rgb <= vec3<f32>(params.extreme_threshold))
+        || all(rgb >= vec3<f32>(1.0 - params.extreme_threshold));
+}
+
+// How much of the blend factor applies to the pixel at index, from 0 to 1
+fn mask_coverage(index: u32) -> f32 {
+    if params.use_mask == 0u { return 1.0; }
+    return mask[index];
+}
+
+// Gamma encoded sRGB values darken midtones when mixed directly, so by default they're mixed as
+// linear light. params.blend_space can also mix them as gamma encoded values or as CIELAB, where
+// the blend factors apply to L, a and b in order. coverage scales the blend factor
+fn blend_colors(original: vec3<f32>, colorized: vec3<f32>, coverage: f32) -> vec3<f32> {
+    let factor = vec3<f32>(params.blend_factor_r, params.blend_factor_g, params.blend_factor_b)
+        * coverage;
+    // Skipping the round trip through another space keeps masked out pixels exactly as they were
+    if all(factor == vec3<f32>(0.0)) { return original; }
+
+    switch params.blend_space {
+        case 0u: { return mix(original, colorized, factor); }
+        case 2u: {
+            return lab_to_rgb(mix(rgb_to_lab(original), rgb_to_lab(clamp_color(colorized)),
+                factor));
+        }
+        default: {
+            return linear_to_srgb(mix(srgb_to_linear(original),
+                srgb_to_linear(clamp_color(colorized)), factor));
+        }
+    }
+}
+
 fn rgb_to_lab(rgb: vec3<f32>) -> vec3<f32> {
     let xyz = rgb_to_xyz(rgb);
     return xyz_to_lab(xyz);
@@ -76,10 +155,18 @@ fn lab_to_xyz(lab: vec3<f32>) -> vec3<f32> {
     return vec3<f32>(xr * 0.950489, yr, zr * 1.088840);
 }
 
+// Linear RGB without any clamping, so colors outside sRGB can be told apart
+fn xyz_to_linear(xyz: vec3<f32>) -> vec3<f32> {
+    return vec3<f32>(xyz.x * 3.2404542 + xyz.y * -1.5371385 + xyz.z * -0.4985314,
+        xyz.x * -0.9692660 + xyz.y * 1.8760108 + xyz.z * 0.0415560,
+        xyz.x * 0.0556434 + xyz.y * -0.2040259 + xyz.z * 1.0572252);
+}
+
 fn xyz_to_rgb(xyz: vec3<f32>) -> vec3<f32> {
-    let r = xyz.x * 3.2404542 + xyz.y * -1.5371385 + xyz.z * -0.4985314;
-    let g = xyz.x * -0.9692660 + xyz.y * 1.8760108 + xyz.z * 0.0415560;
-    let b = xyz.x * 0.0556434 + xyz.y * -0.2040259 + xyz.z * 1.0572252;
+    let linear = xyz_to_linear(xyz);
+    let r = linear.r;
+    let g = linear.g;
+    let b = linear.b;
 
     let r1 = select(12.92 * r, 1.055 * pow(r, 1.0 / 2.4) - 0.055, r > 0.0031308);
     let g1 = select(12.92 * g, 1.055 * pow(g, 1.0 / 2.4) - 0.055, g > 0.0031308);
@@ -89,40 +176,424 @@ fn xyz_to_rgb(xyz: vec3<f32>) -> vec3<f32> {
         clamp(b1, 0.0, 1.0));
 }
 
+fn srgb_to_linear(rgb: vec3<f32>) -> vec3<f32> {
+    return select(rgb / 12.92, pow((rgb + 0.055) / 1.055, vec3<f32>(2.4)), rgb > vec3<f32>(0.04045));
+}
+
+fn linear_to_srgb(rgb: vec3<f32>) -> vec3<f32> {
+    let clamped = max(rgb, vec3<f32>(0.0));
+    return select(12.92 * clamped, 1.055 * pow(clamped, vec3<f32>(1.0 / 2.4)) - 0.055,
+        clamped > vec3<f32>(0.0031308));
+}
+
+fn rgb_to_oklab(rgb: vec3<f32>) -> vec3<f32> {
+    let linear = srgb_to_linear(rgb);
+
+    let lms = vec3<f32>(dot(linear, vec3<f32>(0.4122214708, 0.5363325363, 0.0514459929)),
+        dot(linear, vec3<f32>(0.2119034982, 0.6806995451, 0.1073969566)),
+        dot(linear, vec3<f32>(0.0883024619, 0.2817188376, 0.6299787005)));
+    let lms_ = sign(lms) * pow(abs(lms), vec3<f32>(1.0 / 3.0));
+
+    return vec3<f32>(dot(lms_, vec3<f32>(0.2104542553, 0.7936177850, -0.0040720468)),
+        dot(lms_, vec3<f32>(1.9779984951, -2.4285922050, 0.4505937099)),
+        dot(lms_, vec3<f32>(0.0259040371, 0.7827717662, -0.8086757660)));
+}
+
+fn oklab_to_linear(oklab: vec3<f32>) -> vec3<f32> {
+    let lms_ = vec3<f32>(dot(oklab, vec3<f32>(1.0, 0.3963377774, 0.2158037573)),
+        dot(oklab, vec3<f32>(1.0, -0.1055613458, -0.0638541728)),
+        dot(oklab, vec3<f32>(1.0, -0.0894841775, -1.2914855480)));
+    let lms = lms_ * lms_ * lms_;
+
+    return vec3<f32>(dot(lms, vec3<f32>(4.0767416621, -3.3077115913, 0.2309699292)),
+        dot(lms, vec3<f32>(-1.2684380046, 2.6097574011, -0.3413193965)),
+        dot(lms, vec3<f32>(-0.0041960863, -0.7034186147, 1.7076147010)));
+}
+
+fn oklab_to_rgb(oklab: vec3<f32>) -> vec3<f32> {
+    return clamp(linear_to_srgb(oklab_to_linear(oklab)), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// The working space is Lab unless params.working_space selects Oklab
+fn rgb_to_working(rgb: vec3<f32>) -> vec3<f32> {
+    if params.working_space == 1u { return rgb_to_oklab(rgb); }
+    return rgb_to_lab(rgb);
+}
+
+fn working_to_rgb(color: vec3<f32>) -> vec3<f32> {
+    if params.working_space == 1u { return oklab_to_rgb(color); }
+    return lab_to_rgb(color);
+}
+
+fn working_to_linear(color: vec3<f32>) -> vec3<f32> {
+    if params.working_space == 1u { return oklab_to_linear(color); }
+    return xyz_to_linear(lab_to_xyz(color));
+}
+
+fn is_in_gamut(color: vec3<f32>) -> bool {
+    let linear = working_to_linear(color);
+    return all(linear >= vec3<f32>(-0.0001)) && all(linear <= vec3<f32>(1.0001));
+}
+
+// With params.gamut_mapping set, colors outside sRGB are pulled towards grey of the same
+// lightness until they fit, since clipping each channel on its own shifts their hue
+fn working_to_rgb_in_gamut(color: vec3<f32>) -> vec3<f32> {
+    if params.gamut_mapping == 0u || is_in_gamut(color) { return working_to_rgb(color); }
+
+    var low = 0.0;
+    var high = 1.0;
+    for (var i = 0; i < 16; i++) {
+        let chroma = (low + high) * 0.5;
+        if is_in_gamut(vec3<f32>(color.x, color.yz * chroma)) {
+            low = chroma;
+        } else {
+            high = chroma;
+        }
+    }
+    return working_to_rgb(vec3<f32>(color.x, color.yz * low));
+}
+
+fn cie94(lab1: vec3<f32>, lab2: vec3<f32>) -> f32 {
+    let c1 = length(lab1.yz);
+    let c2 = length(lab2.yz);
+    let delta_l = (lab1.x - lab2.x) * params.luminance_weight;
+    let delta_c = c1 - c2;
+    let delta_ab = lab1.yz - lab2.yz;
+    let delta_h_squared = max(dot(delta_ab, delta_ab) - delta_c * delta_c, 0.0);
+
+    let s_c = 1.0 + 0.045 * c1;
+    let s_h = 1.0 + 0.015 * c1;
+
+    return sqrt(delta_l * delta_l + pow(delta_c / s_c, 2.0) + delta_h_squared / (s_h * s_h));
+}
+
+fn hue_degrees(b: f32, a: f32) -> f32 {
+    if a == 0.0 && b == 0.0 { return 0.0; }
+    let hue = degrees(atan2(b, a));
+    return select(hue, hue + 360.0, hue < 0.0);
+}
+
+fn ciede2000(lab1: vec3<f32>, lab2: vec3<f32>) -> f32 {
+    let pow25_7 = 6103515625.0;
+
+    let c_bar = (length(lab1.yz) + length(lab2.yz)) / 2.0;
+    let c_bar7 = pow(c_bar, 7.0);
+    let g = 0.5 * (1.0 - sqrt(c_bar7 / (c_bar7 + pow25_7)));
+
+    let a1 = lab1.y * (1.0 + g);
+    let a2 = lab2.y * (1.0 + g);
+    let c1 = length(vec2<f32>(a1, lab1.z));
+    let c2 = length(vec2<f32>(a2, lab2.z));
+    let h1 = hue_degrees(lab1.z, a1);
+    let h2 = hue_degrees(lab2.z, a2);
+
+    let delta_l = lab2.x - lab1.x;
+    let delta_c = c2 - c1;
+
+    var delta_h = 0.0;
+    if c1 * c2 != 0.0 {
+        delta_h = h2 - h1;
+        if delta_h > 180.0 { delta_h -= 360.0; } else if delta_h < -180.0 { delta_h += 360.0; }
+    }
+    let delta_big_h = 2.0 * sqrt(c1 * c2) * sin(radians(delta_h) / 2.0);
+
+    let l_bar = (lab1.x + lab2.x) / 2.0;
+    let c_bar_prime = (c1 + c2) / 2.0;
+
+    var h_bar = h1 + h2;
+    if c1 * c2 != 0.0 {
+        if abs(h1 - h2) <= 180.0 {
+            h_bar = h_bar / 2.0;
+        } else if h_bar < 360.0 {
+            h_bar = (h_bar + 360.0) / 2.0;
+        } else {
+            h_bar = (h_bar - 360.0) / 2.0;
+        }
+    }
+
+    let t = 1.0 - 0.17 * cos(radians(h_bar - 30.0)) + 0.24 * cos(radians(2.0 * h_bar))
+        + 0.32 * cos(radians(3.0 * h_bar + 6.0)) - 0.20 * cos(radians(4.0 * h_bar - 63.0));
+    let delta_theta = 30.0 * exp(-pow((h_bar - 275.0) / 25.0, 2.0));
+    let c_bar_prime7 = pow(c_bar_prime, 7.0);
+    let r_c = 2.0 * sqrt(c_bar_prime7 / (c_bar_prime7 + pow25_7));
+    let l_offset = (l_bar - 50.0) * (l_bar - 50.0);
+    let s_l = 1.0 + 0.015 * l_offset / sqrt(20.0 + l_offset);
+    let s_c = 1.0 + 0.045 * c_bar_prime;
+    let s_h = 1.0 + 0.015 * c_bar_prime * t;
+    let r_t = -sin(radians(2.0 * delta_theta)) * r_c;
+
+    let l_term = delta_l / s_l * params.luminance_weight;
+    let c_term = delta_c / s_c;
+    let h_term = delta_big_h / s_h;
+
+    return sqrt(max(l_term * l_term + c_term * c_term + h_term * h_term + r_t * c_term * h_term, 0.0));
+}
+
+fn weighted_euclidean(lab1: vec3<f32>, lab2: vec3<f32>) -> f32 {
+    return length((lab1 - lab2) * vec3<f32>(params.luminance_weight, 1.0, 1.0));
+}
+
+// Every metric scales its lightness term by params.luminance_weight
+fn color_distance(lab1: vec3<f32>, lab2: vec3<f32>) -> f32 {
+    // CIE94 and CIEDE2000 are only defined for Lab
+    if params.working_space == 1u { return weighted_euclidean(lab1, lab2); }
+
+    switch params.distance_metric {
+        case 1u: { return cie94(lab1, lab2); }
+        case 2u: { return ciede2000(lab1, lab2); }
+        default: { return weighted_euclidean(lab1, lab2); }
+    }
+}
+
+// Comparisons with NaN are false, so colors with a NaN distance are skipped. Starting from the
+// largest f32 rather than the first color keeps a NaN first color from winning by default
 fn find_closest_color(lab: vec3<f32>) -> vec3<f32> {
-    var closest_color = vec3<f32>(color_palette[0]);
-    var min_distance = distance(lab, closest_color);
+    var closest_index = 0u;
+    var min_distance = 3.40282347e38;
 
-    for (var i = 1u; i < arrayLength(&color_palette); i = i + 1u) {
-        let current_color = vec3<f32>(color_palette[i]);
-        let current_distance = distance(lab, current_color);
+    for (var i = 0u; i < arrayLength(&color_palette); i = i + 1u) {
+        let current_distance = color_distance(lab, vec3<f32>(color_palette[i]));
         if current_distance < min_distance {
             min_distance = current_distance;
-            closest_color = current_color;
+            closest_index = i;
+        }
+    }
+
+    mapped_index = closest_index;
+    return vec3<f32>(color_palette[closest_index]);
+}
+
+// Upper bound on params.soft_k, since the closest colors are kept in fixed size arrays
+const MAX_SOFT_K = 8u;
+
+// Blends the params.soft_k closest palette colors, each weighted by the inverse of its distance, so
+// gradients move smoothly between palette colors instead of banding. NaN distances are skipped like
+// in find_closest_color, and a color matching a palette color exactly maps to just that color
+fn find_soft_closest_color(lab: vec3<f32>) -> vec3<f32> {
+    let k = min(params.soft_k, MAX_SOFT_K);
+    var distances: array<f32, MAX_SOFT_K>;
+    var indices: array<u32, MAX_SOFT_K>;
+    var found = 0u;
+
+    for (var i = 0u; i < arrayLength(&color_palette); i = i + 1u) {
+        let distance = color_distance(lab, vec3<f32>(color_palette[i]));
+        if !(distance < 3.40282347e38) { continue; }
+        if found == k && !(distance < distances[k - 1u]) { continue; }
+
+        // Insertion sort, dropping the farthest color once k are kept. Ties keep the earlier color
+        // first like find_closest_color
+        var slot = min(found, k - 1u);
+        while slot > 0u && distance < distances[slot - 1u] {
+            distances[slot] = distances[slot - 1u];
+            indices[slot] = indices[slot - 1u];
+            slot = slot - 1u;
+        }
+        distances[slot] = distance;
+        indices[slot] = i;
+        found = min(found + 1u, k);
+    }
+
+    if found == 0u { return find_closest_color(lab); }
+    mapped_index = indices[0];
+    if distances[0] == 0.0 { return vec3<f32>(color_palette[indices[0]]); }
+
+    var sum = vec3<f32>(0.0);
+    var total_weight = 0.0;
+    for (var i = 0u; i < found; i = i + 1u) {
+        let weight = 1.0 / distances[i];
+        sum += vec3<f32>(color_palette[indices[i]]) * weight;
+        total_weight += weight;
+    }
+
+    return sum / total_weight;
+}
+
+// The palette holds evenly spaced gradient stops from black to white, and the lightness picks the
+// point between the two stops around it
+fn gradient_map(lab: vec3<f32>) -> vec3<f32> {
+    let count = arrayLength(&color_palette);
+    let lightness_range = select(100.0, 1.0, params.working_space == 1u);
+    let position = clamp(lab.x / lightness_range, 0.0, 1.0) * f32(count - 1u);
+    let index = min(u32(position), max(count, 2u) - 2u);
+    mapped_index = u32(round(position));
+
+    return mix(color_palette[index], color_palette[min(index + 1u, count - 1u)],
+        position - f32(index));
+}
+
+// Number of closest palette colors stochastic assignment picks between
+const STOCHASTIC_CANDIDATES = 3u;
+
+// Picks one of the STOCHASTIC_CANDIDATES closest palette colors, each with a probability
+// proportional to the inverse of its distance, using noise in [0, 1). Kept in the same order as
+// find_soft_closest_color, so a color matching a palette color exactly always maps to just that
+// color
+fn find_stochastic_color(lab: vec3<f32>, noise: f32) -> vec3<f32> {
+    var distances: array<f32, STOCHASTIC_CANDIDATES>;
+    var indices: array<u32, STOCHASTIC_CANDIDATES>;
+    var found = 0u;
+
+    for (var i = 0u; i < arrayLength(&color_palette); i = i + 1u) {
+        let distance = color_distance(lab, vec3<f32>(color_palette[i]));
+        if !(distance < 3.40282347e38) { continue; }
+        if found == STOCHASTIC_CANDIDATES
+            && !(distance < distances[STOCHASTIC_CANDIDATES - 1u]) { continue; }
+
+        var slot = min(found, STOCHASTIC_CANDIDATES - 1u);
+        while slot > 0u && distance < distances[slot - 1u] {
+            distances[slot] = distances[slot - 1u];
+            indices[slot] = indices[slot - 1u];
+            slot = slot - 1u;
+        }
+        distances[slot] = distance;
+        indices[slot] = i;
+        found = min(found + 1u, STOCHASTIC_CANDIDATES);
+    }
+
+    if found == 0u { return find_closest_color(lab); }
+    if distances[0] == 0.0 {
+        mapped_index = indices[0];
+        return vec3<f32>(color_palette[indices[0]]);
+    }
+
+    var total_weight = 0.0;
+    for (var i = 0u; i < found; i = i + 1u) {
+        total_weight += 1.0 / distances[i];
+    }
+
+    // Walks the cumulative weights up to the noise, falling back on the last candidate in case
+    // rounding leaves the sum just short of it
+    var chosen = indices[found - 1u];
+    var cumulative = 0.0;
+    for (var i = 0u; i < found; i = i + 1u) {
+        cumulative += 1.0 / distances[i] / total_weight;
+        if noise < cumulative {
+            chosen = indices[i];
+            break;
         }
     }
 
-    return closest_color;
+    mapped_index = chosen;
+    return vec3<f32>(color_palette[chosen]);
+}
+
+fn map_color(lab: vec3<f32>, global_id: vec3<u32>) -> vec3<f32> {
+    if params.mapping_mode == 1u { return gradient_map(lab); }
+    if params.soft_k > 1u { return find_soft_closest_color(lab); }
+    if params.stochastic_assignment == 1u {
+        return find_stochastic_color(lab, assignment_noise(global_id.x, global_id.y));
+    }
+    return find_closest_color(lab);
+}
+
+// Unless params.transfer_luminance is off, only the a/b values of the palette color are kept
+fn with_lightness(closest_color: vec3<f32>, lab: vec3<f32>) -> vec3<f32> {
+    if params.transfer_luminance == 0u { return closest_color; }
+    return vec3<f32>(lab.x, closest_color.y, closest_color.z);
+}
+
+// PCG hash. Integer math gives the same noise on every GPU, unlike the precision of sin, so the
+// output only depends on the input
+fn pcg_hash(input: u32) -> u32 {
+    let state = input * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// Uniform noise in [0, 1) from the top 24 bits of the hash, which an f32 represents exactly.
+// Both halves of the seed go into the hash so every seed gives its own pattern
+fn pixel_noise(x: u32, y: u32) -> f32 {
+    let seed = pcg_hash(params.seed_low ^ pcg_hash(params.seed_high));
+    return f32(pcg_hash(x ^ pcg_hash(y ^ seed)) >> 8u) / 16777216.0;
+}
+
+// Offsets the rows pixel_noise is keyed on, so stochastic assignment draws different noise than
+// random dithering does from the same seed
+const ASSIGNMENT_NOISE_SALT = 0x9e3779b9u;
+
+fn assignment_noise(x: u32, y: u32) -> f32 {
+    return pixel_noise(x, y ^ ASSIGNMENT_NOISE_SALT);
 }
 
 fn apply_dithering(color: vec3<f32>, targ: vec3<f32>, amount: f32, global_id: vec3<u32>) -> vec3<f32> {
-    let rand = fract(sin(dot(vec2<f32>(f32(global_id.x), f32(global_id.y)),
-        vec2<f32>(12.9898, 78.233))) * 43758.5453);
+    let rand = pixel_noise(global_id.x, global_id.y);
     return color + (targ - color) * amount * rand;
 }
 
+// Threshold in [0, 1) from a size x size Bayer matrix, built one 2x2 level per bit so any power
+// of two size works without storing the matrices
+fn bayer_threshold(x: u32, y: u32, size: u32) -> f32 {
+    var value = 0u;
+    for (var bit = 1u; bit < size; bit = bit * 2u) {
+        let bx = select(0u, 1u, (x & bit) != 0u);
+        let by = select(0u, 1u, (y & bit) != 0u);
+        value = (value << 2u) | (((bx ^ by) << 1u) | by);
+    }
+    return (f32(value) + 0.5) / f32(size * size);
+}
+
+fn apply_ordered_dithering(lab: vec3<f32>, amount: f32, global_id: vec3<u32>) -> vec3<f32> {
+    // Offsets are expressed as a fraction of the lightness range of the working space
+    let lightness_range = select(100.0, 1.0, params.working_space == 1u);
+    let threshold = bayer_threshold(global_id.x % params.bayer_size, global_id.y % params.bayer_size,
+        params.bayer_size);
+    return lab + vec3<f32>((threshold - 0.5) * amount * lightness_range);
+}
+
+// Same offset as ordered dithering, with the threshold read from the dither texture tiled over
+// the image
+fn apply_texture_dithering(lab: vec3<f32>, amount: f32, global_id: vec3<u32>) -> vec3<f32> {
+    let lightness_range = select(100.0, 1.0, params.working_space == 1u);
+    let threshold = dither_texture[(global_id.x % params.dither_texture_width)
+        + (global_id.y % params.dither_texture_height) * params.dither_texture_width];
+    return lab + vec3<f32>((threshold - 0.5) * amount * lightness_range);
+}
+
 @compute @workgroup_size(16, 16, 1)fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if global_id.x >= params.width || global_id.y >= params.height { return; }
     let index = global_id.x + global_id.y * params.width;
-    if index >= arrayLength(&input) { return; }
 
     let input_color = vec3<f32>(f32(input[index].r), f32(input[index].g), f32(input[index].b));
-    let lab_color = rgb_to_lab(input_color);
-    let closest_color = find_closest_color(lab_color);
-    let final_lab = vec3<f32>(lab_color.x, closest_color.y, closest_color.z);
-    let dithered_lab = apply_dithering(final_lab, lab_color,
-        f32(params.dither_amount), global_id);
-    let final_rgb = lab_to_rgb(dithered_lab);
-    let blended_rgb = clamp_color(mix(input_color, final_rgb, f32(params.blend_factor)));
+    if is_preserved_extreme(input_color) {
+        output[index] = ColorizedPixel(input_color.r, input_color.g, input_color.b);
+        return;
+    }
+
+    let lab_color = rgb_to_working(input_color);
+
+    // Dither patterns are keyed on the position in the whole image, so tiles line up seamlessly
+    let pixel_id = vec3<u32>(global_id.x, global_id.y + params.row_offset, global_id.z);
+
+    var dithered_lab: vec3<f32>;
+    switch params.dither_mode {
+        case 1u: {
+            let closest_color = map_color(lab_color, pixel_id);
+            let final_lab = with_lightness(closest_color, lab_color);
+            dithered_lab = apply_dithering(final_lab, lab_color,
+                f32(params.dither_amount), pixel_id);
+        }
+        case 2u: {
+            let closest_color = map_color(apply_ordered_dithering(lab_color,
+                f32(params.dither_amount), pixel_id), pixel_id);
+            dithered_lab = with_lightness(closest_color, lab_color);
+        }
+        case 3u: {
+            let closest_color = map_color(apply_texture_dithering(lab_color,
+                f32(params.dither_amount), pixel_id), pixel_id);
+            dithered_lab = with_lightness(closest_color, lab_color);
+        }
+        default: {
+            let closest_color = map_color(lab_color, pixel_id);
+            dithered_lab = with_lightness(closest_color, lab_color);
+        }
+    }
+
+    // Overlap rows of a strip are owned by the strip next to it, which counts them instead
+    let is_owned = global_id.y >= params.owned_start && global_id.y < params.owned_end;
+    if params.collect_usage == 1u && is_owned { atomicAdd(&usage[mapped_index], 1u); }
+
+    let final_rgb = working_to_rgb_in_gamut(dithered_lab);
+    let blended_rgb = clamp_color(blend_colors(input_color, final_rgb, mask_coverage(index)));
 
     output[index] = ColorizedPixel(f32(blended_rgb.r), f32(blended_rgb.g),
         f32(blended_rgb.b));
@@ -2,47 +2,186 @@ struct ColorizedPixel {
   r: f32, g: f32, b: f32,
 }
 
+struct SatEntry {
+  l: f32, a: f32, b: f32, weight: f32,
+}
+
 struct Params {
   width: u32,
           height: u32,
-                   blend_factor: f32,
-                                  dither_amount: f32,
-                                                  spatial_radius: u32,
+                   blend_factor_r: f32,
+                                    blend_factor_g: f32,
+                                                     blend_factor_b: f32,
+                                                                      dither_amount: f32,
+                                                                                      dither_mode: u32,
+                                                                                                    bayer_size: u32,
+                                                                                                                 distance_metric: u32,
+                                                                                                                                   working_space: u32,
+                                                                                                                                                   luminance_weight: f32,
+                                                                                                                                                                      spatial_kernel: u32,
+                                                                                                                                                                                       spatial_sigma: f32,
+                                                                                                                                                                                                       range_sigma: f32,
+                                                                                                                                                                                                                     blend_space: u32,
+                                                                                                                                                                                                                                       row_offset: u32,
+                                                                                                                                                                                                                                                    transfer_luminance: u32,
+                                                                                                                                                                                                                                                                         sharpen_amount: f32,
+                                                                                                                                                                                                                                                                                              posterize_levels: u32,
+                                                                                                                                                                                                                                                                                                                     mapping_mode: u32,
+                                                                                                                                                                                                                                                                                                                                        soft_k: u32,
+                                                                                                                                                                                                                                                                                                                                                     preserve_extremes: u32,
+                                                                                                                                                                                                                                                                                                                                                                             extreme_threshold: f32,
+                                                                                                                                                                                                                                                                                                                                                                                                     use_mask: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                    collect_usage: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                        owned_start: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                          owned_end: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                          cvd_type: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                          seed_low: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                          seed_high: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                          gamut_mapping: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                              chroma_scale: f32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                  dither_texture_width: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                      dither_texture_height: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                          split_tone: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                              split_shadow_a: f32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                  split_shadow_b: f32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                      split_highlight_a: f32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                          split_highlight_b: f32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                              split_balance: f32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                  invert_luminance: u32,
+                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                                      stochastic_assignment: u32,
 }
 
 @group(0) @binding(0) var<storage, read> input : array<ColorizedPixel>;
-@group(0) @binding(1) var<storage, read> sat : array<ColorizedPixel>;
+@group(0) @binding(1) var<storage, read> sat : array<SatEntry>;
 @group(0) @binding(2) var<storage, write> output : array<ColorizedPixel>;
 @group(0) @binding(3) var<uniform> params : Params;
+@group(0) @binding(4) var<storage, read> mask : array<f32>;
 
 fn clamp_color(color: vec3<f32>) -> vec3<f32> {
     return clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
 }
 
-fn fast_spatial_color_average(x: u32, y: u32) -> vec3<f32> {
-    let radius = i32(params.spatial_radius);
-    let x1 = max(i32(x) - radius, 0);
-    let y1 = max(i32(y) - radius, 0);
-    let x2 = min(i32(x) + radius, i32(params.width - 1u));
-    let y2 = min(i32(y) + radius, i32(params.height - 1u));
+// Black and white pixels, within params.extreme_threshold, are left alone when
+// params.preserve_extremes is set so line art keeps its crisp edges
+fn is_preserved_extreme(rgb: vec3<f32>) -> bool {
+    if params.preserve_extremes == 0u { return false; }
+    return all(rgb <= vec3<f32>(params.extreme_threshold))
+        || all(rgb >= vec3<f32>(1.0 - params.extreme_threshold));
+}
+
+// How much of the blend factor applies to the pixel at index, from 0 to 1
+fn mask_coverage(index: u32) -> f32 {
+    if params.use_mask == 0u { return 1.0; }
+    return mask[index];
+}
+
+// Gamma encoded sRGB values darken midtones when mixed directly, so by default they're mixed as
+// linear light. params.blend_space can also mix them as gamma encoded values or as CIELAB, where
+// the blend factors apply to L, a and b in order. coverage scales the blend factor
+fn blend_colors(original: vec3<f32>, colorized: vec3<f32>, coverage: f32) -> vec3<f32> {
+    let factor = vec3<f32>(params.blend_factor_r, params.blend_factor_g, params.blend_factor_b)
+        * coverage;
+    // Skipping the round trip through another space keeps masked out pixels exactly as they were
+    if all(factor == vec3<f32>(0.0)) { return original; }
+
+    switch params.blend_space {
+        case 0u: { return mix(original, colorized, factor); }
+        case 2u: {
+            return lab_to_rgb(mix(rgb_to_lab(original), rgb_to_lab(clamp_color(colorized)),
+                factor));
+        }
+        default: {
+            return linear_to_srgb(mix(srgb_to_linear(original),
+                srgb_to_linear(clamp_color(colorized)), factor));
+        }
+    }
+}
+
+// Rounds each channel to the nearest of params.posterize_levels evenly spaced levels, which is 0
+// when posterizing is off
+fn posterize(color: vec3<f32>) -> vec3<f32> {
+    if params.posterize_levels < 2u { return color; }
+
+    let steps = f32(params.posterize_levels - 1u);
+    return round(color * steps) / steps;
+}
 
-    let area = f32((x2 - x1 + 1) * (y2 - y1 + 1));
+// Viénot, Brettel and Mollon (1999) simulation of dichromacy for params.cvd_type, which projects
+// the LMS response of the linear color onto the colors the missing cone type can't tell apart.
+// WGSL matrices are column major, so the matrices are written transposed
+fn simulate_cvd(rgb: vec3<f32>) -> vec3<f32> {
+    if params.cvd_type == 0u { return rgb; }
+
+    let rgb_to_lms = mat3x3<f32>(vec3<f32>(0.31399022, 0.15537241, 0.01775239),
+        vec3<f32>(0.63951294, 0.75789446, 0.10944209),
+        vec3<f32>(0.04649755, 0.08670142, 0.87256922));
+    let lms_to_rgb = mat3x3<f32>(vec3<f32>(5.47221206, -1.1252419, 0.02980165),
+        vec3<f32>(-4.6419601, 2.29317094, -0.19318073),
+        vec3<f32>(0.16963708, -0.1678952, 1.16364789));
+
+    var lms = rgb_to_lms * srgb_to_linear(rgb);
+    switch params.cvd_type {
+        case 1u: { lms.x = 1.05118294 * lms.y - 0.05116099 * lms.z; }
+        case 2u: { lms.y = 0.9513092 * lms.x + 0.04866992 * lms.z; }
+        default: { lms.z = -0.86744736 * lms.x + 1.86727089 * lms.y; }
+    }
+
+    return clamp_color(linear_to_srgb(lms_to_rgb * lms));
+}
 
-    let top_left = get_sat_value(u32(x1), u32(y1));
-    let top_right = get_sat_value(u32(x2 + 1), u32(y1));
-    let bottom_left = get_sat_value(u32(x1), u32(y2 + 1));
-    let bottom_right = get_sat_value(u32(x2 + 1), u32(y2 + 1));
+// Kernels other than the bilateral are averaged on the CPU, which leaves one weighted sum per
+// pixel
+fn precomputed_spatial_average(x: u32, y: u32) -> vec3<f32> {
+    let entry = sat[x + y * params.width];
+    if entry.weight <= 0.0 { return rgb_to_working(get_input_color(x, y)); }
 
-    let sum = vec3<f32>(bottom_right.r - top_right.r - bottom_left.r + top_left.r,
-        bottom_right.g - top_right.g - bottom_left.g + top_left.g,
-        bottom_right.b - top_right.b - bottom_left.b + top_left.b);
+    return vec3<f32>(entry.l, entry.a, entry.b) / entry.weight;
+}
 
-    return vec3<f32>(f32(sum.r / area), f32(sum.g / area), f32(sum.b / area));
+// Neighbors are weighted by both their distance and how similar their a/b values are, so strong
+// edges don't get smoothed over. The sat buffer holds one (l, a, b, weight) entry per pixel here
+fn bilateral_spatial_average(x: u32, y: u32) -> vec3<f32> {
+    let center = sat[x + y * params.width];
+    if center.weight <= 0.0 { return rgb_to_working(get_input_color(x, y)); }
+
+    let center_ab = vec2<f32>(center.a, center.b);
+    let radius = i32(ceil(params.spatial_sigma * 3.0));
+    let spatial_factor = -0.5 / (params.spatial_sigma * params.spatial_sigma);
+    let range_factor = -0.5 / (params.range_sigma * params.range_sigma);
+
+    var sum = vec3<f32>(0.0);
+    var total_weight = 0.0;
+    for (var dy = -radius; dy <= radius; dy = dy + 1) {
+        let ny = i32(y) + dy;
+        if ny < 0 || ny >= i32(params.height) { continue; }
+
+        for (var dx = -radius; dx <= radius; dx = dx + 1) {
+            let nx = i32(x) + dx;
+            if nx < 0 || nx >= i32(params.width) { continue; }
+
+            let neighbor = sat[u32(nx) + u32(ny) * params.width];
+            let delta_ab = vec2<f32>(neighbor.a, neighbor.b) - center_ab;
+            let weight = neighbor.weight * exp(f32(dx * dx + dy * dy) * spatial_factor
+                + dot(delta_ab, delta_ab) * range_factor);
+
+            sum += vec3<f32>(neighbor.l, neighbor.a, neighbor.b) * weight;
+            total_weight += weight;
+        }
+    }
+
+    return sum / total_weight;
 }
 
-fn get_sat_value(x: u32, y: u32) -> vec3<f32> {
-    let index = y * (params.width + 1u) + x;
-    return vec3<f32>(sat[index].r, sat[index].g, sat[index].b);
+fn spatial_color_average(x: u32, y: u32) -> vec3<f32> {
+    switch params.spatial_kernel {
+        case 2u: { return bilateral_spatial_average(x, y); }
+        default: { return precomputed_spatial_average(x, y); }
+    }
+}
+
+fn get_input_color(x: u32, y: u32) -> vec3<f32> {
+    let index = x + y * params.width;
+    return vec3<f32>(input[index].r, input[index].g, input[index].b);
 }
 
 fn rgb_to_lab(rgb: vec3<f32>) -> vec3<f32> {
@@ -98,10 +237,18 @@ fn lab_to_xyz(lab: vec3<f32>) -> vec3<f32> {
     return vec3<f32>(xr * 0.950489, yr, zr * 1.088840);
 }
 
+// Linear RGB without any clamping, so colors outside sRGB can be told apart
+fn xyz_to_linear(xyz: vec3<f32>) -> vec3<f32> {
+    return vec3<f32>(xyz.x * 3.2404542 + xyz.y * -1.5371385 + xyz.z * -0.4985314,
+        xyz.x * -0.9692660 + xyz.y * 1.8760108 + xyz.z * 0.0415560,
+        xyz.x * 0.0556434 + xyz.y * -0.2040259 + xyz.z * 1.0572252);
+}
+
 fn xyz_to_rgb(xyz: vec3<f32>) -> vec3<f32> {
-    let r = xyz.x * 3.2404542 + xyz.y * -1.5371385 + xyz.z * -0.4985314;
-    let g = xyz.x * -0.9692660 + xyz.y * 1.8760108 + xyz.z * 0.0415560;
-    let b = xyz.x * 0.0556434 + xyz.y * -0.2040259 + xyz.z * 1.0572252;
+    let linear = xyz_to_linear(xyz);
+    let r = linear.r;
+    let g = linear.g;
+    let b = linear.b;
 
     let r1 = select(12.92 * r, 1.055 * pow(r, 1.0 / 2.4) - 0.055, r > 0.0031308);
     let g1 = select(12.92 * g, 1.055 * pow(g, 1.0 / 2.4) - 0.055, g > 0.0031308);
@@ -111,6 +258,101 @@ fn xyz_to_rgb(xyz: vec3<f32>) -> vec3<f32> {
         clamp(b1, 0.0, 1.0));
 }
 
+fn srgb_to_linear(rgb: vec3<f32>) -> vec3<f32> {
+    return select(rgb / 12.92, pow((rgb + 0.055) / 1.055, vec3<f32>(2.4)), rgb > vec3<f32>(0.04045));
+}
+
+fn linear_to_srgb(rgb: vec3<f32>) -> vec3<f32> {
+    let clamped = max(rgb, vec3<f32>(0.0));
+    return select(12.92 * clamped, 1.055 * pow(clamped, vec3<f32>(1.0 / 2.4)) - 0.055,
+        clamped > vec3<f32>(0.0031308));
+}
+
+fn rgb_to_oklab(rgb: vec3<f32>) -> vec3<f32> {
+    let linear = srgb_to_linear(rgb);
+
+    let lms = vec3<f32>(dot(linear, vec3<f32>(0.4122214708, 0.5363325363, 0.0514459929)),
+        dot(linear, vec3<f32>(0.2119034982, 0.6806995451, 0.1073969566)),
+        dot(linear, vec3<f32>(0.0883024619, 0.2817188376, 0.6299787005)));
+    let lms_ = sign(lms) * pow(abs(lms), vec3<f32>(1.0 / 3.0));
+
+    return vec3<f32>(dot(lms_, vec3<f32>(0.2104542553, 0.7936177850, -0.0040720468)),
+        dot(lms_, vec3<f32>(1.9779984951, -2.4285922050, 0.4505937099)),
+        dot(lms_, vec3<f32>(0.0259040371, 0.7827717662, -0.8086757660)));
+}
+
+fn oklab_to_linear(oklab: vec3<f32>) -> vec3<f32> {
+    let lms_ = vec3<f32>(dot(oklab, vec3<f32>(1.0, 0.3963377774, 0.2158037573)),
+        dot(oklab, vec3<f32>(1.0, -0.1055613458, -0.0638541728)),
+        dot(oklab, vec3<f32>(1.0, -0.0894841775, -1.2914855480)));
+    let lms = lms_ * lms_ * lms_;
+
+    return vec3<f32>(dot(lms, vec3<f32>(4.0767416621, -3.3077115913, 0.2309699292)),
+        dot(lms, vec3<f32>(-1.2684380046, 2.6097574011, -0.3413193965)),
+        dot(lms, vec3<f32>(-0.0041960863, -0.7034186147, 1.7076147010)));
+}
+
+fn oklab_to_rgb(oklab: vec3<f32>) -> vec3<f32> {
+    return clamp(linear_to_srgb(oklab_to_linear(oklab)), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// The working space is Lab unless params.working_space selects Oklab
+fn rgb_to_working(rgb: vec3<f32>) -> vec3<f32> {
+    if params.working_space == 1u { return rgb_to_oklab(rgb); }
+    return rgb_to_lab(rgb);
+}
+
+fn working_to_rgb(color: vec3<f32>) -> vec3<f32> {
+    if params.working_space == 1u { return oklab_to_rgb(color); }
+    return lab_to_rgb(color);
+}
+
+fn working_to_linear(color: vec3<f32>) -> vec3<f32> {
+    if params.working_space == 1u { return oklab_to_linear(color); }
+    return xyz_to_linear(lab_to_xyz(color));
+}
+
+fn is_in_gamut(color: vec3<f32>) -> bool {
+    let linear = working_to_linear(color);
+    return all(linear >= vec3<f32>(-0.0001)) && all(linear <= vec3<f32>(1.0001));
+}
+
+// With params.gamut_mapping set, colors outside sRGB are pulled towards grey of the same
+// lightness until they fit, since clipping each channel on its own shifts their hue
+fn working_to_rgb_in_gamut(color: vec3<f32>) -> vec3<f32> {
+    if params.gamut_mapping == 0u || is_in_gamut(color) { return working_to_rgb(color); }
+
+    var low = 0.0;
+    var high = 1.0;
+    for (var i = 0; i < 16; i++) {
+        let chroma = (low + high) * 0.5;
+        if is_in_gamut(vec3<f32>(color.x, color.yz * chroma)) {
+            low = chroma;
+        } else {
+            high = chroma;
+        }
+    }
+    return working_to_rgb(vec3<f32>(color.x, color.yz * low));
+}
+
+// Pulls a and b towards the shadow tone below params.split_balance of the lightness range and
+// towards the highlight tone above it, more the further the lightness is from the balance
+fn split_tone(lab: vec3<f32>) -> vec3<f32> {
+    if params.split_tone == 0u { return lab; }
+
+    let lightness_range = select(100.0, 1.0, params.working_space == 1u);
+    let lightness = clamp(lab.x / lightness_range, 0.0, 1.0);
+    let balance = params.split_balance;
+    if lightness < balance {
+        let shadow = vec2<f32>(params.split_shadow_a, params.split_shadow_b);
+        return vec3<f32>(lab.x, mix(lab.yz, shadow, (balance - lightness) / balance));
+    }
+    if balance >= 1.0 { return lab; }
+
+    let highlight = vec2<f32>(params.split_highlight_a, params.split_highlight_b);
+    return vec3<f32>(lab.x, mix(lab.yz, highlight, (lightness - balance) / (1.0 - balance)));
+}
+
 @compute @workgroup_size(16, 16, 1)fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let x = global_id.x;
     let y = global_id.y;
@@ -119,16 +361,31 @@ fn xyz_to_rgb(xyz: vec3<f32>) -> vec3<f32> {
     if x >= params.width || y >= params.height { return; }
 
     let input_color = vec3<f32>(f32(input[index].r), f32(input[index].g), f32(input[index].b));
-    let avg_lab = fast_spatial_color_average(x, y);
-
-    let input_lab = rgb_to_lab(input_color);
-
-    let luminance_transferred_lab = vec3<f32>(input_lab.r, avg_lab.g, avg_lab.b);
-    let luminance_transferred_rgb = lab_to_rgb(luminance_transferred_lab);
-
-    let final_color = mix(input_color, luminance_transferred_rgb, f32(params.blend_factor));
-
-    let clamped_color = clamp_color(final_color);
+    // The first pass leaves preserved pixels untouched, so they're still recognizable here
+    if is_preserved_extreme(input_color) {
+        output[index] = ColorizedPixel(input_color.r, input_color.g, input_color.b);
+        return;
+    }
+
+    let avg_lab = spatial_color_average(x, y);
+
+    let input_lab = rgb_to_working(input_color);
+
+    // The averaged lightness doubles as the blurred copy for the unsharp mask
+    let lightness_range = select(100.0, 1.0, params.working_space == 1u);
+    let sharpened = clamp(select(avg_lab.r, input_lab.r, params.transfer_luminance == 1u)
+        + (input_lab.r - avg_lab.r) * params.sharpen_amount, 0.0, lightness_range);
+    let lightness = select(sharpened, lightness_range - sharpened,
+        params.invert_luminance == 1u);
+    // Averaging mutes a and b, which params.chroma_scale can make up for
+    let luminance_transferred_lab = split_tone(vec3<f32>(lightness,
+        avg_lab.gb * params.chroma_scale));
+    let luminance_transferred_rgb = working_to_rgb_in_gamut(luminance_transferred_lab);
+
+    let final_color = blend_colors(input_color, luminance_transferred_rgb,
+        mask_coverage(index));
+
+    let clamped_color = simulate_cvd(posterize(clamp_color(final_color)));
   // let clamped_color = clamp_color(get_sat_value(x, y));
 
     output[index] = ColorizedPixel(f32(clamped_color.r), f32(clamped_color.g),
@@ -0,0 +1,385 @@
+use crate::{
+    constants::{ADAPTIVE_RADIUS_GRADIENT, MULTI_SCALE_LIGHTNESS_DIFFERENCE},
+    platform::prelude::*,
+    progress::Progress,
+    types::{KernelShape, WorkingSpace},
+    utils::{RgbBuffer, RgbPixel},
+};
+
+/// Per pixel (l, a, b, weight) of the working space values of `image`. Fully transparent pixels
+/// according to `alpha` get a weight of zero so they don't bleed into their neighbors.
+pub fn weighted_pixels<P: RgbPixel>(
+    image: &RgbBuffer<P>,
+    alpha: Option<&[u8]>,
+    space: WorkingSpace,
+) -> Vec<[f32; 4]> {
+    image
+        .pixels()
+        .enumerate()
+        .map(|(i, pixel)| {
+            if alpha.is_some_and(|alpha| alpha[i] == 0) {
+                [0.0; 4]
+            } else {
+                let [l, a, b] = pixel.to_working(space);
+                [l, a, b, 1.0]
+            }
+        })
+        .collect()
+}
+
+// Truncated at three sigma. The weights don't need to be normalized since the weight channel is
+// divided out in the second pass shader
+fn gaussian_kernel(sigma: f32) -> Vec<f32> {
+    let radius = (sigma * 3.0).ceil() as i64;
+    (-radius..=radius)
+        .map(|d| (-((d * d) as f32) / (2.0 * sigma * sigma)).exp())
+        .collect()
+}
+
+fn accumulate(sum: &mut [f32; 4], value: &[f32; 4], weight: f32) {
+    for (s, v) in sum.iter_mut().zip(value) {
+        *s += v * weight;
+    }
+}
+
+/// Gaussian weighted (l, a, b, weight) sums around every pixel of `image`, computed as a
+/// horizontal and then a vertical blur. Dividing by the weight gives the average.
+pub fn gaussian_average<P: RgbPixel>(
+    image: &RgbBuffer<P>,
+    alpha: Option<&[u8]>,
+    space: WorkingSpace,
+    sigma: f32,
+    progress: &Progress<'_>,
+) -> Vec<[f32; 4]> {
+    let (width, height) = image.dimensions();
+    let (width, height) = (width as usize, height as usize);
+    let kernel = gaussian_kernel(sigma);
+    let radius = (kernel.len() / 2) as i64;
+    let pixels = weighted_pixels(image, alpha, space);
+
+    let mut horizontal = vec![[0.0; 4]; width * height];
+    horizontal
+        .par_chunks_mut(width)
+        .zip(pixels.par_chunks(width))
+        .for_each(|(output_row, row)| {
+            // Rows left over once cancelled stay at zero, the caller bails out anyway
+            if progress.is_cancelled() {
+                return;
+            }
+
+            for (x, output) in output_row.iter_mut().enumerate() {
+                for (k, &weight) in kernel.iter().enumerate() {
+                    let sx = x as i64 + k as i64 - radius;
+                    if sx >= 0 && sx < width as i64 {
+                        accumulate(output, &row[sx as usize], weight);
+                    }
+                }
+            }
+        });
+
+    let mut output = vec![[0.0; 4]; width * height];
+    output
+        .par_chunks_mut(width)
+        .enumerate()
+        .for_each(|(y, output_row)| {
+            for (k, &weight) in kernel.iter().enumerate() {
+                let sy = y as i64 + k as i64 - radius;
+                if sy < 0 || sy >= height as i64 {
+                    continue;
+                }
+
+                let row = &horizontal[sy as usize * width..(sy as usize + 1) * width];
+                for (output, value) in output_row.iter_mut().zip(row) {
+                    accumulate(output, value, weight);
+                }
+            }
+            progress.inc(width as u64);
+        });
+
+    output
+}
+
+// Output rows per task of the vertical pass of `separable_box_average`, each sliding its own
+// window down the band
+const SEPARABLE_BAND_HEIGHT: usize = 32;
+
+/// The same square means as `box_average`, computed as a horizontal running sum over every row
+/// and then a vertical one over every column. Only one f32 copy of the image is kept instead of a
+/// summed area table, and the running sums are f64 so adding and removing pixels doesn't drift.
+pub fn separable_box_average<P: RgbPixel>(
+    image: &RgbBuffer<P>,
+    alpha: Option<&[u8]>,
+    space: WorkingSpace,
+    radius: u32,
+    progress: &Progress<'_>,
+) -> Vec<[f32; 4]> {
+    let (width, height) = image.dimensions();
+    let (width, height) = (width as usize, height as usize);
+    let radius = radius as usize;
+    let pixels = weighted_pixels(image, alpha, space);
+
+    let mut horizontal = vec![[0.0; 4]; width * height];
+    horizontal
+        .par_chunks_mut(width.max(1))
+        .zip(pixels.par_chunks(width.max(1)))
+        .for_each(|(output_row, row)| {
+            // Rows left over once cancelled stay at zero, the caller bails out anyway
+            if progress.is_cancelled() {
+                return;
+            }
+
+            let mut window = [0.0f64; 4];
+            for value in &row[..radius.min(width)] {
+                add_to_window(&mut window, value, 1.0);
+            }
+            for (x, output) in output_row.iter_mut().enumerate() {
+                if let Some(value) = row.get(x + radius) {
+                    add_to_window(&mut window, value, 1.0);
+                }
+                *output = window.map(|sum| sum as f32);
+                if x >= radius {
+                    add_to_window(&mut window, &row[x - radius], -1.0);
+                }
+            }
+        });
+
+    let row = |y: usize| &horizontal[y * width..(y + 1) * width];
+    let mut output = vec![[0.0; 4]; width * height];
+    output
+        .par_chunks_mut((width * SEPARABLE_BAND_HEIGHT).max(1))
+        .enumerate()
+        .for_each(|(band, output_rows)| {
+            let top = band * SEPARABLE_BAND_HEIGHT;
+            let mut windows = vec![[0.0f64; 4]; width];
+            for y in top.saturating_sub(radius)..(top + radius).min(height) {
+                for (window, value) in windows.iter_mut().zip(row(y)) {
+                    add_to_window(window, value, 1.0);
+                }
+            }
+
+            for (y, output_row) in (top..).zip(output_rows.chunks_mut(width)) {
+                if y + radius < height {
+                    for (window, value) in windows.iter_mut().zip(row(y + radius)) {
+                        add_to_window(window, value, 1.0);
+                    }
+                }
+
+                // As in `box_average`, the weight channel counts the pixels that contributed
+                for (output, window) in output_row.iter_mut().zip(&windows) {
+                    let area = window[3];
+                    if area > 0.0 {
+                        *output = [
+                            (window[0] / area) as f32,
+                            (window[1] / area) as f32,
+                            (window[2] / area) as f32,
+                            1.0,
+                        ];
+                    }
+                }
+
+                if y >= radius {
+                    for (window, value) in windows.iter_mut().zip(row(y - radius)) {
+                        add_to_window(window, value, -1.0);
+                    }
+                }
+            }
+            progress.inc(output_rows.len() as u64);
+        });
+
+    output
+}
+
+fn add_to_window(window: &mut [f64; 4], value: &[f32; 4], sign: f64) {
+    for (w, v) in window.iter_mut().zip(value) {
+        *w += *v as f64 * sign;
+    }
+}
+
+/// Mean (l, a, b) of the pixels within `radius` of every pixel, read from the summed area table
+/// `integral` of `compute_integral_image`. The weight of each entry is 1, or 0 when every pixel in
+/// reach is transparent.
+///
+/// The sums are differenced in f64 and only the means are narrowed to f32. The raw sums grow with
+/// the image, so narrowing them first would leave too little precision for the difference of two
+/// nearly equal sums in the lower right of large images.
+pub fn box_average(
+    integral: &[Vec<(f64, f64, f64, f64)>],
+    radius: u32,
+    shape: KernelShape,
+) -> Vec<[f32; 4]> {
+    window_average(integral, shape, |_| radius as i64)
+}
+
+/// `box_average` with a radius of its own for every pixel, such as the ones of `adaptive_radii`
+pub fn adaptive_box_average(
+    integral: &[Vec<(f64, f64, f64, f64)>],
+    radii: &[u32],
+    shape: KernelShape,
+) -> Vec<[f32; 4]> {
+    window_average(integral, shape, |index| radii[index] as i64)
+}
+
+/// Blends `box_average` at `radius`, twice `radius`, four times `radius` and so on, one level per
+/// entry of `weights`. Every level past the first is weighted down the further its lightness is
+/// from the first level's, halving at `MULTI_SCALE_LIGHTNESS_DIFFERENCE` percent of the lightness
+/// range. In flat regions the levels agree and the larger ones smooth away what's left of the
+/// noise, while near an edge they reach across it and drop out.
+pub fn multi_scale_box_average(
+    integral: &[Vec<(f64, f64, f64, f64)>],
+    space: WorkingSpace,
+    radius: u32,
+    weights: &[f32],
+    shape: KernelShape,
+) -> Vec<[f32; 4]> {
+    let base = box_average(integral, radius, shape);
+    let scale = 100.0 / space.lightness_range();
+    let mut sums: Vec<[f32; 4]> = base
+        .par_iter()
+        .map(|&[l, a, b, weight]| {
+            let weight = weights[0] * weight;
+            [l * weight, a * weight, b * weight, weight]
+        })
+        .collect();
+
+    for (level, &weight) in weights.iter().enumerate().skip(1) {
+        let averages = box_average(integral, radius << level, shape);
+        sums.par_iter_mut()
+            .zip(averages.par_iter().zip(base.par_iter()))
+            .for_each(|(sum, (average, base))| {
+                let difference = (average[0] - base[0]).abs() * scale;
+                let weight = weight * average[3] * MULTI_SCALE_LIGHTNESS_DIFFERENCE
+                    / (MULTI_SCALE_LIGHTNESS_DIFFERENCE + difference);
+                for (s, c) in sum.iter_mut().zip(&average[..3]) {
+                    *s += c * weight;
+                }
+                sum[3] += weight;
+            });
+    }
+
+    // Only pixels with nothing but transparent pixels in reach of every level are left without
+    // any weight
+    sums.into_par_iter()
+        .map(|[l, a, b, total]| {
+            if total > 0.0 {
+                [l / total, a / total, b / total, 1.0]
+            } else {
+                [0.0; 4]
+            }
+        })
+        .collect()
+}
+
+/// Radius for every pixel of `image` that shrinks from `radius` where its lightness is flat
+/// towards 0 where it changes sharply, so `adaptive_box_average` smooths flat regions without
+/// smearing detail. The radius halves where the Sobel gradient reaches
+/// `ADAPTIVE_RADIUS_GRADIENT` percent of the lightness range per pixel.
+pub fn adaptive_radii<P: RgbPixel>(
+    image: &RgbBuffer<P>,
+    space: WorkingSpace,
+    radius: u32,
+) -> Vec<u32> {
+    let (width, height) = image.dimensions();
+    let (width, height) = (width as usize, height as usize);
+    let scale = 100.0 / space.lightness_range();
+    let lightness: Vec<f32> = image
+        .pixels()
+        .map(|pixel| pixel.to_working(space)[0] * scale)
+        .collect();
+    // Edge pixels repeat outwards, which keeps the border from looking like an edge
+    let at = |x: i64, y: i64| {
+        let x = x.clamp(0, width as i64 - 1) as usize;
+        let y = y.clamp(0, height as i64 - 1) as usize;
+        lightness[y * width + x]
+    };
+
+    let mut radii = vec![radius; width * height];
+    radii
+        .par_chunks_mut(width.max(1))
+        .enumerate()
+        .for_each(|(y, row)| {
+            let y = y as i64;
+            for (x, output) in row.iter_mut().enumerate() {
+                let x = x as i64;
+                let gx = (at(x + 1, y - 1) + 2.0 * at(x + 1, y) + at(x + 1, y + 1))
+                    - (at(x - 1, y - 1) + 2.0 * at(x - 1, y) + at(x - 1, y + 1));
+                let gy = (at(x - 1, y + 1) + 2.0 * at(x, y + 1) + at(x + 1, y + 1))
+                    - (at(x - 1, y - 1) + 2.0 * at(x, y - 1) + at(x + 1, y - 1));
+                // The Sobel kernels weigh a difference over two pixels four times
+                let gradient = gx.hypot(gy) / 8.0;
+                *output = (radius as f32 * ADAPTIVE_RADIUS_GRADIENT
+                    / (ADAPTIVE_RADIUS_GRADIENT + gradient))
+                    .round() as u32;
+            }
+        });
+
+    radii
+}
+
+fn window_average(
+    integral: &[Vec<(f64, f64, f64, f64)>],
+    shape: KernelShape,
+    radius_at: impl Fn(usize) -> i64 + Sync,
+) -> Vec<[f32; 4]> {
+    let height = integral.len() - 1;
+    let width = integral[0].len() - 1;
+
+    // Sum of the pixels in columns x1..=x2 and rows y1..=y2
+    let rect = |x1: usize, y1: usize, x2: usize, y2: usize| {
+        let (top_left, top_right) = (integral[y1][x1], integral[y1][x2 + 1]);
+        let (bottom_left, bottom_right) = (integral[y2 + 1][x1], integral[y2 + 1][x2 + 1]);
+        [
+            bottom_right.0 - top_right.0 - bottom_left.0 + top_left.0,
+            bottom_right.1 - top_right.1 - bottom_left.1 + top_left.1,
+            bottom_right.2 - top_right.2 - bottom_left.2 + top_left.2,
+            bottom_right.3 - top_right.3 - bottom_left.3 + top_left.3,
+        ]
+    };
+    let clamp_x = |x: i64| x.clamp(0, width as i64 - 1) as usize;
+    let clamp_y = |y: i64| y.clamp(0, height as i64 - 1) as usize;
+
+    let mut output = vec![[0.0; 4]; width * height];
+    output
+        .par_chunks_mut(width.max(1))
+        .enumerate()
+        .for_each(|(y, output_row)| {
+            for (x, output) in output_row.iter_mut().enumerate() {
+                let radius = radius_at(y * width + x);
+                let (x, y) = (x as i64, y as i64);
+                let sum = match shape {
+                    KernelShape::Square => rect(
+                        clamp_x(x - radius),
+                        clamp_y(y - radius),
+                        clamp_x(x + radius),
+                        clamp_y(y + radius),
+                    ),
+                    // A disc is summed as one single row rectangle per scanline
+                    KernelShape::Circle => (clamp_y(y - radius)..=clamp_y(y + radius))
+                        .map(|row| {
+                            let dy = row as i64 - y;
+                            let half_width = ((radius * radius - dy * dy) as f64).sqrt() as i64;
+                            rect(clamp_x(x - half_width), row, clamp_x(x + half_width), row)
+                        })
+                        .fold([0.0; 4], |mut sum, row| {
+                            for (s, r) in sum.iter_mut().zip(row) {
+                                *s += r;
+                            }
+                            sum
+                        }),
+                };
+
+                // The weight channel counts the pixels that contributed, which excludes
+                // transparent ones
+                let area = sum[3];
+                if area > 0.0 {
+                    *output = [
+                        (sum[0] / area) as f32,
+                        (sum[1] / area) as f32,
+                        (sum[2] / area) as f32,
+                        1.0,
+                    ];
+                }
+            }
+        });
+
+    output
+}
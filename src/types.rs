@@ -1,10 +1,1192 @@
-use palette::Lab;
+use crate::{
+    builder::AppConfigBuilder,
+    constants::{MAX_MULTI_SCALE_LEVELS, MAX_SOFT_K, MAX_SPATIAL_AVERAGING_RADIUS},
+    distance::ColorDistance,
+    dither::CustomDither,
+    palette::{lab_to_hex, ColorKey},
+    utils::hex_to_rgb,
+};
 
-#[derive(Debug)]
+use std::{fs, path::Path, str::FromStr};
+
+use palette::{FromColor, Lab};
+use serde::{de::Error, Deserializer, Serializer};
+use serde_derive::{Deserialize, Serialize};
+
+// Settings are stored in config files the same way they're passed on the command line, through
+// their `Display` and `FromStr` implementations
+macro_rules! serde_as_str {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl serde::Serialize for $ty {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.collect_str(self)
+                }
+            }
+
+            impl<'de> serde::Deserialize<'de> for $ty {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    <String as serde::Deserialize>::deserialize(deserializer)?
+                        .parse()
+                        .map_err(D::Error::custom)
+                }
+            }
+        )*
+    };
+}
+
+serde_as_str!(
+    DitherAlgorithm,
+    DistanceMetric,
+    BlendFactor,
+    SpatialKernel,
+    SpatialImpl,
+    KernelShape,
+    BlendSpace,
+    GamutMapping,
+    ToneMap,
+    WorkingSpace,
+    MappingMode,
+    CvdType,
+    SplitTone,
+    SkinTone,
+);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DitherAlgorithm {
+    None,
+    Random,
+    Ordered { size: u8 },
+    FloydSteinberg,
+    Atkinson,
+}
+
+impl DitherAlgorithm {
+    pub fn is_error_diffusion(&self) -> bool {
+        matches!(
+            self,
+            DitherAlgorithm::FloydSteinberg | DitherAlgorithm::Atkinson
+        )
+    }
+
+    /// Mode identifier understood by the first pass shader
+    pub fn shader_mode(&self) -> u32 {
+        match self {
+            DitherAlgorithm::Random => 1,
+            DitherAlgorithm::Ordered { .. } => 2,
+            _ => 0,
+        }
+    }
+
+    pub fn bayer_size(&self) -> u32 {
+        match self {
+            DitherAlgorithm::Ordered { size } => *size as u32,
+            _ => 0,
+        }
+    }
+}
+
+impl FromStr for DitherAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(DitherAlgorithm::None),
+            "random" => Ok(DitherAlgorithm::Random),
+            "ordered" | "ordered-4" => Ok(DitherAlgorithm::Ordered { size: 4 }),
+            "ordered-2" => Ok(DitherAlgorithm::Ordered { size: 2 }),
+            "ordered-8" => Ok(DitherAlgorithm::Ordered { size: 8 }),
+            "floyd-steinberg" => Ok(DitherAlgorithm::FloydSteinberg),
+            "atkinson" => Ok(DitherAlgorithm::Atkinson),
+            _ => Err(format!(
+                "Unknown dither algorithm '{}'. Expected one of: none, random, ordered, ordered-2, ordered-4, ordered-8, floyd-steinberg, atkinson",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for DitherAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DitherAlgorithm::None => write!(f, "none"),
+            DitherAlgorithm::Random => write!(f, "random"),
+            DitherAlgorithm::Ordered { size } => write!(f, "ordered-{}", size),
+            DitherAlgorithm::FloydSteinberg => write!(f, "floyd-steinberg"),
+            DitherAlgorithm::Atkinson => write!(f, "atkinson"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DistanceMetric {
+    Cie76,
+    Cie94,
+    Ciede2000,
+}
+
+impl DistanceMetric {
+    /// Metric identifier understood by the first pass shader
+    pub fn shader_mode(&self) -> u32 {
+        match self {
+            DistanceMetric::Cie76 => 0,
+            DistanceMetric::Cie94 => 1,
+            DistanceMetric::Ciede2000 => 2,
+        }
+    }
+}
+
+impl FromStr for DistanceMetric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "cie76" => Ok(DistanceMetric::Cie76),
+            "cie94" => Ok(DistanceMetric::Cie94),
+            "ciede2000" => Ok(DistanceMetric::Ciede2000),
+            _ => Err(format!(
+                "Unknown distance metric '{}'. Expected one of: cie76, cie94, ciede2000",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for DistanceMetric {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DistanceMetric::Cie76 => write!(f, "cie76"),
+            DistanceMetric::Cie94 => write!(f, "cie94"),
+            DistanceMetric::Ciede2000 => write!(f, "ciede2000"),
+        }
+    }
+}
+
+/// How much of the colorized image replaces the original, either for all channels at once or for
+/// red, green and blue separately
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlendFactor {
+    Uniform(f32),
+    PerChannel([f32; 3]),
+}
+
+impl BlendFactor {
+    pub fn channels(&self) -> [f32; 3] {
+        match self {
+            BlendFactor::Uniform(factor) => [*factor; 3],
+            BlendFactor::PerChannel(factors) => *factors,
+        }
+    }
+}
+
+impl From<f32> for BlendFactor {
+    fn from(factor: f32) -> Self {
+        BlendFactor::Uniform(factor)
+    }
+}
+
+impl From<[f32; 3]> for BlendFactor {
+    fn from(factors: [f32; 3]) -> Self {
+        BlendFactor::PerChannel(factors)
+    }
+}
+
+impl FromStr for BlendFactor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let factors = s
+            .split(',')
+            .map(|factor| factor.trim().parse::<f32>().map_err(|e| e.to_string()))
+            .collect::<Result<Vec<f32>, String>>()?;
+
+        match factors[..] {
+            [factor] => Ok(BlendFactor::Uniform(factor)),
+            [r, g, b] => Ok(BlendFactor::PerChannel([r, g, b])),
+            _ => Err(format!(
+                "Invalid blend factor '{}'. Expected a single value or three comma separated values",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for BlendFactor {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BlendFactor::Uniform(factor) => write!(f, "{}", factor),
+            BlendFactor::PerChannel([r, g, b]) => write!(f, "{},{},{}", r, g, b),
+        }
+    }
+}
+
+/// Neighborhood weighting used when spatially averaging the colorized image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpatialKernel {
+    Box,
+    Gaussian {
+        sigma: f32,
+    },
+    Bilateral {
+        spatial_sigma: f32,
+        range_sigma: f32,
+    },
+}
+
+impl SpatialKernel {
+    /// Kernel identifier understood by the second pass shader
+    pub fn shader_mode(&self) -> u32 {
+        match self {
+            SpatialKernel::Box => 0,
+            SpatialKernel::Gaussian { .. } => 1,
+            SpatialKernel::Bilateral { .. } => 2,
+        }
+    }
+
+    /// (spatial sigma, range sigma) for the second pass shader, only used by the bilateral kernel
+    pub fn bilateral_sigmas(&self) -> (f32, f32) {
+        match self {
+            SpatialKernel::Bilateral {
+                spatial_sigma,
+                range_sigma,
+            } => (*spatial_sigma, *range_sigma),
+            _ => (0.0, 0.0),
+        }
+    }
+}
+
+impl FromStr for SpatialKernel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        let parse_sigma = |sigma: &str| match sigma.parse::<f32>() {
+            Ok(sigma) if sigma > 0.0 => Ok(sigma),
+            _ => Err(format!(
+                "Invalid sigma '{}'. Expected a positive number",
+                sigma
+            )),
+        };
+
+        match s.as_str() {
+            "box" => Ok(SpatialKernel::Box),
+            "gaussian" => Ok(SpatialKernel::Gaussian { sigma: 5.0 }),
+            "bilateral" => Ok(SpatialKernel::Bilateral {
+                spatial_sigma: 5.0,
+                range_sigma: 10.0,
+            }),
+            _ => {
+                if let Some(sigma) = s.strip_prefix("gaussian-") {
+                    return Ok(SpatialKernel::Gaussian {
+                        sigma: parse_sigma(sigma)?,
+                    });
+                }
+
+                match s.strip_prefix("bilateral-").and_then(|s| s.split_once('-')) {
+                    Some((spatial_sigma, range_sigma)) => Ok(SpatialKernel::Bilateral {
+                        spatial_sigma: parse_sigma(spatial_sigma)?,
+                        range_sigma: parse_sigma(range_sigma)?,
+                    }),
+                    None => Err(format!(
+                        "Unknown spatial kernel '{}'. Expected one of: box, gaussian, gaussian-<sigma>, bilateral, bilateral-<spatial sigma>-<range sigma>",
+                        s
+                    )),
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for SpatialKernel {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SpatialKernel::Box => write!(f, "box"),
+            SpatialKernel::Gaussian { sigma } => write!(f, "gaussian-{}", sigma),
+            SpatialKernel::Bilateral {
+                spatial_sigma,
+                range_sigma,
+            } => write!(f, "bilateral-{}-{}", spatial_sigma, range_sigma),
+        }
+    }
+}
+
+/// How the square box spatial kernel is averaged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpatialImpl {
+    /// From a summed area table, which takes the same time for any radius
+    Integral,
+    /// As a horizontal and then a vertical running sum, which needs far less memory
+    Separable,
+}
+
+impl FromStr for SpatialImpl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "integral" => Ok(SpatialImpl::Integral),
+            "separable" => Ok(SpatialImpl::Separable),
+            _ => Err(format!(
+                "Unknown spatial implementation '{}'. Expected one of: integral, separable",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for SpatialImpl {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SpatialImpl::Integral => write!(f, "integral"),
+            SpatialImpl::Separable => write!(f, "separable"),
+        }
+    }
+}
+
+/// Shape of the region averaged by the box spatial kernel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KernelShape {
+    Square,
+    Circle,
+}
+
+impl FromStr for KernelShape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "square" => Ok(KernelShape::Square),
+            "circle" => Ok(KernelShape::Circle),
+            _ => Err(format!(
+                "Unknown kernel shape '{}'. Expected one of: square, circle",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for KernelShape {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            KernelShape::Square => write!(f, "square"),
+            KernelShape::Circle => write!(f, "circle"),
+        }
+    }
+}
+
+/// Color space the colorized image is mixed into the original in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendSpace {
+    /// Gamma encoded sRGB values, which darkens midtones
+    Srgb,
+    LinearRgb,
+    /// CIELAB, which keeps saturated colors from going muddy
+    Lab,
+}
+
+impl BlendSpace {
+    /// Space identifier understood by both shader passes
+    pub fn shader_mode(&self) -> u32 {
+        match self {
+            BlendSpace::Srgb => 0,
+            BlendSpace::LinearRgb => 1,
+            BlendSpace::Lab => 2,
+        }
+    }
+}
+
+impl FromStr for BlendSpace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "srgb" => Ok(BlendSpace::Srgb),
+            "linear-rgb" => Ok(BlendSpace::LinearRgb),
+            "lab" => Ok(BlendSpace::Lab),
+            _ => Err(format!(
+                "Unknown blend space '{}'. Expected one of: srgb, linear-rgb, lab",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for BlendSpace {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BlendSpace::Srgb => write!(f, "srgb"),
+            BlendSpace::LinearRgb => write!(f, "linear-rgb"),
+            BlendSpace::Lab => write!(f, "lab"),
+        }
+    }
+}
+
+/// How colors that land outside the sRGB gamut after luminance transfer are brought back into it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GamutMapping {
+    /// Clamps each channel on its own, which can shift the hue of saturated colors
+    Clip,
+    /// Lowers the chroma towards grey of the same lightness until the color fits
+    DesaturateToFit,
+}
+
+impl GamutMapping {
+    /// Mapping identifier understood by both shader passes
+    pub fn shader_mode(&self) -> u32 {
+        match self {
+            GamutMapping::Clip => 0,
+            GamutMapping::DesaturateToFit => 1,
+        }
+    }
+}
+
+impl FromStr for GamutMapping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "clip" => Ok(GamutMapping::Clip),
+            "desaturate" => Ok(GamutMapping::DesaturateToFit),
+            _ => Err(format!(
+                "Unknown gamut mapping '{}'. Expected one of: clip, desaturate",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for GamutMapping {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            GamutMapping::Clip => write!(f, "clip"),
+            GamutMapping::DesaturateToFit => write!(f, "desaturate"),
+        }
+    }
+}
+
+/// How floating point images, such as EXR renders with values above 1.0, are compressed into
+/// displayable range before the first pass. Their values are taken to be linear
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMap {
+    /// Clamps every channel to 1.0, which flattens highlights to white
+    None,
+    /// Reinhard's L / (1 + L) on the luminance, keeping the hue of bright colors
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve per channel, with more contrast than Reinhard
+    Aces,
+}
+
+impl FromStr for ToneMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(ToneMap::None),
+            "reinhard" => Ok(ToneMap::Reinhard),
+            "aces" => Ok(ToneMap::Aces),
+            _ => Err(format!(
+                "Unknown tone map '{}'. Expected one of: none, reinhard, aces",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for ToneMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ToneMap::None => write!(f, "none"),
+            ToneMap::Reinhard => write!(f, "reinhard"),
+            ToneMap::Aces => write!(f, "aces"),
+        }
+    }
+}
+
+/// Color space used for palette matching, dithering and spatial averaging
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WorkingSpace {
+    Lab,
+    Oklab,
+}
+
+impl WorkingSpace {
+    /// Space identifier understood by both shader passes
+    pub fn shader_mode(&self) -> u32 {
+        match self {
+            WorkingSpace::Lab => 0,
+            WorkingSpace::Oklab => 1,
+        }
+    }
+
+    /// Lightness of white in this space
+    pub fn lightness_range(&self) -> f32 {
+        match self {
+            WorkingSpace::Lab => 100.0,
+            WorkingSpace::Oklab => 1.0,
+        }
+    }
+}
+
+impl FromStr for WorkingSpace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "lab" => Ok(WorkingSpace::Lab),
+            "oklab" => Ok(WorkingSpace::Oklab),
+            _ => Err(format!(
+                "Unknown working space '{}'. Expected one of: lab, oklab",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for WorkingSpace {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            WorkingSpace::Lab => write!(f, "lab"),
+            WorkingSpace::Oklab => write!(f, "oklab"),
+        }
+    }
+}
+
+/// How the first pass picks a palette color for each pixel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MappingMode {
+    /// The closest palette color according to the distance metric
+    Nearest,
+    /// The palette is a gradient of evenly spaced stops from black to white, and the lightness of
+    /// the pixel picks the point along it
+    GradientMap,
+}
+
+impl MappingMode {
+    /// Mode identifier understood by the first pass shader
+    pub fn shader_mode(&self) -> u32 {
+        match self {
+            MappingMode::Nearest => 0,
+            MappingMode::GradientMap => 1,
+        }
+    }
+}
+
+impl FromStr for MappingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "nearest" => Ok(MappingMode::Nearest),
+            "gradient-map" => Ok(MappingMode::GradientMap),
+            _ => Err(format!(
+                "Unknown mapping mode '{}'. Expected one of: nearest, gradient-map",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for MappingMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MappingMode::Nearest => write!(f, "nearest"),
+            MappingMode::GradientMap => write!(f, "gradient-map"),
+        }
+    }
+}
+
+/// Ellipse around typical skin tones in the a/b plane of CIELAB, used by `protect_skin`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SkinTone {
+    pub center_a: f32,
+    pub center_b: f32,
+    pub radius_a: f32,
+    pub radius_b: f32,
+}
+
+impl Default for SkinTone {
+    // Covers light to dark skin in well lit photos without reaching neutral greys
+    fn default() -> Self {
+        SkinTone {
+            center_a: 14.0,
+            center_b: 26.0,
+            radius_a: 10.0,
+            radius_b: 20.0,
+        }
+    }
+}
+
+impl SkinTone {
+    /// How much of `lab` is protected from remapping, 1.0 within half of the ellipse, fading out to
+    /// 0.0 at its edge
+    pub fn protection(&self, lab: &Lab) -> f32 {
+        let distance = (((lab.a - self.center_a) / self.radius_a).powi(2)
+            + ((lab.b - self.center_b) / self.radius_b).powi(2))
+        .sqrt();
+        ((1.0 - distance) * 2.0).clamp(0.0, 1.0)
+    }
+}
+
+impl FromStr for SkinTone {
+    type Err = String;
+
+    /// `<a>,<b>,<radius a>,<radius b>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|value| value.trim().parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Invalid skin tone '{}': {}", s, e))?;
+
+        match values[..] {
+            [center_a, center_b, radius_a, radius_b] => Ok(SkinTone {
+                center_a,
+                center_b,
+                radius_a,
+                radius_b,
+            }),
+            _ => Err(format!(
+                "Expected '<a>,<b>,<radius a>,<radius b>', got '{}'",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for SkinTone {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{},{},{},{}",
+            self.center_a, self.center_b, self.radius_a, self.radius_b
+        )
+    }
+}
+
+/// Tints the shadows and the highlights of the output with two different colors. Pixels darker
+/// than `balance`, as a fraction of the lightness range, have their a and b pulled towards those
+/// of `shadow`, and brighter ones towards those of `highlight`. The pull grows from nothing at the
+/// balance to the full tone at black and white, so tones of low chroma give a subtle tint.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SplitTone {
+    pub shadow: Lab,
+    pub highlight: Lab,
+    pub balance: f32,
+}
+
+impl FromStr for SplitTone {
+    type Err = String;
+
+    /// `<shadow hex>,<highlight hex>` or `<shadow hex>,<highlight hex>,<balance>`, with the
+    /// balance at 0.5 when left out
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+        let (shadow, highlight, balance) = match parts[..] {
+            [shadow, highlight] => (shadow, highlight, 0.5),
+            [shadow, highlight, balance] => (
+                shadow,
+                highlight,
+                balance
+                    .parse::<f32>()
+                    .map_err(|e| format!("Invalid balance '{}': {}", balance, e))?,
+            ),
+            _ => {
+                return Err(format!(
+                    "Expected '<shadow>,<highlight>' or '<shadow>,<highlight>,<balance>', got '{}'",
+                    s
+                ))
+            }
+        };
+        if !(0.0..=1.0).contains(&balance) {
+            return Err(format!(
+                "Expected a balance from 0.0 to 1.0, got {}",
+                balance
+            ));
+        }
+
+        Ok(SplitTone {
+            shadow: Lab::from_color(hex_to_rgb(shadow)?),
+            highlight: Lab::from_color(hex_to_rgb(highlight)?),
+            balance,
+        })
+    }
+}
+
+impl std::fmt::Display for SplitTone {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{},{},{}",
+            lab_to_hex(&self.shadow),
+            lab_to_hex(&self.highlight),
+            self.balance
+        )
+    }
+}
+
+/// Kind of color vision deficiency simulated on the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CvdType {
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+impl CvdType {
+    /// Type identifier understood by the second pass shader, where 0 means no simulation
+    pub fn shader_mode(&self) -> u32 {
+        match self {
+            CvdType::Protanopia => 1,
+            CvdType::Deuteranopia => 2,
+            CvdType::Tritanopia => 3,
+        }
+    }
+}
+
+impl FromStr for CvdType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "protanopia" => Ok(CvdType::Protanopia),
+            "deuteranopia" => Ok(CvdType::Deuteranopia),
+            "tritanopia" => Ok(CvdType::Tritanopia),
+            _ => Err(format!(
+                "Unknown color vision deficiency '{}'. Expected one of: protanopia, deuteranopia, tritanopia",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for CvdType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CvdType::Protanopia => write!(f, "protanopia"),
+            CvdType::Deuteranopia => write!(f, "deuteranopia"),
+            CvdType::Tritanopia => write!(f, "tritanopia"),
+        }
+    }
+}
+
+/// Every setting of a colorize run. Config files written through serde store the palette as sRGB
+/// hex codes and every other setting the way it's given on the command line, leaving out the
+/// input and output paths and the custom distance and dithering, which can't be written out.
+/// Settings missing from a file keep the defaults of `AppConfigBuilder`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AppConfig {
+    #[serde(skip)]
     pub input_output_pairs: Vec<(String, String)>,
-    pub blend_factor: f32,
+    #[serde(skip)]
+    pub input_output_dirs: Vec<(String, String)>,
+    pub blend_factor: BlendFactor,
+    pub blend_space: BlendSpace,
+    #[serde(with = "crate::palette::hex_colors")]
     pub colors: Vec<Lab>,
+    pub mapping_mode: MappingMode,
+    pub distance_metric: DistanceMetric,
+    /// Picks the closest palette color instead of `distance_metric` and `luminance_weight` when
+    /// set, which moves the first pass onto the CPU. Blending the closest colors through
+    /// `soft_k` still uses `distance_metric`
+    #[serde(skip)]
+    pub custom_distance: Option<Box<dyn ColorDistance>>,
+    /// Number of closest palette colors blended together for each pixel, up to `MAX_SOFT_K`. 1 maps
+    /// every pixel to the single closest color
+    pub soft_k: usize,
+    /// Picks one of the `STOCHASTIC_CANDIDATES` closest palette colors for each pixel at random,
+    /// each with a probability inversely proportional to its distance, so pixels between palette
+    /// colors split between them in an uncorrelated texture. The pattern follows `dither_seed`.
+    /// Ignored when `soft_k` blends colors
+    pub stochastic_assignment: bool,
+    pub luminance_weight: f32,
+    /// Leaves black and white pixels as they are instead of mapping them onto the palette
+    pub preserve_extremes: bool,
+    /// How far from 0 or 255 every channel of a pixel may be, for `preserve_extremes` to still
+    /// count it as black or white
+    pub extreme_threshold: u8,
+    /// Lowers the blend factor of skin colored pixels so faces keep their natural color
+    pub protect_skin: bool,
+    /// Colors `protect_skin` counts as skin
+    pub skin_tone: SkinTone,
+    /// Only colorizes pixels within the tolerance of one of these source colors, on every sRGB
+    /// channel, and leaves every other pixel as it is. `None` colorizes the whole image
+    #[serde(with = "crate::palette::color_keys")]
+    pub replace_only: Option<Vec<ColorKey>>,
+    /// Keeps the lightness of the original image, otherwise the palette's own lightness is used
+    pub transfer_luminance: bool,
+    /// Flips the lightness of the output after luminance transfer, so light becomes dark and dark
+    /// becomes light while the colors stay on the palette
+    pub invert_luminance: bool,
+    pub gamut_mapping: GamutMapping,
+    /// Brings floating point input into displayable range before anything else sees it
+    pub tone_map: ToneMap,
+    /// Added to the Lab b channel of the image before mapping, warming it when positive and
+    /// cooling it when negative. 0.0 leaves it unchanged
+    pub temperature: f32,
+    /// Added to the Lab a channel of the image before mapping, towards magenta when positive and
+    /// green when negative. 0.0 leaves it unchanged
+    pub tint: f32,
+    /// Added to the Lab lightness of the image before mapping, 0.0 leaves it unchanged
+    pub brightness: f32,
+    /// Scales the Lab lightness of the image around mid grey before mapping, 1.0 leaves it unchanged
+    pub contrast: f32,
     pub dither_amount: f32,
+    /// Seeds the noise of random dithering. The same seed always gives the same output, while
+    /// changing it changes the noise pattern
+    pub dither_seed: u64,
+    pub dither_algorithm: DitherAlgorithm,
+    /// Dithers with a custom implementation instead of `dither_algorithm` when set, which moves the
+    /// first pass onto the CPU
+    #[serde(skip)]
+    pub custom_dither: Option<CustomDither>,
+    /// Scans every other row right to left during error diffusion
+    pub serpentine: bool,
+    /// Reuses the colorized pixel of the previous GIF frame wherever the source pixel at the same
+    /// position of the canvas hasn't changed, so static regions keep the exact same dither from
+    /// frame to frame and only moving content changes
+    pub temporal_coherence: bool,
     pub spatial_averaging_radius: u32,
+    /// Shrinks the box kernel's radius for every pixel as the lightness gradient around it grows,
+    /// so detail isn't smeared while flat regions get the full radius. Always averages through the
+    /// summed area table, whatever `spatial_impl` is
+    pub adaptive_radius: bool,
+    /// Weights of box averages at `spatial_averaging_radius`, twice that, four times that and so
+    /// on, blended into one so flat regions are smoothed at the larger radii while edges keep the
+    /// first. Replaces `adaptive_radius` and always averages through the summed area table when
+    /// set. `None` averages at the one radius
+    pub multi_scale_weights: Option<Vec<f32>>,
+    pub spatial_kernel: SpatialKernel,
+    /// Number of times spatial averaging and luminance transfer run, each on the result of the one
+    /// before. 1 runs them once
+    pub refine_iterations: usize,
+    /// Strength of the unsharp mask applied to the lightness after spatial averaging, 0.0 disables it
+    pub sharpen_amount: f32,
+    /// Multiplies the a and b channels after spatial averaging, above 1.0 to saturate the output
+    /// and below to mute it
+    pub chroma_scale: f32,
+    /// Tints the shadows and highlights differently after chroma scaling, or `None` to leave them
+    pub split_tone: Option<SplitTone>,
+    /// Rounds each output channel to this many evenly spaced levels after blending, or `None` to
+    /// keep every level
+    pub posterize_levels: Option<u8>,
+    /// Shows the output the way it looks with a color vision deficiency, applied after everything
+    /// else
+    pub simulate_cvd: Option<CvdType>,
+    pub kernel_shape: KernelShape,
+    /// How square box averages are computed. Circles always use the summed area table
+    pub spatial_impl: SpatialImpl,
+    pub working_space: WorkingSpace,
+    pub show_progress: bool,
+    /// Draws progress bars even when stderr isn't a terminal, where they're hidden otherwise
+    pub force_progress: bool,
+    /// Quality from 1 to 100 that JPEG output is written with by `batch::save_image`
+    pub jpeg_quality: u8,
+    /// Whether `colorize_with_report` measures the SSIM between the luma of the original and the
+    /// output
+    pub compute_ssim: bool,
+    /// Number of threads for the CPU side of the pipeline, or `None` for the global rayon pool. With
+    /// a single thread every stage runs in order, which gives the same output as any other count
+    pub threads: Option<usize>,
+    /// Rows per strip when colorizing in tiles, or `None` to colorize the whole image at once
+    pub tile_height: Option<u32>,
+    /// Makes the command line print the `estimate_cost` of every input instead of colorizing it
+    #[serde(skip)]
+    pub dry_run: bool,
+}
+
+impl AppConfig {
+    /// Builder starting from the defaults of `AppConfigBuilder`
+    pub fn builder() -> AppConfigBuilder {
+        AppConfigBuilder::new()
+    }
+
+    /// Reads a config from a TOML file and validates it
+    pub fn from_toml_path(path: &Path) -> Result<AppConfig, ConfigFileError> {
+        let config: AppConfig = toml::from_str(&fs::read_to_string(path)?)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Reads a config from a JSON file and validates it
+    pub fn from_json_path(path: &Path) -> Result<AppConfig, ConfigFileError> {
+        let config: AppConfig = serde_json::from_str(&fs::read_to_string(path)?)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks that every setting is in a range the pipeline can work with, naming the first one
+    /// that isn't
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.colors.is_empty() {
+            return Err(ConfigError::EmptyColors);
+        }
+
+        self.validate_settings()
+    }
+
+    /// Same as `validate`, but leaves `colors` out, for callers that bring their own palettes
+    pub(crate) fn validate_settings(&self) -> Result<(), ConfigError> {
+        if let Some(factor) = self
+            .blend_factor
+            .channels()
+            .into_iter()
+            .find(|factor| !(0.0..=1.0).contains(factor))
+        {
+            return Err(ConfigError::OutOfRange {
+                field: "blend_factor",
+                message: format!("Expected a value from 0.0 to 1.0, got {}", factor),
+            });
+        }
+
+        if !self.dither_amount.is_finite() || self.dither_amount < 0.0 {
+            return Err(ConfigError::OutOfRange {
+                field: "dither_amount",
+                message: format!("Expected a non-negative value, got {}", self.dither_amount),
+            });
+        }
+
+        if let DitherAlgorithm::Ordered { size } = self.dither_algorithm {
+            if ![2, 4, 8].contains(&size) {
+                return Err(ConfigError::OutOfRange {
+                    field: "dither_algorithm",
+                    message: format!("Expected a Bayer matrix size of 2, 4 or 8, got {}", size),
+                });
+            }
+        }
+
+        if self.spatial_averaging_radius > MAX_SPATIAL_AVERAGING_RADIUS {
+            return Err(ConfigError::OutOfRange {
+                field: "spatial_averaging_radius",
+                message: format!(
+                    "Expected a value from 0 to {}, got {}",
+                    MAX_SPATIAL_AVERAGING_RADIUS, self.spatial_averaging_radius
+                ),
+            });
+        }
+
+        let sigmas = match self.spatial_kernel {
+            SpatialKernel::Box => vec![],
+            SpatialKernel::Gaussian { sigma } => vec![sigma],
+            SpatialKernel::Bilateral {
+                spatial_sigma,
+                range_sigma,
+            } => vec![spatial_sigma, range_sigma],
+        };
+        if let Some(sigma) = sigmas
+            .into_iter()
+            .find(|sigma| !(sigma.is_finite() && *sigma > 0.0))
+        {
+            return Err(ConfigError::OutOfRange {
+                field: "spatial_kernel",
+                message: format!("Expected a positive sigma, got {}", sigma),
+            });
+        }
+
+        if let Some((field, value)) = [("temperature", self.temperature), ("tint", self.tint)]
+            .into_iter()
+            .find(|(_, value)| !value.is_finite())
+        {
+            return Err(ConfigError::OutOfRange {
+                field,
+                message: format!("Expected a finite value, got {}", value),
+            });
+        }
+
+        if let Some((field, value, range)) = [
+            ("luminance_weight", self.luminance_weight, 0.0..=10.0),
+            ("brightness", self.brightness, -100.0..=100.0),
+            ("contrast", self.contrast, 0.0..=5.0),
+            ("sharpen_amount", self.sharpen_amount, 0.0..=5.0),
+        ]
+        .into_iter()
+        .find(|(_, value, range)| !range.contains(value))
+        {
+            return Err(ConfigError::OutOfRange {
+                field,
+                message: format!(
+                    "Expected a value from {:?} to {:?}, got {}",
+                    range.start(),
+                    range.end(),
+                    value
+                ),
+            });
+        }
+
+        if self.refine_iterations == 0 {
+            return Err(ConfigError::OutOfRange {
+                field: "refine_iterations",
+                message: "Expected at least 1 iteration, got 0".to_string(),
+            });
+        }
+
+        if !(self.chroma_scale.is_finite() && self.chroma_scale >= 0.0) {
+            return Err(ConfigError::OutOfRange {
+                field: "chroma_scale",
+                message: format!("Expected a non-negative value, got {}", self.chroma_scale),
+            });
+        }
+
+        if !(1..=MAX_SOFT_K).contains(&self.soft_k) {
+            return Err(ConfigError::OutOfRange {
+                field: "soft_k",
+                message: format!(
+                    "Expected a value from 1 to {}, got {}",
+                    MAX_SOFT_K, self.soft_k
+                ),
+            });
+        }
+
+        if let Some(levels @ 0..=1) = self.posterize_levels {
+            return Err(ConfigError::OutOfRange {
+                field: "posterize_levels",
+                message: format!("Expected a value from 2 to 255, got {}", levels),
+            });
+        }
+
+        if let Some(weights) = &self.multi_scale_weights {
+            if weights.is_empty() || weights.len() > MAX_MULTI_SCALE_LEVELS {
+                return Err(ConfigError::OutOfRange {
+                    field: "multi_scale_weights",
+                    message: format!(
+                        "Expected 1 to {} weights, got {}",
+                        MAX_MULTI_SCALE_LEVELS,
+                        weights.len()
+                    ),
+                });
+            }
+            if !weights
+                .iter()
+                .all(|weight| weight.is_finite() && *weight >= 0.0)
+                || !weights.iter().any(|weight| *weight > 0.0)
+            {
+                return Err(ConfigError::OutOfRange {
+                    field: "multi_scale_weights",
+                    message: format!(
+                        "Expected non-negative weights that aren't all 0, got {:?}",
+                        weights
+                    ),
+                });
+            }
+        }
+
+        let skin_tone = self.skin_tone;
+        if ![skin_tone.center_a, skin_tone.center_b]
+            .iter()
+            .all(|c| c.is_finite())
+            || ![skin_tone.radius_a, skin_tone.radius_b]
+                .iter()
+                .all(|r| r.is_finite() && *r > 0.0)
+        {
+            return Err(ConfigError::OutOfRange {
+                field: "skin_tone",
+                message: format!(
+                    "Expected a finite center and positive radii, got {}",
+                    skin_tone
+                ),
+            });
+        }
+
+        if let Some(balance) = self
+            .split_tone
+            .map(|tone| tone.balance)
+            .filter(|balance| !(0.0..=1.0).contains(balance))
+        {
+            return Err(ConfigError::OutOfRange {
+                field: "split_tone",
+                message: format!("Expected a balance from 0.0 to 1.0, got {}", balance),
+            });
+        }
+
+        if !(1..=100).contains(&self.jpeg_quality) {
+            return Err(ConfigError::OutOfRange {
+                field: "jpeg_quality",
+                message: format!("Expected a value from 1 to 100, got {}", self.jpeg_quality),
+            });
+        }
+
+        // Rayon reads 0 threads as one per core, which would lift the cap `threads` is for
+        if self.threads == Some(0) {
+            return Err(ConfigError::OutOfRange {
+                field: "threads",
+                message: "Expected at least 1 thread, got 0".to_string(),
+            });
+        }
+
+        if self.tile_height == Some(0) {
+            return Err(ConfigError::OutOfRange {
+                field: "tile_height",
+                message: "Expected at least 1 row, got 0".to_string(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Setting of an `AppConfig` that `AppConfig::validate` rejected
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    EmptyColors,
+    OutOfRange {
+        field: &'static str,
+        message: String,
+    },
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConfigError::EmptyColors => write!(f, "colors: The palette has no colors"),
+            ConfigError::OutOfRange { field, message } => write!(f, "{}: {}", field, message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Failure to load an `AppConfig` from a file
+#[derive(Debug)]
+pub enum ConfigFileError {
+    Io(std::io::Error),
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+    Invalid(ConfigError),
+}
+
+impl std::fmt::Display for ConfigFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConfigFileError::Io(err) => write!(f, "I/O error: {}", err),
+            ConfigFileError::Toml(err) => write!(f, "TOML error: {}", err),
+            ConfigFileError::Json(err) => write!(f, "JSON error: {}", err),
+            ConfigFileError::Invalid(err) => write!(f, "Invalid config: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ConfigFileError {}
+
+impl From<std::io::Error> for ConfigFileError {
+    fn from(err: std::io::Error) -> ConfigFileError {
+        ConfigFileError::Io(err)
+    }
+}
+
+impl From<toml::de::Error> for ConfigFileError {
+    fn from(err: toml::de::Error) -> ConfigFileError {
+        ConfigFileError::Toml(err)
+    }
+}
+
+impl From<serde_json::Error> for ConfigFileError {
+    fn from(err: serde_json::Error) -> ConfigFileError {
+        ConfigFileError::Json(err)
+    }
+}
+
+impl From<ConfigError> for ConfigFileError {
+    fn from(err: ConfigError) -> ConfigFileError {
+        ConfigFileError::Invalid(err)
+    }
 }
@@ -1,6 +1,15 @@
-use image::RgbImage;
-use indicatif::ProgressBar;
-use palette::{IntoColor, Lab, Srgb};
+use crate::{
+    constants::{MAX_SOFT_K, STOCHASTIC_CANDIDATES},
+    platform::prelude::*,
+    progress::Progress,
+    spatial::weighted_pixels,
+    types::{BlendSpace, DistanceMetric, GamutMapping, WorkingSpace},
+};
+
+use std::{ops::Deref, sync::OnceLock};
+
+use image::{DynamicImage, GrayImage, ImageBuffer, Pixel, Rgb};
+use palette::{convert::FromColorUnclamped, FromColor, Lab, LinSrgb, Oklab, Srgb};
 
 pub fn hex_to_rgb(input: &str) -> Result<Srgb<f32>, String> {
     let cleaned = input.trim_start_matches('#');
@@ -36,6 +45,246 @@ pub fn hex_to_rgb(input: &str) -> Result<Srgb<f32>, String> {
     }
 }
 
+// Linearizing sRGB is the expensive part of converting to Lab and only ever sees 256 distinct
+// values per channel, so it's done once up front
+pub(crate) fn srgb_to_linear_table() -> &'static [f32; 256] {
+    static TABLE: OnceLock<[f32; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        std::array::from_fn(|i| Srgb::new(i as f32 / 255.0, 0.0, 0.0).into_linear().red)
+    })
+}
+
+pub fn rgb8_to_lab(r: u8, g: u8, b: u8) -> Lab {
+    let table = srgb_to_linear_table();
+    Lab::from_color(LinSrgb::new(
+        table[r as usize],
+        table[g as usize],
+        table[b as usize],
+    ))
+}
+
+/// Image of `RgbPixel`s
+pub type RgbBuffer<P> = ImageBuffer<P, Vec<<P as Pixel>::Subpixel>>;
+
+/// Image of `RgbPixel`s borrowing its samples, for the parts of the pipeline that only read them
+pub type RgbView<'a, P> = ImageBuffer<P, &'a [<P as Pixel>::Subpixel]>;
+
+/// `RgbView` of all of `img`
+pub(crate) fn as_view<P: RgbPixel>(img: &RgbBuffer<P>) -> RgbView<'_, P> {
+    // The samples of a buffer always fit its own dimensions
+    ImageBuffer::from_raw(img.width(), img.height(), img.as_raw().as_slice()).unwrap()
+}
+
+/// Copy of `img` that owns its samples, whatever it read them from
+pub(crate) fn to_buffer<P: RgbPixel, C: Deref<Target = [P::Subpixel]>>(
+    img: &ImageBuffer<P, C>,
+) -> RgbBuffer<P> {
+    ImageBuffer::from_raw(img.width(), img.height(), img.to_vec()).unwrap()
+}
+
+/// RGB pixel types the pipeline reads and writes. 8 bit pixels go through the lookup table while
+/// 16 bit ones keep their full precision through every conversion
+pub trait RgbPixel: Pixel<Subpixel: Send + Sync> + Send + Sync + 'static {
+    /// Value of a fully saturated channel
+    const MAX: f32;
+
+    /// Channels scaled to 0.0..=1.0
+    fn to_srgb(&self) -> [f32; 3];
+
+    /// Saturating cast from channels already scaled to 0.0..=MAX, truncating like `as` does
+    fn from_scaled(channels: [f32; 3]) -> Self;
+
+    /// Converts the pixel to (lightness, a, b) in `space`
+    fn to_working(&self, space: WorkingSpace) -> [f32; 3];
+
+    fn convert(img: &DynamicImage) -> RgbBuffer<Self>;
+}
+
+impl RgbPixel for Rgb<u8> {
+    const MAX: f32 = 255.0;
+
+    fn to_srgb(&self) -> [f32; 3] {
+        self.0.map(|c| c as f32 / 255.0)
+    }
+
+    fn from_scaled(channels: [f32; 3]) -> Self {
+        Rgb(channels.map(|c| c as u8))
+    }
+
+    fn to_working(&self, space: WorkingSpace) -> [f32; 3] {
+        let [r, g, b] = self.0;
+        rgb8_to_working(r, g, b, space)
+    }
+
+    fn convert(img: &DynamicImage) -> RgbBuffer<Self> {
+        img.to_rgb8()
+    }
+}
+
+impl RgbPixel for Rgb<u16> {
+    const MAX: f32 = 65535.0;
+
+    fn to_srgb(&self) -> [f32; 3] {
+        self.0.map(|c| c as f32 / 65535.0)
+    }
+
+    fn from_scaled(channels: [f32; 3]) -> Self {
+        Rgb(channels.map(|c| c as u16))
+    }
+
+    fn to_working(&self, space: WorkingSpace) -> [f32; 3] {
+        let [r, g, b] = self.to_srgb();
+        let linear = Srgb::new(r, g, b).into_linear();
+        match space {
+            WorkingSpace::Lab => lab_to_working(&Lab::from_color(linear), space),
+            WorkingSpace::Oklab => {
+                let oklab = Oklab::from_color(linear);
+                [oklab.l, oklab.a, oklab.b]
+            }
+        }
+    }
+
+    fn convert(img: &DynamicImage) -> RgbBuffer<Self> {
+        img.to_rgb16()
+    }
+}
+
+/// Whether `img` has more than 8 bits per channel, so it's worth colorizing at 16 bits
+pub fn is_high_bit_depth(img: &DynamicImage) -> bool {
+    img.color().bytes_per_pixel() > img.color().channel_count()
+}
+
+/// Converts an sRGB pixel to (lightness, a, b) in `space`
+pub fn rgb8_to_working(r: u8, g: u8, b: u8, space: WorkingSpace) -> [f32; 3] {
+    match space {
+        WorkingSpace::Lab => lab_to_working(&rgb8_to_lab(r, g, b), space),
+        WorkingSpace::Oklab => {
+            let table = srgb_to_linear_table();
+            let oklab = Oklab::from_color(LinSrgb::new(
+                table[r as usize],
+                table[g as usize],
+                table[b as usize],
+            ));
+            [oklab.l, oklab.a, oklab.b]
+        }
+    }
+}
+
+pub fn lab_to_working(lab: &Lab, space: WorkingSpace) -> [f32; 3] {
+    match space {
+        WorkingSpace::Lab => [lab.l, lab.a, lab.b],
+        WorkingSpace::Oklab => {
+            let oklab = Oklab::from_color(*lab);
+            [oklab.l, oklab.a, oklab.b]
+        }
+    }
+}
+
+pub fn working_to_lab(color: [f32; 3], space: WorkingSpace) -> Lab {
+    let [l, a, b] = color;
+    match space {
+        WorkingSpace::Lab => Lab::new(l, a, b),
+        WorkingSpace::Oklab => Lab::from_color(Oklab::new(l, a, b)),
+    }
+}
+
+pub fn working_to_srgb(color: [f32; 3], space: WorkingSpace) -> Srgb {
+    let [l, a, b] = color;
+    match space {
+        WorkingSpace::Lab => Srgb::from_color(Lab::new(l, a, b)),
+        WorkingSpace::Oklab => Srgb::from_color(Oklab::new(l, a, b)),
+    }
+}
+
+/// `working_to_srgb` that brings colors outside of sRGB back in with `mapping` instead of always
+/// clipping them
+pub fn working_to_srgb_in_gamut(
+    color: [f32; 3],
+    space: WorkingSpace,
+    mapping: GamutMapping,
+) -> Srgb {
+    if mapping == GamutMapping::Clip || is_in_gamut(color, space) {
+        return working_to_srgb(color, space);
+    }
+
+    // Binary search for the largest chroma scale that still fits, at the same lightness and hue
+    let [l, a, b] = color;
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..16 {
+        let chroma = (low + high) * 0.5;
+        if is_in_gamut([l, a * chroma, b * chroma], space) {
+            low = chroma;
+        } else {
+            high = chroma;
+        }
+    }
+    working_to_srgb([l, a * low, b * low], space)
+}
+
+// `FromColor` clamps, so the check has to go through the unclamped conversion
+fn is_in_gamut(color: [f32; 3], space: WorkingSpace) -> bool {
+    let [l, a, b] = color;
+    let rgb = match space {
+        WorkingSpace::Lab => LinSrgb::from_color_unclamped(Lab::new(l, a, b)),
+        WorkingSpace::Oklab => LinSrgb::from_color_unclamped(Oklab::new(l, a, b)),
+    };
+    [rgb.red, rgb.green, rgb.blue]
+        .iter()
+        .all(|c| (-0.0001..=1.0001).contains(c))
+}
+
+/// Mixes `colorized` into `original` by the per channel `blend_factor` in `space`. Mixing the
+/// gamma encoded values directly darkens midtones. In Lab the factors apply to L, a and b in order
+pub fn blend_colors(
+    original: Srgb,
+    colorized: Srgb,
+    blend_factor: [f32; 3],
+    space: BlendSpace,
+) -> Srgb {
+    // Skipping the round trip through another space keeps masked out pixels exactly as they were
+    if blend_factor == [0.0; 3] {
+        return original;
+    }
+
+    let mix =
+        |original: f32, colorized: f32, factor: f32| original + (colorized - original) * factor;
+    let colorized = Srgb::new(
+        colorized.red.clamp(0.0, 1.0),
+        colorized.green.clamp(0.0, 1.0),
+        colorized.blue.clamp(0.0, 1.0),
+    );
+
+    let blended = match space {
+        BlendSpace::Srgb => Srgb::new(
+            mix(original.red, colorized.red, blend_factor[0]),
+            mix(original.green, colorized.green, blend_factor[1]),
+            mix(original.blue, colorized.blue, blend_factor[2]),
+        ),
+        BlendSpace::LinearRgb => {
+            let (original, colorized) = (original.into_linear(), colorized.into_linear());
+            Srgb::from_linear(LinSrgb::new(
+                mix(original.red, colorized.red, blend_factor[0]),
+                mix(original.green, colorized.green, blend_factor[1]),
+                mix(original.blue, colorized.blue, blend_factor[2]),
+            ))
+        }
+        BlendSpace::Lab => {
+            let (original, colorized) = (Lab::from_color(original), Lab::from_color(colorized));
+            Srgb::from_color(Lab::new(
+                mix(original.l, colorized.l, blend_factor[0]),
+                mix(original.a, colorized.a, blend_factor[1]),
+                mix(original.b, colorized.b, blend_factor[2]),
+            ))
+        }
+    };
+
+    Srgb::new(
+        blended.red.clamp(0.0, 1.0),
+        blended.green.clamp(0.0, 1.0),
+        blended.blue.clamp(0.0, 1.0),
+    )
+}
+
 pub fn interpolate_color(color1: &Lab, color2: &Lab, t: f32) -> Lab {
     Lab::new(
         color1.l + (color2.l - color1.l) * t,
@@ -44,36 +293,377 @@ pub fn interpolate_color(color1: &Lab, color2: &Lab, t: f32) -> Lab {
     )
 }
 
-pub fn compute_integral_image(
-    image: &RgbImage,
-    progress_bar: &ProgressBar,
-) -> Vec<Vec<(f64, f64, f64)>> {
+fn cie94(lab1: &Lab, lab2: &Lab, luminance_weight: f32) -> f32 {
+    let c1 = (lab1.a.powi(2) + lab1.b.powi(2)).sqrt();
+    let c2 = (lab2.a.powi(2) + lab2.b.powi(2)).sqrt();
+    let delta_l = (lab1.l - lab2.l) * luminance_weight;
+    let delta_c = c1 - c2;
+    let delta_h_squared =
+        ((lab1.a - lab2.a).powi(2) + (lab1.b - lab2.b).powi(2) - delta_c.powi(2)).max(0.0);
+
+    let s_c = 1.0 + 0.045 * c1;
+    let s_h = 1.0 + 0.015 * c1;
+
+    (delta_l.powi(2) + (delta_c / s_c).powi(2) + delta_h_squared / s_h.powi(2)).sqrt()
+}
+
+fn hue_degrees(b: f32, a: f32) -> f32 {
+    if a == 0.0 && b == 0.0 {
+        return 0.0;
+    }
+    let hue = b.atan2(a).to_degrees();
+    if hue < 0.0 {
+        hue + 360.0
+    } else {
+        hue
+    }
+}
+
+// Mirrors `ciede2000` in the first pass shader, since palette's implementation has no way to
+// weight the lightness term
+fn ciede2000(lab1: &Lab, lab2: &Lab, luminance_weight: f32) -> f32 {
+    const POW25_7: f32 = 6103515625.0;
+
+    let c_bar =
+        ((lab1.a.powi(2) + lab1.b.powi(2)).sqrt() + (lab2.a.powi(2) + lab2.b.powi(2)).sqrt()) / 2.0;
+    let c_bar7 = c_bar.powi(7);
+    let g = 0.5 * (1.0 - (c_bar7 / (c_bar7 + POW25_7)).sqrt());
+
+    let a1 = lab1.a * (1.0 + g);
+    let a2 = lab2.a * (1.0 + g);
+    let c1 = (a1.powi(2) + lab1.b.powi(2)).sqrt();
+    let c2 = (a2.powi(2) + lab2.b.powi(2)).sqrt();
+    let h1 = hue_degrees(lab1.b, a1);
+    let h2 = hue_degrees(lab2.b, a2);
+
+    let delta_l = lab2.l - lab1.l;
+    let delta_c = c2 - c1;
+
+    let mut delta_h = 0.0;
+    if c1 * c2 != 0.0 {
+        delta_h = h2 - h1;
+        if delta_h > 180.0 {
+            delta_h -= 360.0;
+        } else if delta_h < -180.0 {
+            delta_h += 360.0;
+        }
+    }
+    let delta_big_h = 2.0 * (c1 * c2).sqrt() * (delta_h.to_radians() / 2.0).sin();
+
+    let l_bar = (lab1.l + lab2.l) / 2.0;
+    let c_bar_prime = (c1 + c2) / 2.0;
+
+    let mut h_bar = h1 + h2;
+    if c1 * c2 != 0.0 {
+        if (h1 - h2).abs() <= 180.0 {
+            h_bar /= 2.0;
+        } else if h_bar < 360.0 {
+            h_bar = (h_bar + 360.0) / 2.0;
+        } else {
+            h_bar = (h_bar - 360.0) / 2.0;
+        }
+    }
+
+    let t = 1.0 - 0.17 * (h_bar - 30.0).to_radians().cos()
+        + 0.24 * (2.0 * h_bar).to_radians().cos()
+        + 0.32 * (3.0 * h_bar + 6.0).to_radians().cos()
+        - 0.20 * (4.0 * h_bar - 63.0).to_radians().cos();
+    let delta_theta = 30.0 * (-((h_bar - 275.0) / 25.0).powi(2)).exp();
+    let c_bar_prime7 = c_bar_prime.powi(7);
+    let r_c = 2.0 * (c_bar_prime7 / (c_bar_prime7 + POW25_7)).sqrt();
+    let l_offset = (l_bar - 50.0).powi(2);
+    let s_l = 1.0 + 0.015 * l_offset / (20.0 + l_offset).sqrt();
+    let s_c = 1.0 + 0.045 * c_bar_prime;
+    let s_h = 1.0 + 0.015 * c_bar_prime * t;
+    let r_t = -(2.0 * delta_theta).to_radians().sin() * r_c;
+
+    let l_term = delta_l / s_l * luminance_weight;
+    let c_term = delta_c / s_c;
+    let h_term = delta_big_h / s_h;
+
+    (l_term.powi(2) + c_term.powi(2) + h_term.powi(2) + r_t * c_term * h_term)
+        .max(0.0)
+        .sqrt()
+}
+
+/// Distance between two colors with the lightness difference scaled by `luminance_weight`, so a
+/// weight of 1.0 gives the plain metric
+pub fn color_distance(
+    lab1: &Lab,
+    lab2: &Lab,
+    metric: DistanceMetric,
+    luminance_weight: f32,
+) -> f32 {
+    match metric {
+        DistanceMetric::Cie76 => weighted_euclidean(
+            &[lab1.l, lab1.a, lab1.b],
+            &[lab2.l, lab2.a, lab2.b],
+            luminance_weight,
+        ),
+        DistanceMetric::Cie94 => cie94(lab1, lab2, luminance_weight),
+        DistanceMetric::Ciede2000 => ciede2000(lab1, lab2, luminance_weight),
+    }
+}
+
+fn working_distance(
+    color1: &[f32; 3],
+    color2: &[f32; 3],
+    metric: DistanceMetric,
+    space: WorkingSpace,
+    luminance_weight: f32,
+) -> f32 {
+    match space {
+        WorkingSpace::Lab => color_distance(
+            &Lab::new(color1[0], color1[1], color1[2]),
+            &Lab::new(color2[0], color2[1], color2[2]),
+            metric,
+            luminance_weight,
+        ),
+        // CIE94 and CIEDE2000 are only defined for Lab
+        WorkingSpace::Oklab => weighted_euclidean(color1, color2, luminance_weight),
+    }
+}
+
+pub(crate) fn weighted_euclidean(
+    color1: &[f32; 3],
+    color2: &[f32; 3],
+    luminance_weight: f32,
+) -> f32 {
+    (((color1[0] - color2[0]) * luminance_weight).powi(2)
+        + (color1[1] - color2[1]).powi(2)
+        + (color1[2] - color2[2]).powi(2))
+    .sqrt()
+}
+
+/// CPU counterpart of `find_closest_color` in the first pass shader. `color` and `colors` are
+/// expected to already be in `space`. Colors whose distance comes out as NaN, like palette entries
+/// with NaN components, are never picked unless nothing else is left.
+pub fn find_closest_color(
+    color: &[f32; 3],
+    colors: &[[f32; 3]],
+    metric: DistanceMetric,
+    space: WorkingSpace,
+    luminance_weight: f32,
+) -> [f32; 3] {
+    // total_cmp orders NaNs with their sign bit set below every number, so they're mapped to
+    // infinity instead
+    let distance = |other: &[f32; 3]| {
+        let distance = working_distance(color, other, metric, space, luminance_weight);
+        if distance.is_nan() {
+            f32::INFINITY
+        } else {
+            distance
+        }
+    };
+    *colors
+        .iter()
+        .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+        .unwrap()
+}
+
+// CPU counterpart of `pcg_hash` in the first pass shader
+fn pcg_hash(input: u32) -> u32 {
+    let state = input.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+/// CPU counterpart of `pixel_noise` in the first pass shader. Uniform noise in [0, 1) for the
+/// pixel at `x`, `y` of the whole image, which only changes with `seed`
+pub fn pixel_noise(x: u32, y: u32, seed: u64) -> f32 {
+    let seed = pcg_hash(seed as u32 ^ pcg_hash((seed >> 32) as u32));
+    (pcg_hash(x ^ pcg_hash(y ^ seed)) >> 8) as f32 / 16777216.0
+}
+
+/// CPU counterpart of `assignment_noise` in the first pass shader. Same as `pixel_noise` on offset
+/// rows, so stochastic assignment doesn't line up with random dithering from the same `seed`
+pub fn assignment_noise(x: u32, y: u32, seed: u64) -> f32 {
+    pixel_noise(x, y ^ 0x9e3779b9, seed)
+}
+
+/// CPU counterpart of `bayer_threshold` in the first pass shader. Threshold in [0, 1) from a
+/// `size` x `size` Bayer matrix, where `size` is a power of two
+pub fn bayer_threshold(x: u32, y: u32, size: u32) -> f32 {
+    let mut value = 0;
+    let mut bit = 1;
+    while bit < size {
+        let bx = ((x & bit) != 0) as u32;
+        let by = ((y & bit) != 0) as u32;
+        value = (value << 2) | (((bx ^ by) << 1) | by);
+        bit *= 2;
+    }
+
+    (value as f32 + 0.5) / (size * size) as f32
+}
+
+/// Threshold in (0, 1) for the pixel at `x`, `y` from `texture` repeated over the image, for
+/// dithering with a blue noise or any other tileable mask. `texture` can't be empty.
+pub fn texture_threshold(texture: &GrayImage, x: u32, y: u32) -> f32 {
+    let value = texture.get_pixel(x % texture.width(), y % texture.height())[0];
+    (value as f32 + 0.5) / 256.0
+}
+
+/// CPU counterpart of `is_preserved_extreme` in both shader passes. Whether every channel of `rgb`,
+/// scaled to 0.0..=1.0, is within `threshold` out of 255 of black, or of white.
+pub fn is_extreme(rgb: [f32; 3], threshold: u8) -> bool {
+    let threshold = threshold as f32 / 255.0;
+    rgb.iter().all(|&c| c <= threshold) || rgb.iter().all(|&c| c >= 1.0 - threshold)
+}
+
+/// CPU counterpart of `find_soft_closest_color` in the first pass shader. Blends the `k` closest
+/// `colors`, up to `MAX_SOFT_K`, each weighted by the inverse of its distance, which smooths the
+/// jumps between palette colors on gradients. A `k` of 1 is the same as `find_closest_color`, and
+/// a color matching a palette color exactly maps to just that color.
+pub fn find_soft_closest_color(
+    color: &[f32; 3],
+    colors: &[[f32; 3]],
+    metric: DistanceMetric,
+    space: WorkingSpace,
+    luminance_weight: f32,
+    k: usize,
+) -> [f32; 3] {
+    if k <= 1 {
+        return find_closest_color(color, colors, metric, space, luminance_weight);
+    }
+
+    // Colors with a NaN distance are skipped just like in find_closest_color
+    let mut closest: Vec<(f32, [f32; 3])> = colors
+        .iter()
+        .map(|other| {
+            let distance = working_distance(color, other, metric, space, luminance_weight);
+            (distance, *other)
+        })
+        .filter(|(distance, _)| distance.is_finite())
+        .collect();
+
+    if closest.is_empty() {
+        return find_closest_color(color, colors, metric, space, luminance_weight);
+    }
+
+    // The sort is stable, so ties go to the earlier color like they do in find_closest_color
+    closest.sort_by(|a, b| a.0.total_cmp(&b.0));
+    closest.truncate(k.min(MAX_SOFT_K));
+    if closest[0].0 == 0.0 {
+        return closest[0].1;
+    }
+
+    let mut sum = [0.0; 3];
+    let mut total_weight = 0.0;
+    for (distance, other) in closest {
+        let weight = 1.0 / distance;
+        for (sum, c) in sum.iter_mut().zip(other) {
+            *sum += c * weight;
+        }
+        total_weight += weight;
+    }
+
+    sum.map(|c| c / total_weight)
+}
+
+/// CPU counterpart of `find_stochastic_color` in the first pass shader. Picks one of the
+/// `STOCHASTIC_CANDIDATES` closest colors, each with a probability proportional to the inverse of
+/// its distance, using `noise` in [0, 1), and returns its index in `colors`. A color matching one of
+/// `colors` exactly always picks that one.
+pub fn find_stochastic_index(
+    color: &[f32; 3],
+    colors: &[[f32; 3]],
+    metric: DistanceMetric,
+    space: WorkingSpace,
+    luminance_weight: f32,
+    noise: f32,
+) -> usize {
+    // Colors with a NaN distance are skipped just like in find_soft_closest_color
+    let mut closest: Vec<(f32, usize)> = colors
+        .iter()
+        .map(|other| working_distance(color, other, metric, space, luminance_weight))
+        .enumerate()
+        .map(|(index, distance)| (distance, index))
+        .filter(|(distance, _)| distance.is_finite())
+        .collect();
+
+    if closest.is_empty() {
+        return 0;
+    }
+
+    closest.sort_by(|a, b| a.0.total_cmp(&b.0));
+    closest.truncate(STOCHASTIC_CANDIDATES);
+    if closest[0].0 == 0.0 {
+        return closest[0].1;
+    }
+
+    let total_weight: f32 = closest.iter().map(|(distance, _)| 1.0 / distance).sum();
+    let mut cumulative = 0.0;
+    for &(distance, index) in &closest {
+        cumulative += 1.0 / distance / total_weight;
+        if noise < cumulative {
+            return index;
+        }
+    }
+
+    // Rounding can leave the sum just short of the noise
+    closest[closest.len() - 1].1
+}
+
+/// CPU counterpart of `gradient_map` in the first pass shader. `stops` are evenly spaced from black
+/// to white and the lightness of `color` picks the point between the two stops around it, so the
+/// result is interpolated linearly in `space`.
+pub fn gradient_map(color: &[f32; 3], stops: &[[f32; 3]], space: WorkingSpace) -> [f32; 3] {
+    let position = (color[0] / space.lightness_range()).clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+    let index = (position as usize).min(stops.len().max(2) - 2);
+    let (from, to) = (stops[index], stops[(index + 1).min(stops.len() - 1)]);
+    let t = position - index as f32;
+
+    std::array::from_fn(|i| from[i] + (to[i] - from[i]) * t)
+}
+
+/// Summed area table of the `space` values of `image`, plus a fourth channel counting the pixels that
+/// contributed. Fully transparent pixels according to `alpha` don't contribute at all.
+///
+/// Every row is summed up on its own in parallel first, and the rows are then added onto the ones
+/// below them with the columns in parallel. Each entry is always summed in the same order, so the
+/// table doesn't depend on the number of threads.
+pub fn compute_integral_image<P: RgbPixel>(
+    image: &RgbBuffer<P>,
+    alpha: Option<&[u8]>,
+    space: WorkingSpace,
+    progress: &Progress<'_>,
+) -> Vec<Vec<(f64, f64, f64, f64)>> {
     let (width, height) = image.dimensions();
-    let mut integral = vec![vec![(0.0, 0.0, 0.0); width as usize + 1]; height as usize + 1];
-
-    for y in 1..=height as usize {
-        for x in 1..=width as usize {
-            let pixel = image.get_pixel(x as u32 - 1, y as u32 - 1);
-            let lab: Lab = Srgb::new(
-                pixel[0] as f32 / 255.0,
-                pixel[1] as f32 / 255.0,
-                pixel[2] as f32 / 255.0,
-            )
-            .into_color();
-
-            integral[y][x] = (
-                integral[y - 1][x].0 + integral[y][x - 1].0 - integral[y - 1][x - 1].0
-                    + lab.l as f64,
-                integral[y - 1][x].1 + integral[y][x - 1].1 - integral[y - 1][x - 1].1
-                    + lab.a as f64,
-                integral[y - 1][x].2 + integral[y][x - 1].2 - integral[y - 1][x - 1].2
-                    + lab.b as f64,
-            );
-
-            if (y * width as usize + x) % 100 == 0 {
-                progress_bar.inc(100);
+    let width = width as usize;
+    let pixels = weighted_pixels(image, alpha, space);
+    let mut integral = vec![vec![(0.0, 0.0, 0.0, 0.0); width + 1]; height as usize + 1];
+
+    integral[1..]
+        .par_iter_mut()
+        .zip(pixels.par_chunks(width.max(1)))
+        .for_each(|(row, pixels)| {
+            // The rest of the table is left at zero once cancelled, the caller bails out anyway
+            if progress.is_cancelled() {
+                return;
             }
-        }
+
+            let mut sum = (0.0, 0.0, 0.0, 0.0);
+            for (entry, &[l, a, b, weight]) in row[1..].iter_mut().zip(pixels) {
+                sum.0 += l as f64;
+                sum.1 += a as f64;
+                sum.2 += b as f64;
+                sum.3 += weight as f64;
+                *entry = sum;
+            }
+            progress.inc(width as u64);
+        });
+
+    for y in 2..integral.len() {
+        let (above, rows) = integral.split_at_mut(y);
+        rows[0]
+            .par_iter_mut()
+            .zip(above[y - 1].par_iter())
+            .for_each(|(entry, above)| {
+                entry.0 += above.0;
+                entry.1 += above.1;
+                entry.2 += above.2;
+                entry.3 += above.3;
+            });
     }
 
     integral
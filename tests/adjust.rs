@@ -0,0 +1,62 @@
+use image_colorizer::{
+    adjust::{adjust_lightness, adjust_white_balance},
+    types::WorkingSpace,
+    utils::rgb8_to_working,
+};
+
+use image::{Rgb, RgbImage};
+
+fn image() -> RgbImage {
+    RgbImage::from_fn(64, 64, |x, y| {
+        Rgb([(x * 4) as u8, (y * 4) as u8, ((x + y) * 2) as u8])
+    })
+}
+
+fn lightness(pixel: &Rgb<u8>) -> f32 {
+    rgb8_to_working(pixel[0], pixel[1], pixel[2], WorkingSpace::Lab)[0]
+}
+
+#[test]
+fn neutral_adjustment_is_a_no_op() {
+    let img = image();
+    assert_eq!(adjust_lightness(&img, 0.0, 1.0), img);
+    assert_eq!(adjust_white_balance(&img, 0.0, 0.0), img);
+}
+
+#[test]
+fn brightness_and_contrast_move_lightness() {
+    let img = image();
+    let brightened = adjust_lightness(&img, 20.0, 1.0);
+
+    for (original, brightened) in img.pixels().zip(brightened.pixels()) {
+        assert!(lightness(brightened) >= lightness(original) - 0.5);
+    }
+
+    // Without contrast everything collapses to mid grey. Greys are used since saturated colors can
+    // leave the sRGB gamut at that lightness
+    let greys = RgbImage::from_fn(256, 1, |x, _| Rgb([x as u8; 3]));
+    for pixel in adjust_lightness(&greys, 0.0, 0.0).pixels() {
+        assert!((lightness(pixel) - 50.0).abs() < 0.5);
+    }
+}
+
+#[test]
+fn temperature_and_tint_shift_b_and_a() {
+    // Mid greys stay inside the sRGB gamut after a moderate shift either way
+    let greys = RgbImage::from_fn(64, 1, |x, _| Rgb([(96 + x) as u8; 3]));
+
+    for (temperature, tint) in [(15.0, 0.0), (-15.0, 0.0), (0.0, 15.0), (0.0, -15.0)] {
+        let adjusted = adjust_white_balance(&greys, temperature, tint);
+        for (original, pixel) in greys.pixels().zip(adjusted.pixels()) {
+            let [l, a, b] = rgb8_to_working(pixel[0], pixel[1], pixel[2], WorkingSpace::Lab);
+            assert!((l - lightness(original)).abs() < 2.0);
+            assert!((a - tint).abs() < 1.5, "a is {} for a tint of {}", a, tint);
+            assert!(
+                (b - temperature).abs() < 1.5,
+                "b is {} for a temperature of {}",
+                b,
+                temperature
+            );
+        }
+    }
+}
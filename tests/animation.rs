@@ -0,0 +1,92 @@
+mod common;
+
+use common::test_config;
+use image::{Rgba, RgbaImage};
+use image_colorizer::{
+    animation::colorize_gif,
+    colorize::ColorizeError,
+    types::{AppConfig, DitherAlgorithm},
+};
+
+// 16x16 frames have at most 256 colors, so the encoder keeps them exactly
+fn source_frame() -> RgbaImage {
+    RgbaImage::from_fn(16, 16, |x, y| {
+        Rgba([(x * 16) as u8, (y * 16) as u8, ((x + y) * 8) as u8, 255])
+    })
+}
+
+// The whole canvas, then the same with the top left corner changed, then a quarter of the canvas
+// redrawn exactly as it was
+fn animation() -> Vec<u8> {
+    let mut moved = source_frame();
+    for y in 0..4 {
+        for x in 0..4 {
+            moved.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+        }
+    }
+    let corner = image::imageops::crop_imm(&source_frame(), 8, 8, 4, 4).to_image();
+
+    let mut bytes = Vec::new();
+    let mut encoder = gif::Encoder::new(&mut bytes, 16, 16, &[]).unwrap();
+    for (image, left, top) in [(source_frame(), 0, 0), (moved, 0, 0), (corner, 8, 8)] {
+        let (width, height) = (image.width() as u16, image.height() as u16);
+        let mut frame = gif::Frame::from_rgba(width, height, &mut image.into_raw());
+        frame.left = left;
+        frame.top = top;
+        encoder.write_frame(&frame).unwrap();
+    }
+    drop(encoder);
+    bytes
+}
+
+async fn colorized_frames(config: &AppConfig) -> Option<Vec<RgbaImage>> {
+    let mut output = Vec::new();
+    match colorize_gif(animation().as_slice(), &mut output, config).await {
+        Ok(()) => {}
+        Err(ColorizeError::NoAdapter) => return None,
+        Err(e) => panic!("{}", e),
+    }
+
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options.read_info(output.as_slice()).unwrap();
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        let (width, height) = (frame.width as u32, frame.height as u32);
+        frames.push(RgbaImage::from_raw(width, height, frame.buffer.to_vec()).unwrap());
+    }
+    Some(frames)
+}
+
+fn changed_static_pixels(frames: &[RgbaImage]) -> usize {
+    let (first, second) = (&frames[0], &frames[1]);
+    first
+        .enumerate_pixels()
+        .filter(|&(x, y, pixel)| (x >= 4 || y >= 4) && pixel != second.get_pixel(x, y))
+        .count()
+}
+
+#[tokio::test]
+async fn static_regions_keep_their_dither_across_frames() {
+    let mut config = test_config(DitherAlgorithm::FloydSteinberg);
+    config.temporal_coherence = true;
+
+    let Some(frames) = colorized_frames(&config).await else {
+        return;
+    };
+    assert_eq!(changed_static_pixels(&frames), 0);
+
+    // The redrawn quarter is placed back at its position on the canvas
+    let corner = image::imageops::crop_imm(&frames[0], 8, 8, 4, 4).to_image();
+    assert_eq!(frames[2], corner);
+}
+
+#[tokio::test]
+async fn error_diffusion_spreads_changes_without_it() {
+    let config = test_config(DitherAlgorithm::FloydSteinberg);
+
+    let Some(frames) = colorized_frames(&config).await else {
+        return;
+    };
+    assert!(changed_static_pixels(&frames) > 0);
+}
@@ -0,0 +1,154 @@
+mod common;
+
+use common::{test_config, test_image};
+use image::{DynamicImage, RgbImage};
+use image_colorizer::{
+    batch::{colorize_dir, colorize_dir_with_events, BatchEvent},
+    colorize::{colorize, ColorizeError},
+    dither::{CustomDither, Ditherer},
+    types::DitherAlgorithm,
+};
+use palette::Lab;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+#[derive(Debug, PartialEq)]
+enum Seen {
+    Listed(usize),
+    Started(PathBuf),
+    Progress(PathBuf),
+    Finished(PathBuf, bool),
+}
+
+#[tokio::test]
+async fn every_image_reports_its_start_progress_and_end() {
+    let config = test_config(DitherAlgorithm::None);
+    if let Err(ColorizeError::NoAdapter) = colorize(&test_image(), &config).await {
+        return;
+    }
+
+    let dir = std::env::temp_dir().join(format!("colorizer-batch-{}", std::process::id()));
+    let (input, output) = (dir.join("input"), dir.join("output"));
+    fs::create_dir_all(&input).unwrap();
+    test_image().save(input.join("a.png")).unwrap();
+    test_image().save(input.join("b.png")).unwrap();
+    // The header is enough to be listed, but the pixel data is cut off
+    let bytes = fs::read(input.join("a.png")).unwrap();
+    fs::write(input.join("c.png"), &bytes[..bytes.len() / 2]).unwrap();
+    fs::write(input.join("notes.txt"), "not an image").unwrap();
+
+    let seen = Mutex::new(Vec::new());
+    let events = |event: BatchEvent<'_>| {
+        let owned = |path: &Path| path.file_name().unwrap().into();
+        seen.lock().unwrap().push(match event {
+            BatchEvent::Listed { images } => Seen::Listed(images),
+            BatchEvent::Started { path } => Seen::Started(owned(path)),
+            BatchEvent::Progress { path, .. } => Seen::Progress(owned(path)),
+            BatchEvent::Finished { path, error } => Seen::Finished(owned(path), error.is_none()),
+        });
+    };
+    let summary = colorize_dir_with_events(&input, &output, &config, &events).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(summary.colorized, 2);
+    let seen = seen.into_inner().unwrap();
+    assert_eq!(seen[0], Seen::Listed(3));
+    for (name, succeeded) in [("a.png", true), ("b.png", true), ("c.png", false)] {
+        let position = |wanted: &Seen| seen.iter().position(|seen| seen == wanted).unwrap();
+        let started = position(&Seen::Started(name.into()));
+        let finished = position(&Seen::Finished(name.into(), succeeded));
+        assert!(started < finished, "{} finished before it started", name);
+
+        // Progress of a file only arrives while it's being colorized
+        assert!(seen
+            .iter()
+            .enumerate()
+            .filter(|(_, seen)| **seen == Seen::Progress(name.into()))
+            .all(|(index, _)| started < index && index < finished));
+    }
+}
+
+// Leaves every pixel as it is, but gives up on images wider than the test image
+#[derive(Debug)]
+struct NarrowOnly;
+
+impl Ditherer for NarrowOnly {
+    fn dither(&self, value: Lab, x: u32, _y: u32, _amount: f32) -> Lab {
+        assert!(x < 64, "Too wide");
+        value
+    }
+}
+
+#[tokio::test]
+async fn a_panic_only_skips_its_own_image() {
+    let mut config = test_config(DitherAlgorithm::None);
+    config.threads = Some(2);
+    config.custom_dither = Some(CustomDither::PerPixel(Box::new(NarrowOnly)));
+    if let Err(ColorizeError::NoAdapter) = colorize(&test_image(), &config).await {
+        return;
+    }
+
+    let dir = std::env::temp_dir().join(format!("colorizer-panic-{}", std::process::id()));
+    let (input, output) = (dir.join("input"), dir.join("output"));
+    fs::create_dir_all(&input).unwrap();
+    test_image().save(input.join("a.png")).unwrap();
+    DynamicImage::ImageRgb8(RgbImage::new(80, 8))
+        .save(input.join("b.png"))
+        .unwrap();
+    test_image().save(input.join("c.png")).unwrap();
+
+    let summary = colorize_dir(&input, &output, &config).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(summary.colorized, 2);
+    assert_eq!(
+        summary.skipped,
+        vec![(input.join("b.png"), "Panicked: Too wide".to_string())]
+    );
+}
+
+#[tokio::test]
+async fn images_share_the_thread_limit() {
+    let mut config = test_config(DitherAlgorithm::None);
+    config.threads = Some(3);
+    if let Err(ColorizeError::NoAdapter) = colorize(&test_image(), &config).await {
+        return;
+    }
+
+    let dir = std::env::temp_dir().join(format!("colorizer-threads-{}", std::process::id()));
+    let (input, output) = (dir.join("input"), dir.join("output"));
+    fs::create_dir_all(&input).unwrap();
+    for name in ["a.png", "b.png", "c.png", "d.png"] {
+        test_image().save(input.join(name)).unwrap();
+    }
+
+    // Progress is reported from the pool the CPU stages of an image run on, so the most threads
+    // in use at once is at most the images being colorized times the size of that pool
+    let (images, busiest) = (AtomicUsize::new(0), AtomicUsize::new(0));
+    let events = |event: BatchEvent<'_>| match event {
+        BatchEvent::Started { .. } => {
+            images.fetch_add(1, Ordering::Relaxed);
+        }
+        BatchEvent::Progress { .. } if rayon::current_thread_index().is_some() => {
+            let threads = images.load(Ordering::Relaxed) * rayon::current_num_threads();
+            busiest.fetch_max(threads, Ordering::Relaxed);
+        }
+        BatchEvent::Finished { .. } => {
+            images.fetch_sub(1, Ordering::Relaxed);
+        }
+        _ => {}
+    };
+    let summary = colorize_dir_with_events(&input, &output, &config, &events).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(summary.colorized, 4);
+    let busiest = busiest.into_inner();
+    assert!((1..=3).contains(&busiest), "{} threads were busy", busiest);
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use common::test_image;
+use image::{DynamicImage, Rgb, RgbImage};
+use image_colorizer::{
+    palette::{builtin, choose_best_palette, extract_kmeans},
+    utils::rgb8_to_lab,
+};
+
+#[test]
+fn picks_the_palette_closest_to_the_image() {
+    // Warm oranges and browns
+    let img = DynamicImage::ImageRgb8(RgbImage::from_fn(64, 48, |x, y| {
+        Rgb([180 + x as u8, 90 + y as u8, 30])
+    }));
+    let cool = vec![rgb8_to_lab(20, 60, 160), rgb8_to_lab(40, 140, 200)];
+    let warm = vec![rgb8_to_lab(200, 100, 30), rgb8_to_lab(240, 130, 40)];
+
+    assert_eq!(choose_best_palette(&img, &[cool.clone(), warm.clone()]), 1);
+    assert_eq!(choose_best_palette(&img, &[warm, cool]), 0);
+}
+
+#[test]
+fn the_images_own_colors_beat_a_colorscheme() {
+    let img = test_image();
+    let own = extract_kmeans(&img, 16, 20, 0);
+    let kanagawa = builtin("kanagawa").unwrap();
+
+    assert_eq!(choose_best_palette(&img, &[kanagawa, own]), 1);
+}
+
+#[test]
+fn empty_palettes_never_win() {
+    let img = test_image();
+    let nord = builtin("nord").unwrap();
+
+    assert_eq!(choose_best_palette(&img, &[Vec::new(), nord]), 1);
+    assert_eq!(choose_best_palette(&img, &[]), 0);
+}
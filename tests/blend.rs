@@ -0,0 +1,98 @@
+use image_colorizer::{types::BlendSpace, utils::blend_colors};
+
+use palette::Srgb;
+
+// A blend factor above 1 extrapolates past the colorized color, which used to be able to leave
+// the 0..=255 range before the result was quantized
+#[test]
+fn blend_colors_clamps_before_quantizing() {
+    let original = Srgb::new(0.9, 0.5, 0.1);
+    let colorized = Srgb::new(1.0, 0.0, 0.0);
+
+    for space in [BlendSpace::Srgb, BlendSpace::LinearRgb, BlendSpace::Lab] {
+        let blended = blend_colors(original, colorized, [2.0, 2.0, 2.0], space);
+
+        assert_eq!(
+            [
+                (blended.red * 255.0) as u8,
+                (blended.green * 255.0) as u8,
+                (blended.blue * 255.0) as u8,
+            ],
+            [255, 0, 0],
+            "{:?}",
+            space
+        );
+    }
+}
+
+#[test]
+fn blend_spaces_meet_at_different_greys() {
+    let black = Srgb::new(0.0, 0.0, 0.0);
+    let white = Srgb::new(1.0, 1.0, 1.0);
+
+    // Halfway in lightness, in gamma encoded values and in light respectively
+    for (space, expected) in [
+        (BlendSpace::Lab, 119),
+        (BlendSpace::Srgb, 128),
+        (BlendSpace::LinearRgb, 188),
+    ] {
+        let blended = blend_colors(black, white, [0.5; 3], space);
+
+        for channel in [blended.red, blended.green, blended.blue] {
+            assert!(
+                ((channel * 255.0).round() as i32 - expected).abs() <= 1,
+                "{:?} gives {}",
+                space,
+                channel * 255.0
+            );
+        }
+    }
+}
+
+#[test]
+fn blend_factor_endpoints_and_midpoint() {
+    let original = Srgb::new(0.2, 0.4, 0.6);
+    let colorized = Srgb::new(0.8, 0.6, 0.0);
+
+    for space in [BlendSpace::Srgb, BlendSpace::LinearRgb, BlendSpace::Lab] {
+        let channels = |factor: f32| {
+            let blended = blend_colors(original, colorized, [factor; 3], space);
+            [blended.red, blended.green, blended.blue]
+        };
+
+        assert_eq!(channels(0.0), [0.2, 0.4, 0.6], "{:?}", space);
+        for (channel, expected) in channels(1.0).into_iter().zip([0.8, 0.6, 0.0]) {
+            assert!((channel - expected).abs() < 1e-3, "{:?}", space);
+        }
+
+        // Halfway lands between the two in every space, even if not at the arithmetic mean
+        for ((channel, from), to) in channels(0.5)
+            .into_iter()
+            .zip([0.2, 0.4, 0.6])
+            .zip([0.8, 0.6, 0.0])
+        {
+            assert!(
+                channel > f32::min(from, to) && channel < f32::max(from, to),
+                "{:?} gives {}",
+                space,
+                channel
+            );
+        }
+    }
+
+    assert_eq!(
+        blend_colors(original, colorized, [0.5; 3], BlendSpace::Srgb),
+        Srgb::new(0.5, 0.5, 0.3)
+    );
+}
+
+// Out of range colorized values are clamped before mixing, so they can't drag the original past
+// the range either
+#[test]
+fn blend_colors_clamps_the_colorized_color() {
+    let original = Srgb::new(0.5, 0.5, 0.5);
+    let colorized = Srgb::new(1.5, -0.5, 0.5);
+
+    let blended = blend_colors(original, colorized, [0.5; 3], BlendSpace::Srgb);
+    assert_eq!(blended, Srgb::new(0.75, 0.25, 0.5));
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use common::test_config;
+use image::{DynamicImage, Rgb, RgbImage};
+use image_colorizer::{
+    colorize::{colorize, ColorizeError},
+    progress::Progress,
+    spatial::{box_average, gaussian_average, separable_box_average},
+    types::{DitherAlgorithm, KernelShape, SpatialImpl, SpatialKernel, WorkingSpace},
+    utils::{compute_integral_image, rgb8_to_working},
+};
+
+// Windows reaching past the edges are clamped, so every average has to divide by the pixels that
+// are actually inside the window for the border to match the interior
+#[test]
+fn border_averages_of_a_uniform_image_match_the_interior() {
+    let (width, height) = (40, 30);
+    let image = RgbImage::from_pixel(width, height, Rgb([37, 142, 220]));
+    let expected = rgb8_to_working(37, 142, 220, WorkingSpace::Lab);
+    let progress = Progress::new(None, 0);
+    let integral = compute_integral_image(&image, None, WorkingSpace::Lab, &progress);
+
+    let check = |name: String, averages: Vec<[f32; 4]>| {
+        for (i, &[l, a, b, weight]) in averages.iter().enumerate() {
+            for (channel, expected) in [l / weight, a / weight, b / weight]
+                .into_iter()
+                .zip(expected)
+            {
+                assert!(
+                    (channel - expected).abs() < 1e-3,
+                    "{} at ({}, {}) is {} instead of {}",
+                    name,
+                    i % width as usize,
+                    i / width as usize,
+                    channel,
+                    expected
+                );
+            }
+        }
+    };
+
+    for radius in [1, 5, 25, 100] {
+        for shape in [KernelShape::Square, KernelShape::Circle] {
+            check(
+                format!("{:?} box average of radius {}", shape, radius),
+                box_average(&integral, radius, shape),
+            );
+        }
+        check(
+            format!("Separable box average of radius {}", radius),
+            separable_box_average(&image, None, WorkingSpace::Lab, radius, &progress),
+        );
+    }
+
+    for sigma in [1.0, 4.0, 20.0] {
+        check(
+            format!("Gaussian average of sigma {}", sigma),
+            gaussian_average(&image, None, WorkingSpace::Lab, sigma, &progress),
+        );
+    }
+}
+
+#[tokio::test]
+async fn colorizing_a_uniform_image_has_no_rim() {
+    let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(40, 30, Rgb([37, 142, 220])));
+
+    for spatial_kernel in [
+        SpatialKernel::Box,
+        SpatialKernel::Gaussian { sigma: 5.0 },
+        SpatialKernel::Bilateral {
+            spatial_sigma: 5.0,
+            range_sigma: 10.0,
+        },
+    ] {
+        for (kernel_shape, spatial_impl) in [
+            (KernelShape::Square, SpatialImpl::Integral),
+            (KernelShape::Square, SpatialImpl::Separable),
+            (KernelShape::Circle, SpatialImpl::Integral),
+        ] {
+            let mut config = test_config(DitherAlgorithm::None);
+            config.spatial_averaging_radius = 12;
+            config.spatial_kernel = spatial_kernel;
+            config.kernel_shape = kernel_shape;
+            config.spatial_impl = spatial_impl;
+
+            let output = match colorize(&img, &config).await {
+                Ok(output) => output,
+                Err(ColorizeError::NoAdapter) => return,
+                Err(e) => panic!("{}", e),
+            };
+
+            let center = *output.get_pixel(20, 15);
+            for (x, y, pixel) in output.enumerate_pixels() {
+                assert_eq!(
+                    *pixel, center,
+                    "{:?} {:?} {:?} at ({}, {})",
+                    spatial_kernel, kernel_shape, spatial_impl, x, y
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use image_colorizer::{
+    palette::builtin,
+    types::{AppConfig, BlendFactor, ConfigError, DitherAlgorithm},
+};
+
+#[test]
+fn builder_defaults_are_valid() {
+    let config = AppConfig::builder().build().unwrap();
+
+    assert_eq!(config.blend_factor, BlendFactor::Uniform(1.0));
+    assert_eq!(config.dither_amount, 0.0);
+    assert_eq!(config.spatial_averaging_radius, 1);
+    assert!(!config.colors.is_empty());
+}
+
+#[test]
+fn builder_applies_every_setter() {
+    let colors = builtin("nord").unwrap();
+    let config = AppConfig::builder()
+        .colors(colors.clone())
+        .blend_factor([0.5, 0.75, 1.0])
+        .dither_algorithm(DitherAlgorithm::FloydSteinberg)
+        .spatial_averaging_radius(6)
+        .build()
+        .unwrap();
+
+    assert_eq!(config.colors, colors);
+    assert_eq!(
+        config.blend_factor,
+        BlendFactor::PerChannel([0.5, 0.75, 1.0])
+    );
+    assert_eq!(config.dither_algorithm, DitherAlgorithm::FloydSteinberg);
+    assert_eq!(config.spatial_averaging_radius, 6);
+}
+
+#[test]
+fn builder_validates() {
+    assert_eq!(
+        AppConfig::builder().colors(Vec::new()).build().unwrap_err(),
+        ConfigError::EmptyColors
+    );
+    assert!(matches!(
+        AppConfig::builder().blend_factor(2.0).build(),
+        Err(ConfigError::OutOfRange {
+            field: "blend_factor",
+            ..
+        })
+    ));
+}
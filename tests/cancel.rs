@@ -0,0 +1,33 @@
+mod common;
+
+use common::{test_config, test_image};
+use image_colorizer::{
+    colorize::{colorize, colorize_with_cancel, ColorizeError},
+    types::DitherAlgorithm,
+};
+
+use std::sync::atomic::AtomicBool;
+
+#[tokio::test]
+async fn set_flag_cancels() {
+    let config = test_config(DitherAlgorithm::Random);
+
+    match colorize_with_cancel(&test_image(), &config, &AtomicBool::new(true)).await {
+        Err(ColorizeError::Cancelled) | Err(ColorizeError::NoAdapter) => {}
+        result => panic!("Expected the run to be cancelled, got {:?}", result.err()),
+    }
+}
+
+#[tokio::test]
+async fn unset_flag_changes_nothing() {
+    let config = test_config(DitherAlgorithm::Random);
+    let img = test_image();
+
+    let colorized = match colorize_with_cancel(&img, &config, &AtomicBool::new(false)).await {
+        Ok(colorized) => colorized,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+
+    assert_eq!(colorized, colorize(&img, &config).await.unwrap());
+}
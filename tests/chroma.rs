@@ -0,0 +1,58 @@
+mod common;
+
+use common::{test_config, test_image};
+use image_colorizer::{
+    colorize::{colorize, ColorizeError},
+    types::{BlendFactor, DitherAlgorithm},
+};
+
+use image::RgbImage;
+use palette::{FromColor, Lab, Srgb};
+
+async fn colorize_with_chroma_scale(chroma_scale: f32) -> Option<RgbImage> {
+    let mut config = test_config(DitherAlgorithm::None);
+    config.blend_factor = BlendFactor::Uniform(1.0);
+    config.chroma_scale = chroma_scale;
+
+    match colorize(&test_image(), &config).await {
+        Ok(colorized) => Some(colorized),
+        Err(ColorizeError::NoAdapter) => None,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+fn mean_chroma(img: &RgbImage) -> f32 {
+    let total: f32 = img
+        .pixels()
+        .map(|pixel| {
+            let [r, g, b] = pixel.0;
+            let lab = Lab::from_color(Srgb::new(r, g, b).into_format::<f32>().into_linear());
+            lab.a.hypot(lab.b)
+        })
+        .sum();
+    total / (img.width() * img.height()) as f32
+}
+
+#[tokio::test]
+async fn zero_chroma_scale_gives_greys() {
+    let Some(colorized) = colorize_with_chroma_scale(0.0).await else {
+        return;
+    };
+
+    for pixel in colorized.pixels() {
+        let [r, g, b] = pixel.0.map(i32::from);
+        assert!((r - g).abs() <= 2 && (g - b).abs() <= 2, "{:?}", pixel);
+    }
+}
+
+#[tokio::test]
+async fn larger_chroma_scale_saturates_more() {
+    let (Some(unscaled), Some(boosted)) = (
+        colorize_with_chroma_scale(1.0).await,
+        colorize_with_chroma_scale(1.5).await,
+    ) else {
+        return;
+    };
+
+    assert!(mean_chroma(&boosted) > mean_chroma(&unscaled) * 1.2);
+}
@@ -0,0 +1,62 @@
+use image_colorizer::{
+    types::{DistanceMetric, WorkingSpace},
+    utils::find_closest_color,
+};
+
+const BLACK: [f32; 3] = [0.0, 0.0, 0.0];
+const WHITE: [f32; 3] = [100.0, 0.0, 0.0];
+const RED: [f32; 3] = [53.2, 80.1, 67.2];
+const BLUE: [f32; 3] = [32.3, 79.2, -107.9];
+const PALETTE: [[f32; 3]; 4] = [BLACK, WHITE, RED, BLUE];
+
+const METRICS: [DistanceMetric; 3] = [
+    DistanceMetric::Cie76,
+    DistanceMetric::Cie94,
+    DistanceMetric::Ciede2000,
+];
+
+fn closest(color: [f32; 3], metric: DistanceMetric) -> [f32; 3] {
+    find_closest_color(&color, &PALETTE, metric, WorkingSpace::Lab, 1.0)
+}
+
+#[test]
+fn palette_entries_map_to_themselves() {
+    for metric in METRICS {
+        for color in PALETTE {
+            assert_eq!(closest(color, metric), color, "{:?}", metric);
+        }
+    }
+}
+
+#[test]
+fn hand_picked_colors_map_to_the_expected_entry() {
+    for metric in METRICS {
+        for (color, expected) in [
+            ([10.0, 2.0, -2.0], BLACK),
+            ([92.0, -3.0, 4.0], WHITE),
+            ([45.0, 60.0, 50.0], RED),
+            ([40.0, 50.0, -80.0], BLUE),
+        ] {
+            assert_eq!(closest(color, metric), expected, "{:?} {:?}", metric, color);
+        }
+    }
+}
+
+#[test]
+fn luminance_weight_trades_lightness_for_hue() {
+    // Dark red is closer to black in lightness but closer to red in hue
+    let dark_red = [20.0, 50.0, 40.0];
+    let palette = [BLACK, RED];
+    let closest = |weight| {
+        find_closest_color(
+            &dark_red,
+            &palette,
+            DistanceMetric::Cie76,
+            WorkingSpace::Lab,
+            weight,
+        )
+    };
+
+    assert_eq!(closest(1.0), RED);
+    assert_eq!(closest(10.0), BLACK);
+}
@@ -0,0 +1,33 @@
+// Every test binary compiles its own copy of this module and only uses some of the helpers
+#![allow(dead_code)]
+
+use image_colorizer::{
+    palette::builtin,
+    types::{AppConfig, BlendFactor, DitherAlgorithm},
+};
+
+use image::{DynamicImage, Rgb, RgbImage};
+
+pub fn test_config(dither_algorithm: DitherAlgorithm) -> AppConfig {
+    AppConfig {
+        blend_factor: BlendFactor::Uniform(0.9),
+        colors: builtin("kanagawa").unwrap(),
+        dither_amount: 0.1,
+        dither_algorithm,
+        spatial_averaging_radius: 4,
+        ..AppConfig::default()
+    }
+}
+
+pub fn test_image() -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_fn(64, 48, |x, y| {
+        Rgb([(x * 4) as u8, (y * 5) as u8, ((x + y) * 2) as u8])
+    }))
+}
+
+// Not a multiple of the 16x16 workgroups, so the last column of workgroups only partly covers it
+pub fn odd_test_image() -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_fn(18, 10, |x, y| {
+        Rgb([(x * 14) as u8, (y * 25) as u8, ((x + y) * 9) as u8])
+    }))
+}
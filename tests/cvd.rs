@@ -0,0 +1,102 @@
+mod common;
+
+use common::test_config;
+use image_colorizer::{
+    colorize::{colorize, ColorizeError},
+    types::{BlendFactor, CvdType, DitherAlgorithm},
+};
+
+use image::{DynamicImage, Rgb, RgbImage};
+
+#[tokio::test]
+async fn simulation_leaves_neutral_colors_neutral() {
+    let img = DynamicImage::ImageRgb8(RgbImage::from_fn(64, 4, |x, _| Rgb([x as u8 * 4; 3])));
+
+    for cvd in [
+        CvdType::Protanopia,
+        CvdType::Deuteranopia,
+        CvdType::Tritanopia,
+    ] {
+        let mut config = test_config(DitherAlgorithm::None);
+        config.blend_factor = BlendFactor::Uniform(0.0);
+        config.simulate_cvd = Some(cvd);
+
+        let simulated = match colorize(&img, &config).await {
+            Ok(simulated) => simulated,
+            Err(ColorizeError::NoAdapter) => return,
+            Err(e) => panic!("{}", e),
+        };
+
+        for (x, _, pixel) in simulated.enumerate_pixels() {
+            let expected = x as i32 * 4;
+            assert!(
+                pixel.0.iter().all(|&c| (c as i32 - expected).abs() <= 2),
+                "{:?} turned {:?} into {:?}",
+                cvd,
+                expected,
+                pixel
+            );
+        }
+    }
+}
+
+/// Distance between the left and right halves of a two color image after colorizing with
+/// `simulate_cvd`
+async fn simulated_distance(
+    left: Rgb<u8>,
+    right: Rgb<u8>,
+    cvd: Option<CvdType>,
+) -> Result<f32, ColorizeError> {
+    let img = DynamicImage::ImageRgb8(RgbImage::from_fn(
+        64,
+        4,
+        |x, _| {
+            if x < 32 {
+                left
+            } else {
+                right
+            }
+        },
+    ));
+    let mut config = test_config(DitherAlgorithm::None);
+    config.blend_factor = BlendFactor::Uniform(0.0);
+    config.simulate_cvd = cvd;
+
+    let simulated = colorize(&img, &config).await?;
+    let (left, right) = (simulated.get_pixel(0, 0), simulated.get_pixel(63, 0));
+    Ok(left
+        .0
+        .iter()
+        .zip(right.0)
+        .map(|(&l, r)| (l as f32 - r as f32).powi(2))
+        .sum::<f32>()
+        .sqrt())
+}
+
+#[tokio::test]
+async fn simulation_confuses_the_missing_cone_colors() {
+    // Each pair has roughly matching CIE lightness, so only hue tells them apart
+    for (left, right, cvd) in [
+        (Rgb([255, 0, 0]), Rgb([0, 148, 0]), CvdType::Protanopia),
+        (Rgb([0, 0, 255]), Rgb([80, 80, 0]), CvdType::Tritanopia),
+    ] {
+        let (unsimulated, simulated) = match (
+            simulated_distance(left, right, None).await,
+            simulated_distance(left, right, Some(cvd)).await,
+        ) {
+            (Ok(unsimulated), Ok(simulated)) => (unsimulated, simulated),
+            (Err(ColorizeError::NoAdapter), _) | (_, Err(ColorizeError::NoAdapter)) => return,
+            (Err(e), _) | (_, Err(e)) => panic!("{}", e),
+        };
+
+        assert!(
+            simulated * 2.0 < unsimulated,
+            "{:?} kept {:?} and {:?} {} apart, {} without simulation",
+            cvd,
+            left,
+            right,
+            simulated,
+            unsimulated
+        );
+    }
+}
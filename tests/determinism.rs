@@ -0,0 +1,153 @@
+mod common;
+
+use common::{odd_test_image, test_config, test_image};
+use image_colorizer::{
+    colorize::{colorize, ColorizeError},
+    dither::error_diffusion,
+    types::{DitherAlgorithm, SpatialKernel},
+};
+
+#[test]
+fn error_diffusion_is_deterministic() {
+    let config = test_config(DitherAlgorithm::FloydSteinberg);
+    let img = test_image();
+
+    assert_eq!(
+        error_diffusion(&img, &config).into_raw(),
+        error_diffusion(&img, &config).into_raw()
+    );
+}
+
+#[tokio::test]
+async fn colorize_is_deterministic() {
+    let config = test_config(DitherAlgorithm::Random);
+
+    for img in [test_image(), odd_test_image()] {
+        let first = match colorize(&img, &config).await {
+            Ok(first) => first,
+            // Nothing to compare on machines without a GPU
+            Err(ColorizeError::NoAdapter) => return,
+            Err(e) => panic!("{}", e),
+        };
+        let second = colorize(&img, &config).await.unwrap();
+
+        assert_eq!(first.into_raw(), second.into_raw());
+    }
+}
+
+#[tokio::test]
+async fn tiles_are_seamless() {
+    let img = test_image();
+
+    for (dither_algorithm, spatial_kernel) in [
+        (DitherAlgorithm::Random, SpatialKernel::Box),
+        (
+            DitherAlgorithm::Ordered { size: 4 },
+            SpatialKernel::Gaussian { sigma: 2.0 },
+        ),
+        (
+            DitherAlgorithm::FloydSteinberg,
+            SpatialKernel::Bilateral {
+                spatial_sigma: 3.0,
+                range_sigma: 10.0,
+            },
+        ),
+    ] {
+        let mut config = test_config(dither_algorithm);
+        config.spatial_kernel = spatial_kernel;
+
+        let whole = match colorize(&img, &config).await {
+            Ok(whole) => whole,
+            Err(ColorizeError::NoAdapter) => return,
+            Err(e) => panic!("{}", e),
+        };
+
+        config.tile_height = Some(7);
+        let tiled = colorize(&img, &config).await.unwrap();
+
+        assert_eq!(whole.into_raw(), tiled.into_raw());
+    }
+}
+
+#[tokio::test]
+async fn tiles_are_seamless_on_odd_widths() {
+    let img = odd_test_image();
+
+    for dither_algorithm in [
+        DitherAlgorithm::Random,
+        DitherAlgorithm::Ordered { size: 4 },
+        DitherAlgorithm::FloydSteinberg,
+    ] {
+        let mut config = test_config(dither_algorithm);
+
+        let whole = match colorize(&img, &config).await {
+            Ok(whole) => whole,
+            Err(ColorizeError::NoAdapter) => return,
+            Err(e) => panic!("{}", e),
+        };
+
+        config.tile_height = Some(3);
+        let tiled = colorize(&img, &config).await.unwrap();
+
+        assert_eq!(whole.into_raw(), tiled.into_raw(), "{}", dither_algorithm);
+    }
+}
+
+#[tokio::test]
+async fn serial_matches_parallel() {
+    let img = test_image();
+
+    for spatial_kernel in [SpatialKernel::Box, SpatialKernel::Gaussian { sigma: 2.0 }] {
+        let mut config = test_config(DitherAlgorithm::Random);
+        config.spatial_kernel = spatial_kernel;
+
+        let parallel = match colorize(&img, &config).await {
+            Ok(parallel) => parallel,
+            Err(ColorizeError::NoAdapter) => return,
+            Err(e) => panic!("{}", e),
+        };
+
+        config.threads = Some(1);
+        let serial = colorize(&img, &config).await.unwrap();
+
+        assert_eq!(parallel.into_raw(), serial.into_raw());
+    }
+}
+
+#[tokio::test]
+async fn dither_seed_changes_the_noise() {
+    let mut config = test_config(DitherAlgorithm::Random);
+    config.dither_amount = 1.0;
+    let img = test_image();
+
+    let first = match colorize(&img, &config).await {
+        Ok(first) => first,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+    config.dither_seed = 1 << 32;
+    let reseeded = colorize(&img, &config).await.unwrap();
+
+    assert_ne!(first.into_raw(), reseeded.into_raw());
+}
+
+#[tokio::test]
+async fn zero_dither_amount_matches_no_dithering() {
+    let img = test_image();
+    let undithered = match colorize(&img, &test_config(DitherAlgorithm::None)).await {
+        Ok(undithered) => undithered,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+
+    for dither_algorithm in [
+        DitherAlgorithm::Random,
+        DitherAlgorithm::Ordered { size: 4 },
+    ] {
+        let mut config = test_config(dither_algorithm);
+        config.dither_amount = 0.0;
+
+        let colorized = colorize(&img, &config).await.unwrap();
+        assert_eq!(colorized.as_raw(), undithered.as_raw());
+    }
+}
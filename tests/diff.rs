@@ -0,0 +1,62 @@
+mod common;
+
+use common::{test_config, test_image};
+use image_colorizer::{
+    colorize::{colorize, colorize_with_diff, ColorizeError},
+    types::{BlendFactor, DistanceMetric, DitherAlgorithm},
+    utils::{color_distance, rgb8_to_lab},
+};
+
+#[tokio::test]
+async fn diff_is_the_scaled_ciede2000_distance() {
+    let config = test_config(DitherAlgorithm::Random);
+    let img = test_image();
+
+    let (output, diff) = match colorize_with_diff(&img, &config).await {
+        Ok(result) => result,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+    assert_eq!(output, colorize(&img, &config).await.unwrap());
+    assert_eq!(diff.dimensions(), output.dimensions());
+
+    let original = img.to_rgb8();
+    let distances: Vec<f32> = original
+        .pixels()
+        .zip(output.pixels())
+        .map(|(a, b)| {
+            color_distance(
+                &rgb8_to_lab(a[0], a[1], a[2]),
+                &rgb8_to_lab(b[0], b[1], b[2]),
+                DistanceMetric::Ciede2000,
+                1.0,
+            )
+        })
+        .collect();
+    let max = distances.iter().copied().fold(0.0, f32::max);
+    assert!(max > 0.0);
+
+    // The most changed pixel is white and every other one is in proportion
+    assert_eq!(diff.pixels().map(|p| p[0]).max(), Some(255));
+    for (distance, level) in distances.iter().zip(diff.pixels()) {
+        let expected = distance / max * 255.0;
+        assert!((level[0] as f32 - expected).abs() <= 1.0);
+    }
+}
+
+#[tokio::test]
+async fn unchanged_images_give_a_black_diff() {
+    let mut config = test_config(DitherAlgorithm::None);
+    config.blend_factor = BlendFactor::Uniform(0.0);
+    config.transfer_luminance = false;
+    let img = test_image();
+
+    let (output, diff) = match colorize_with_diff(&img, &config).await {
+        Ok(result) => result,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+
+    assert_eq!(output, img.to_rgb8());
+    assert!(diff.pixels().all(|p| p[0] == 0));
+}
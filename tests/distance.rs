@@ -0,0 +1,54 @@
+mod common;
+
+use common::{test_config, test_image};
+use image_colorizer::{
+    distance::ColorDistance,
+    dither::error_diffusion,
+    types::{DistanceMetric, DitherAlgorithm},
+};
+
+use palette::Lab;
+
+// Every color is as close as any other, so the first one always wins
+#[derive(Debug)]
+struct Indifferent;
+
+impl ColorDistance for Indifferent {
+    fn distance(&self, _: &Lab, _: &Lab) -> f32 {
+        0.0
+    }
+}
+
+#[test]
+fn builtin_metrics_match_as_custom_distances() {
+    for metric in [
+        DistanceMetric::Cie76,
+        DistanceMetric::Cie94,
+        DistanceMetric::Ciede2000,
+    ] {
+        let mut config = test_config(DitherAlgorithm::FloydSteinberg);
+        config.distance_metric = metric;
+        let builtin = error_diffusion(&test_image(), &config);
+
+        config.custom_distance = Some(Box::new(metric));
+        assert_eq!(
+            error_diffusion(&test_image(), &config),
+            builtin,
+            "{:?}",
+            metric
+        );
+    }
+}
+
+#[test]
+fn custom_distance_picks_the_colors() {
+    for dither_algorithm in [DitherAlgorithm::None, DitherAlgorithm::Random] {
+        let mut config = test_config(dither_algorithm);
+        config.colors.truncate(1);
+        let single_color = error_diffusion(&test_image(), &config);
+
+        let mut config = test_config(dither_algorithm);
+        config.custom_distance = Some(Box::new(Indifferent));
+        assert_eq!(error_diffusion(&test_image(), &config), single_color);
+    }
+}
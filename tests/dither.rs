@@ -0,0 +1,118 @@
+mod common;
+
+use common::{test_config, test_image};
+use image::{DynamicImage, Rgb, RgbImage};
+use image_colorizer::{
+    colorize::{colorize_stages, ColorizeError},
+    dither::{error_diffusion, CustomDither, FloydSteinberg, Ordered},
+    types::{BlendFactor, DitherAlgorithm},
+    utils::{bayer_threshold, pixel_noise},
+};
+use palette::Lab;
+
+#[test]
+fn bayer_matrices_use_every_threshold_once() {
+    for size in [2, 4, 8] {
+        let mut thresholds: Vec<u32> = (0..size * size)
+            .map(|i| (bayer_threshold(i % size, i / size, size) * (size * size) as f32) as u32)
+            .collect();
+        thresholds.sort();
+
+        assert_eq!(thresholds, (0..size * size).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn pixel_noise_depends_on_the_seed() {
+    let noise = |seed| (0..64).map(|x| pixel_noise(x, 3, seed)).collect::<Vec<_>>();
+
+    assert!(noise(0).iter().all(|n| (0.0..1.0).contains(n)));
+    assert_eq!(noise(7), noise(7));
+    assert_ne!(noise(0), noise(1 << 32));
+}
+
+#[test]
+fn builtin_ditherers_match_their_algorithms() {
+    for (dither_algorithm, custom_dither) in [
+        (
+            DitherAlgorithm::Ordered { size: 4 },
+            CustomDither::PerPixel(Box::new(Ordered { size: 4 })),
+        ),
+        (
+            DitherAlgorithm::FloydSteinberg,
+            CustomDither::Diffusion(Box::new(FloydSteinberg)),
+        ),
+    ] {
+        let builtin = error_diffusion(&test_image(), &test_config(dither_algorithm));
+
+        let mut config = test_config(DitherAlgorithm::None);
+        config.custom_dither = Some(custom_dither);
+        assert_eq!(error_diffusion(&test_image(), &config), builtin);
+    }
+}
+
+#[tokio::test]
+async fn custom_dithering_runs_the_first_pass_on_the_cpu() {
+    let mut config = test_config(DitherAlgorithm::Random);
+    config.custom_dither = Some(CustomDither::PerPixel(Box::new(Ordered { size: 8 })));
+
+    let stages = match colorize_stages(&test_image(), &config).await {
+        Ok(stages) => stages,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+
+    assert_eq!(stages.first_pass, error_diffusion(&test_image(), &config));
+}
+
+#[tokio::test]
+async fn pixels_past_the_last_full_workgroup_dither_like_the_rest() {
+    let pattern = |x: u32, y: u32| Rgb([(x * 7) as u8, (y * 23) as u8, ((x + y) * 5) as u8]);
+    let wide = DynamicImage::ImageRgb8(RgbImage::from_fn(32, 10, pattern));
+    let narrow = DynamicImage::ImageRgb8(RgbImage::from_fn(18, 10, pattern));
+
+    for dither_algorithm in [
+        DitherAlgorithm::Ordered { size: 8 },
+        DitherAlgorithm::Random,
+    ] {
+        let mut config = test_config(dither_algorithm);
+        config.dither_amount = 0.5;
+
+        let wide = match colorize_stages(&wide, &config).await {
+            Ok(stages) => stages.first_pass,
+            Err(ColorizeError::NoAdapter) => return,
+            Err(e) => panic!("{}", e),
+        };
+        let narrow = colorize_stages(&narrow, &config).await.unwrap().first_pass;
+        assert_eq!(
+            narrow,
+            image::imageops::crop_imm(&wide, 0, 0, 18, 10).to_image(),
+            "{}",
+            dither_algorithm
+        );
+    }
+}
+
+#[test]
+fn palette_lightness_error_is_diffused() {
+    // L 50 is sRGB 119, halfway between black and white
+    let grey = DynamicImage::ImageRgb8(RgbImage::from_pixel(64, 48, Rgb([119; 3])));
+
+    for dither_algorithm in [DitherAlgorithm::FloydSteinberg, DitherAlgorithm::Atkinson] {
+        let mut config = test_config(dither_algorithm);
+        config.colors = vec![Lab::new(0.0, 0.0, 0.0), Lab::new(100.0, 0.0, 0.0)];
+        config.blend_factor = BlendFactor::Uniform(1.0);
+        config.transfer_luminance = false;
+
+        let output = error_diffusion(&grey, &config);
+        let white = output.pixels().filter(|pixel| pixel[0] > 128).count();
+        let total = output.pixels().count();
+        assert!(
+            white > total * 2 / 5 && white < total * 3 / 5,
+            "{} of {} pixels are white with {}",
+            white,
+            total,
+            dither_algorithm
+        );
+    }
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use common::{test_config, test_image};
+use image::{GrayImage, Luma};
+use image_colorizer::{
+    colorize::{colorize, colorize_with_dither_texture, ColorizeError},
+    types::{DistanceMetric, DitherAlgorithm},
+    utils::texture_threshold,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+// Not actual blue noise, but any texture has to tile the same way
+fn noise_texture(width: u32, height: u32) -> GrayImage {
+    let mut rng = StdRng::seed_from_u64(81);
+    GrayImage::from_fn(width, height, |_, _| Luma([rng.gen()]))
+}
+
+fn tiled(texture: &GrayImage, width: u32, height: u32) -> GrayImage {
+    GrayImage::from_fn(width, height, |x, y| {
+        *texture.get_pixel(x % texture.width(), y % texture.height())
+    })
+}
+
+#[test]
+fn thresholds_wrap_around_the_texture() {
+    let texture = noise_texture(5, 3);
+
+    for (x, y) in [(0, 0), (4, 2), (5, 3), (12, 7), (63, 47)] {
+        assert_eq!(
+            texture_threshold(&texture, x, y),
+            texture_threshold(&texture, x % 5, y % 3)
+        );
+    }
+    assert_eq!(texture_threshold(&GrayImage::new(1, 1), 9, 9), 0.5 / 256.0);
+}
+
+#[tokio::test]
+async fn small_textures_tile_over_the_image() {
+    let config = test_config(DitherAlgorithm::Random);
+    let img = test_image();
+    let texture = noise_texture(5, 3);
+
+    let small = match colorize_with_dither_texture(&img, Some(&texture), &config).await {
+        Ok(small) => small,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+    let full = colorize_with_dither_texture(&img, Some(&tiled(&texture, 64, 48)), &config)
+        .await
+        .unwrap();
+
+    assert_eq!(small, full);
+    assert_ne!(small, colorize(&img, &config).await.unwrap());
+}
+
+#[tokio::test]
+async fn texture_replaces_the_dithering_algorithm() {
+    let img = test_image();
+    let texture = noise_texture(16, 16);
+
+    let random = match colorize_with_dither_texture(
+        &img,
+        Some(&texture),
+        &test_config(DitherAlgorithm::Random),
+    )
+    .await
+    {
+        Ok(random) => random,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+
+    for dither_algorithm in [
+        DitherAlgorithm::None,
+        DitherAlgorithm::Ordered { size: 4 },
+        DitherAlgorithm::FloydSteinberg,
+    ] {
+        let config = test_config(dither_algorithm);
+        let output = colorize_with_dither_texture(&img, Some(&texture), &config)
+            .await
+            .unwrap();
+        assert_eq!(output, random, "{:?}", dither_algorithm);
+    }
+}
+
+#[tokio::test]
+async fn cpu_first_pass_dithers_like_the_shader() {
+    let mut config = test_config(DitherAlgorithm::Random);
+    config.dither_amount = 0.5;
+    let img = test_image();
+    let texture = noise_texture(7, 7);
+
+    let gpu = match colorize_with_dither_texture(&img, Some(&texture), &config).await {
+        Ok(gpu) => gpu,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+
+    // A custom distance runs the first pass on the CPU
+    config.custom_distance = Some(Box::new(DistanceMetric::Cie76));
+    let cpu = colorize_with_dither_texture(&img, Some(&texture), &config)
+        .await
+        .unwrap();
+
+    let difference: u64 = gpu
+        .as_raw()
+        .iter()
+        .zip(cpu.as_raw())
+        .map(|(&a, &b)| (a as i64 - b as i64).unsigned_abs())
+        .sum();
+    assert!(
+        difference as f64 / (gpu.as_raw().len() as f64) < 0.5,
+        "{}",
+        difference
+    );
+}
+
+#[tokio::test]
+async fn empty_textures_are_rejected() {
+    let config = test_config(DitherAlgorithm::Random);
+
+    assert!(matches!(
+        colorize_with_dither_texture(&test_image(), Some(&GrayImage::new(0, 4)), &config).await,
+        Err(ColorizeError::EmptyDitherTexture)
+    ));
+}
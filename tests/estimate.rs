@@ -0,0 +1,91 @@
+mod common;
+
+use common::{test_config, test_image};
+use image_colorizer::{
+    colorize::{estimate_cost, estimate_cost_for_size},
+    types::{DitherAlgorithm, SpatialImpl, SpatialKernel},
+};
+use palette::Lab;
+
+#[test]
+fn memory_adds_up_the_buffers_of_the_pipeline() {
+    let config = test_config(DitherAlgorithm::None);
+    let estimate = estimate_cost(&test_image(), &config);
+
+    assert_eq!(estimate, estimate_cost_for_size(64, 48, &config));
+    // Four f64 sums per entry, with a row and column of zeros in front
+    assert_eq!(estimate.integral_image_bytes, 65 * 49 * 32);
+    assert_eq!(estimate.output_bytes, 64 * 48 * 3);
+    assert_eq!(
+        estimate.peak_memory_bytes,
+        estimate.first_pass_bytes + estimate.integral_image_bytes + estimate.output_bytes
+    );
+}
+
+#[test]
+fn only_the_summed_area_table_path_counts_it() {
+    let mut config = test_config(DitherAlgorithm::None);
+    config.spatial_impl = SpatialImpl::Separable;
+    assert_eq!(
+        estimate_cost_for_size(64, 48, &config).integral_image_bytes,
+        0
+    );
+
+    config.spatial_impl = SpatialImpl::Integral;
+    config.spatial_kernel = SpatialKernel::Gaussian { sigma: 2.0 };
+    assert_eq!(
+        estimate_cost_for_size(64, 48, &config).integral_image_bytes,
+        0
+    );
+}
+
+#[test]
+fn tiling_bounds_everything_but_the_output() {
+    let mut config = test_config(DitherAlgorithm::None);
+    let whole = estimate_cost_for_size(4000, 3000, &config);
+    config.tile_height = Some(100);
+    let tiled = estimate_cost_for_size(4000, 3000, &config);
+
+    assert!(tiled.first_pass_bytes * 20 < whole.first_pass_bytes);
+    assert!(tiled.integral_image_bytes * 20 < whole.integral_image_bytes);
+    assert_eq!(tiled.output_bytes, whole.output_bytes);
+}
+
+#[test]
+fn time_grows_with_the_pixels_and_the_palette() {
+    let mut config = test_config(DitherAlgorithm::None);
+    config.threads = Some(1);
+    config.colors.truncate(4);
+    let small = estimate_cost_for_size(1000, 1000, &config).duration;
+    assert!(estimate_cost_for_size(2000, 2000, &config).duration > small * 3);
+
+    config.colors = (0..64).map(|i| Lab::new(i as f32, 0.0, 0.0)).collect();
+    assert!(estimate_cost_for_size(1000, 1000, &config).duration > small);
+
+    // More threads share the CPU side of the work
+    config.threads = Some(4);
+    let shared = estimate_cost_for_size(1000, 1000, &config).duration;
+    config.threads = Some(1);
+    assert!(shared < estimate_cost_for_size(1000, 1000, &config).duration);
+}
+
+#[test]
+fn threads_only_shorten_the_cpu_side() {
+    // Extra time a bigger palette adds with `threads` threads
+    let palette_time = |dither_algorithm, threads| {
+        let mut config = test_config(dither_algorithm);
+        config.threads = Some(threads);
+        config.colors.truncate(4);
+        let small = estimate_cost_for_size(1000, 1000, &config).duration;
+        config.colors = (0..64).map(|i| Lab::new(i as f32, 0.0, 0.0)).collect();
+        (estimate_cost_for_size(1000, 1000, &config).duration - small).as_secs_f64()
+    };
+
+    // The GPU matches the palette in the same time whatever the thread count
+    let gpu = palette_time(DitherAlgorithm::None, 1);
+    assert!((palette_time(DitherAlgorithm::None, 4) - gpu).abs() < 1e-6);
+
+    // Error diffusion matches it on the CPU, which threads split
+    let cpu = palette_time(DitherAlgorithm::FloydSteinberg, 1);
+    assert!((palette_time(DitherAlgorithm::FloydSteinberg, 4) * 4.0 - cpu).abs() < 1e-6);
+}
@@ -0,0 +1,151 @@
+mod common;
+
+use common::{test_config, test_image};
+use image_colorizer::{
+    batch::process_file,
+    colorize::ColorizeError,
+    exif::{apply_orientation, read_orientation},
+    types::DitherAlgorithm,
+};
+
+use std::{fs, io::Cursor};
+
+use image::{DynamicImage, ImageOutputFormat, Rgb, RgbImage};
+
+// Minimal TIFF structure with a single IFD holding only the orientation tag
+fn tiff(orientation: u16, little_endian: bool) -> Vec<u8> {
+    let u16_bytes = |value: u16| {
+        if little_endian {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        }
+    };
+    let u32_bytes = |value: u32| {
+        if little_endian {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        }
+    };
+
+    let mut tiff = if little_endian {
+        b"II".to_vec()
+    } else {
+        b"MM".to_vec()
+    };
+    tiff.extend(u16_bytes(42));
+    tiff.extend(u32_bytes(8));
+    tiff.extend(u16_bytes(1));
+    tiff.extend(u16_bytes(0x0112));
+    tiff.extend(u16_bytes(3));
+    tiff.extend(u32_bytes(1));
+    tiff.extend(u16_bytes(orientation));
+    tiff.extend([0, 0]);
+    tiff.extend(u32_bytes(0));
+    tiff
+}
+
+fn jpeg_with_orientation(img: &DynamicImage, orientation: u16, little_endian: bool) -> Vec<u8> {
+    let mut jpeg = Vec::new();
+    img.write_to(&mut Cursor::new(&mut jpeg), ImageOutputFormat::Jpeg(90))
+        .unwrap();
+
+    let mut segment = b"Exif\0\0".to_vec();
+    segment.extend(tiff(orientation, little_endian));
+    let mut app1 = vec![0xFF, 0xE1];
+    app1.extend((segment.len() as u16 + 2).to_be_bytes());
+    app1.extend(segment);
+
+    // Right after the start of image marker
+    jpeg.splice(2..2, app1);
+    jpeg
+}
+
+// Where the pixel stored at (x, y) of a width x height image ends up once it's upright, straight
+// from the row 0 / column 0 table of the EXIF spec
+fn upright_position(orientation: u8, x: u32, y: u32, width: u32, height: u32) -> (u32, u32) {
+    match orientation {
+        1 => (x, y),
+        2 => (width - 1 - x, y),
+        3 => (width - 1 - x, height - 1 - y),
+        4 => (x, height - 1 - y),
+        5 => (y, x),
+        6 => (height - 1 - y, x),
+        7 => (height - 1 - y, width - 1 - x),
+        8 => (y, width - 1 - x),
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn orientation_is_read_in_both_byte_orders() {
+    let img = DynamicImage::ImageRgb8(RgbImage::new(8, 8));
+
+    for orientation in 1..=8 {
+        for little_endian in [true, false] {
+            let jpeg = jpeg_with_orientation(&img, orientation, little_endian);
+            assert_eq!(read_orientation(&jpeg), Some(orientation as u8));
+        }
+    }
+}
+
+#[test]
+fn missing_or_invalid_orientation_is_none() {
+    let img = DynamicImage::ImageRgb8(RgbImage::new(8, 8));
+
+    let mut png = Vec::new();
+    img.write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+        .unwrap();
+    assert_eq!(read_orientation(&png), None);
+
+    let mut jpeg = Vec::new();
+    img.write_to(&mut Cursor::new(&mut jpeg), ImageOutputFormat::Jpeg(90))
+        .unwrap();
+    assert_eq!(read_orientation(&jpeg), None);
+
+    assert_eq!(
+        read_orientation(&jpeg_with_orientation(&img, 9, true)),
+        None
+    );
+    assert_eq!(read_orientation(&[]), None);
+}
+
+#[test]
+fn every_orientation_is_turned_upright() {
+    let (width, height) = (3, 2);
+    let stored = RgbImage::from_fn(width, height, |x, y| Rgb([x as u8, y as u8, 0]));
+
+    for orientation in 1..=8 {
+        let upright =
+            apply_orientation(DynamicImage::ImageRgb8(stored.clone()), orientation).to_rgb8();
+
+        for (x, y, pixel) in stored.enumerate_pixels() {
+            let (ux, uy) = upright_position(orientation, x, y, width, height);
+            assert_eq!(
+                upright.get_pixel(ux, uy),
+                pixel,
+                "orientation {}",
+                orientation
+            );
+        }
+    }
+}
+
+#[test]
+fn process_file_turns_photos_upright() {
+    let dir = std::env::temp_dir().join(format!("colorizer-exif-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("sideways.jpg");
+    let output = dir.join("upright.png");
+    fs::write(&input, jpeg_with_orientation(&test_image(), 6, false)).unwrap();
+
+    match process_file(&input, &output, &test_config(DitherAlgorithm::None)) {
+        Ok(()) => {}
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    }
+
+    assert_eq!(image::image_dimensions(&output).unwrap(), (48, 64));
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,38 @@
+mod common;
+
+use common::test_config;
+use image_colorizer::{dither::error_diffusion, types::DitherAlgorithm, utils::is_extreme};
+
+use image::{DynamicImage, Rgb, RgbImage};
+
+#[test]
+fn threshold_widens_black_and_white() {
+    assert!(is_extreme([0.0, 0.0, 0.0], 0));
+    assert!(is_extreme([1.0, 1.0, 1.0], 0));
+    assert!(!is_extreme([0.0, 0.0, 1.0 / 255.0], 0));
+    assert!(is_extreme([0.0, 0.0, 1.0 / 255.0], 1));
+    assert!(is_extreme([250.0 / 255.0, 1.0, 1.0], 5));
+    assert!(!is_extreme([0.0, 1.0, 1.0], 100));
+}
+
+#[test]
+fn error_diffusion_preserves_black_and_white() {
+    let mut config = test_config(DitherAlgorithm::FloydSteinberg);
+    config.preserve_extremes = true;
+
+    // Line art style stripes of black and white around grey
+    let img = RgbImage::from_fn(32, 32, |x, _| match x % 4 {
+        0 => Rgb([0, 0, 0]),
+        1 => Rgb([255, 255, 255]),
+        _ => Rgb([120, 90, 60]),
+    });
+    let diffused = error_diffusion(&DynamicImage::ImageRgb8(img.clone()), &config);
+
+    for (original, diffused) in img.pixels().zip(diffused.pixels()) {
+        if original[0] == 0 || original[0] == 255 {
+            assert_eq!(original, diffused);
+        } else {
+            assert_ne!(original, diffused);
+        }
+    }
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::test_config;
+use image_colorizer::{
+    colorize::{colorize, ColorizeError},
+    types::{BlendFactor, DitherAlgorithm, GamutMapping, WorkingSpace},
+    utils::working_to_srgb_in_gamut,
+};
+
+use image::{DynamicImage, Rgb, RgbImage};
+use palette::{FromColor, Lab, Srgb};
+
+fn hue(lab: Lab) -> f32 {
+    lab.b.atan2(lab.a).to_degrees()
+}
+
+// Dark yellow is far outside of sRGB at full chroma
+const DARK_YELLOW: [f32; 3] = [30.0, -21.6, 94.5];
+
+#[test]
+fn desaturating_keeps_hue_and_lightness() {
+    let fitted = working_to_srgb_in_gamut(
+        DARK_YELLOW,
+        WorkingSpace::Lab,
+        GamutMapping::DesaturateToFit,
+    );
+    for channel in [fitted.red, fitted.green, fitted.blue] {
+        assert!((-0.001..=1.001).contains(&channel), "{:?}", fitted);
+    }
+
+    let lab = Lab::from_color(fitted.into_linear());
+    assert!((lab.l - DARK_YELLOW[0]).abs() < 0.5, "{:?}", lab);
+    assert!(
+        (hue(lab) - hue(Lab::from(DARK_YELLOW))).abs() < 1.0,
+        "{:?}",
+        lab
+    );
+}
+
+#[tokio::test]
+async fn luminance_transfer_keeps_the_palette_hue() {
+    // Dark grey takes its lightness from the image and its chroma from bright yellow
+    let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(32, 32, Rgb([70, 70, 70])));
+    let yellow = Lab::from_color(Srgb::new(1.0, 1.0, 0.0).into_linear());
+
+    let mut config = test_config(DitherAlgorithm::None);
+    config.colors = vec![yellow];
+    config.blend_factor = BlendFactor::Uniform(1.0);
+    config.gamut_mapping = GamutMapping::DesaturateToFit;
+
+    let colorized = match colorize(&img, &config).await {
+        Ok(colorized) => colorized,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+
+    let [r, g, b] = colorized.get_pixel(16, 16).0;
+    let lab = Lab::from_color(Srgb::new(r, g, b).into_format::<f32>().into_linear());
+    assert!((hue(lab) - hue(yellow)).abs() < 3.0, "{:?}", lab);
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::test_config;
+use image_colorizer::{
+    colorize::{colorize, ColorizeError},
+    types::{AppConfig, DitherAlgorithm, SpatialKernel},
+};
+
+use std::path::PathBuf;
+
+// GPUs are free to round differently, so channels may drift this far from the golden image
+const TOLERANCE: i32 = 3;
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+/// Colorizes `tests/golden/input.png` with `config` and compares it against
+/// `tests/golden/<name>.png`. Running with `UPDATE_GOLDEN=1` writes the output as the new golden
+/// image instead, for when the output is meant to change.
+async fn assert_matches_golden(name: &str, config: &AppConfig) {
+    let input = image::open(golden_dir().join("input.png")).unwrap();
+    let colorized = match colorize(&input, config).await {
+        Ok(colorized) => colorized,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+
+    let path = golden_dir().join(format!("{}.png", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        colorized.save(&path).unwrap();
+        return;
+    }
+
+    let expected = image::open(&path)
+        .unwrap_or_else(|e| panic!("{}: {}. Run with UPDATE_GOLDEN=1 to create it", name, e))
+        .to_rgb8();
+    assert_eq!(colorized.dimensions(), expected.dimensions(), "{}", name);
+
+    for (x, y, pixel) in colorized.enumerate_pixels() {
+        let golden = expected.get_pixel(x, y);
+        assert!(
+            pixel
+                .0
+                .iter()
+                .zip(golden.0)
+                .all(|(&c, g)| (c as i32 - g as i32).abs() <= TOLERANCE),
+            "{} differs at ({}, {}): {:?} instead of {:?}",
+            name,
+            x,
+            y,
+            pixel,
+            golden
+        );
+    }
+}
+
+#[tokio::test]
+async fn random_dither_box_average() {
+    assert_matches_golden(
+        "random_dither_box_average",
+        &test_config(DitherAlgorithm::Random),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn floyd_steinberg_gaussian_average() {
+    let mut config = test_config(DitherAlgorithm::FloydSteinberg);
+    config.spatial_kernel = SpatialKernel::Gaussian { sigma: 2.0 };
+
+    assert_matches_golden("floyd_steinberg_gaussian_average", &config).await;
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::test_config;
+use image_colorizer::{
+    palette::{builtin, format_gpl, load_gpl, parse_gpl, save_gpl},
+    types::DitherAlgorithm,
+};
+
+use std::fs;
+
+use palette::{FromColor, Lab, Srgb};
+
+fn to_rgb8(lab: &Lab) -> [u8; 3] {
+    let rgb: Srgb<u8> = Srgb::from_color(*lab).into_format();
+    [rgb.red, rgb.green, rgb.blue]
+}
+
+#[test]
+fn saved_palettes_load_back_the_same_colors() {
+    let colors = test_config(DitherAlgorithm::None).colors;
+    let path = std::env::temp_dir().join(format!("colorizer-{}.gpl", std::process::id()));
+
+    save_gpl(&path, &colors, None).unwrap();
+    let loaded = load_gpl(&path).unwrap();
+    fs::remove_file(path).unwrap();
+
+    assert_eq!(loaded.len(), colors.len());
+    for (original, loaded) in colors.iter().zip(&loaded) {
+        assert_eq!(to_rgb8(original), to_rgb8(loaded));
+    }
+}
+
+#[test]
+fn colors_are_named_when_names_are_given() {
+    let colors = builtin("nord").unwrap()[..2].to_vec();
+    let names = vec!["polar night".to_string()];
+
+    let gpl = format_gpl(&colors, Some(&names));
+    let lines: Vec<&str> = gpl.lines().collect();
+
+    assert_eq!(lines[0], "GIMP Palette");
+    assert!(lines[lines.len() - 2].ends_with("\tpolar night"));
+    assert!(lines[lines.len() - 1].ends_with("\tcolor1"));
+    assert_eq!(parse_gpl(&gpl).unwrap().len(), 2);
+}
@@ -0,0 +1,46 @@
+use image_colorizer::{palette::duotone, types::WorkingSpace, utils::gradient_map};
+
+use palette::Lab;
+
+#[test]
+fn lightness_interpolates_between_stops() {
+    let stops = [[0.0, 20.0, -20.0], [50.0, 0.0, 0.0], [100.0, -40.0, 60.0]];
+    let map = |l: f32| gradient_map(&[l, 5.0, 5.0], &stops, WorkingSpace::Lab);
+
+    assert_eq!(map(0.0), stops[0]);
+    assert_eq!(map(50.0), stops[1]);
+    assert_eq!(map(100.0), stops[2]);
+    assert_eq!(map(25.0), [25.0, 10.0, -10.0]);
+    assert_eq!(map(75.0), [75.0, -20.0, 30.0]);
+
+    // Lightness outside the range sticks to the end stops
+    assert_eq!(map(-10.0), stops[0]);
+    assert_eq!(map(120.0), stops[2]);
+}
+
+#[test]
+fn single_stop_is_used_everywhere() {
+    let stops = [[40.0, 10.0, 10.0]];
+
+    for l in [0.0, 50.0, 100.0] {
+        assert_eq!(
+            gradient_map(&[l, 0.0, 0.0], &stops, WorkingSpace::Lab),
+            stops[0]
+        );
+    }
+}
+
+#[test]
+fn duotone_spaces_stops_evenly() {
+    let shadow = Lab::new(10.0, 30.0, -40.0);
+    let highlight = Lab::new(90.0, -10.0, 20.0);
+    let stops = duotone(shadow, highlight, 5);
+
+    assert_eq!(stops.len(), 5);
+    assert_eq!(stops[0], shadow);
+    assert_eq!(stops[4], highlight);
+    assert_eq!(stops[2], Lab::new(50.0, 10.0, -10.0));
+
+    // Fewer than two steps would leave out one of the colors
+    assert_eq!(duotone(shadow, highlight, 0), vec![shadow, highlight]);
+}
@@ -0,0 +1,44 @@
+mod common;
+
+use common::{test_config, test_image};
+use image_colorizer::{
+    colorize::{colorize, colorize_grayscale, ColorizeError},
+    types::DitherAlgorithm,
+};
+
+use image::{DynamicImage, GrayImage, Luma};
+
+fn ramp() -> DynamicImage {
+    DynamicImage::ImageLuma8(GrayImage::from_fn(256, 8, |x, _| Luma([x as u8])))
+}
+
+#[tokio::test]
+async fn grayscale_ramps_are_deterministic() {
+    for dither_algorithm in [DitherAlgorithm::Random, DitherAlgorithm::FloydSteinberg] {
+        let config = test_config(dither_algorithm);
+
+        let first = match colorize_grayscale(&ramp(), &config).await {
+            Ok(first) => first,
+            Err(ColorizeError::NoAdapter) => return,
+            Err(e) => panic!("{}", e),
+        };
+        let second = colorize_grayscale(&ramp(), &config).await.unwrap();
+
+        assert_eq!(first, second);
+    }
+}
+
+#[tokio::test]
+async fn color_input_is_reduced_to_luma() {
+    let config = test_config(DitherAlgorithm::Random);
+    let img = test_image();
+
+    let from_color = match colorize_grayscale(&img, &config).await {
+        Ok(from_color) => from_color,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+    let gray = DynamicImage::ImageLuma8(img.to_luma8());
+
+    assert_eq!(from_color, colorize(&gray, &config).await.unwrap());
+}
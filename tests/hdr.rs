@@ -0,0 +1,112 @@
+mod common;
+
+use common::test_config;
+use image::{DynamicImage, Rgb, Rgb32FImage};
+use image_colorizer::{
+    batch::process_file,
+    colorize::{colorize_rgb16, ColorizeError},
+    hdr::tone_map,
+    types::{DitherAlgorithm, ToneMap},
+};
+
+// A horizontal ramp from black up to 4 times brighter than white
+fn hdr_ramp() -> Rgb32FImage {
+    Rgb32FImage::from_fn(64, 48, |x, _| {
+        let value = x as f32 / 63.0 * 4.0;
+        Rgb([value, value * 0.8, value * 0.6])
+    })
+}
+
+#[test]
+fn operators_keep_highlights_apart_that_clamping_flattens() {
+    let ramp = hdr_ramp();
+
+    let clamped = tone_map(&ramp, ToneMap::None);
+    assert_eq!(clamped.get_pixel(40, 0), clamped.get_pixel(63, 0));
+
+    // Green stays below the luminance, so it's never clipped after Reinhard scales by it
+    for operator in [ToneMap::Reinhard, ToneMap::Aces] {
+        let mapped = tone_map(&ramp, operator);
+        for x in 1..64 {
+            assert!(
+                mapped.get_pixel(x, 0)[1] > mapped.get_pixel(x - 1, 0)[1],
+                "{} isn't increasing at {}",
+                operator,
+                x
+            );
+        }
+        assert!(mapped.get_pixel(63, 0)[1] < u16::MAX);
+    }
+}
+
+#[test]
+fn values_in_range_are_only_encoded_without_an_operator() {
+    let linear = Rgb32FImage::from_pixel(1, 1, Rgb([0.0, 0.214, 1.0]));
+
+    let [r, g, b] = tone_map(&linear, ToneMap::None).get_pixel(0, 0).0;
+    assert_eq!((r, b), (0, u16::MAX));
+    assert!((g as f32 / 65535.0 - 0.5).abs() < 0.01);
+}
+
+#[tokio::test]
+async fn float_images_are_colorized_from_the_tone_mapped_result() {
+    let img = DynamicImage::ImageRgb32F(hdr_ramp());
+    let mut config = test_config(DitherAlgorithm::None);
+    config.tone_map = ToneMap::Reinhard;
+
+    let output = match colorize_rgb16(&img, &config).await {
+        Ok(output) => output,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+    let mapped = DynamicImage::ImageRgb16(tone_map(&hdr_ramp(), ToneMap::Reinhard));
+
+    assert_eq!(output, colorize_rgb16(&mapped, &config).await.unwrap());
+}
+
+#[test]
+fn exr_files_are_read_as_hdr() {
+    let dir = std::env::temp_dir().join(format!("colorizer-hdr-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("render.exr");
+    let output = dir.join("render.png");
+    DynamicImage::ImageRgb32F(hdr_ramp()).save(&input).unwrap();
+
+    let mut config = test_config(DitherAlgorithm::None);
+    config.tone_map = ToneMap::Aces;
+    let result = process_file(&input, &output, &config);
+    let written = image::open(&output);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    match result {
+        Ok(()) => {}
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    }
+    // 16 bits are kept all the way through to the PNG
+    assert!(matches!(written.unwrap(), DynamicImage::ImageRgb16(_)));
+}
+
+#[test]
+fn exr_files_are_written_as_exr() {
+    let dir = std::env::temp_dir().join(format!("colorizer-exr-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("render.exr");
+    let output = dir.join("render_kanagawa.exr");
+    DynamicImage::ImageRgb32F(hdr_ramp()).save(&input).unwrap();
+
+    let mut config = test_config(DitherAlgorithm::None);
+    config.tone_map = ToneMap::Aces;
+    let result = process_file(&input, &output, &config);
+    let written = image::open(&output);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    match result {
+        Ok(()) => {}
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    }
+    let written = written.unwrap();
+    assert_eq!((written.width(), written.height()), (64, 48));
+    assert!(matches!(written, DynamicImage::ImageRgb32F(_)));
+}
@@ -0,0 +1,37 @@
+mod common;
+
+use common::test_config;
+use image_colorizer::{
+    colorize::{colorize, colorize_rgb16, ColorizeError},
+    types::DitherAlgorithm,
+};
+
+use image::{DynamicImage, ImageBuffer, Rgb};
+
+#[tokio::test]
+async fn rgb16_keeps_precision_and_matches_rgb8() {
+    let img = DynamicImage::ImageRgb16(ImageBuffer::from_fn(64, 48, |x, y| {
+        Rgb([(x * 1000) as u16, (y * 1300) as u16, ((x + y) * 500) as u16])
+    }));
+    let config = test_config(DitherAlgorithm::None);
+
+    let rgb8 = match colorize(&img, &config).await {
+        Ok(rgb8) => rgb8,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+    let rgb16 = colorize_rgb16(&img, &config).await.unwrap();
+
+    // Pixels close to the boundary between two palette colors can flip, so only the average
+    // difference is bounded
+    let total_difference: f32 = rgb16
+        .as_raw()
+        .iter()
+        .zip(rgb8.as_raw())
+        .map(|(&high, &low)| (high as f32 / 257.0 - low as f32).abs())
+        .sum();
+    assert!(total_difference / (rgb8.as_raw().len() as f32) < 1.5);
+
+    // An 8 bit result scaled up would only ever contain multiples of 257
+    assert!(rgb16.as_raw().iter().any(|channel| channel % 257 != 0));
+}
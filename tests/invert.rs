@@ -0,0 +1,67 @@
+mod common;
+
+use common::{test_config, test_image};
+use image_colorizer::{
+    colorize::{colorize, ColorizeError},
+    types::{BlendFactor, DitherAlgorithm},
+    utils::rgb8_to_lab,
+};
+
+use image::{DynamicImage, Rgb, RgbImage};
+
+fn lightness(pixel: &Rgb<u8>) -> f32 {
+    rgb8_to_lab(pixel[0], pixel[1], pixel[2]).l
+}
+
+#[tokio::test]
+async fn lightness_is_flipped() {
+    // Without chroma the output is grey, so its lightness can't be moved by gamut mapping
+    let img = test_image();
+    let mut config = test_config(DitherAlgorithm::None);
+    config.blend_factor = BlendFactor::Uniform(1.0);
+    config.chroma_scale = 0.0;
+    config.invert_luminance = true;
+
+    let inverted = match colorize(&img, &config).await {
+        Ok(inverted) => inverted,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+
+    for (original, pixel) in img.to_rgb8().pixels().zip(inverted.pixels()) {
+        let expected = 100.0 - lightness(original);
+        assert!(
+            (lightness(pixel) - expected).abs() < 1.0,
+            "Expected a lightness of {}, got {}",
+            expected,
+            lightness(pixel)
+        );
+    }
+}
+
+#[tokio::test]
+async fn dark_and_light_trade_places() {
+    let img = DynamicImage::ImageRgb8(RgbImage::from_fn(128, 16, |x, _| {
+        let v = (x * 2) as u8;
+        Rgb([v, v, v])
+    }));
+    let mut config = test_config(DitherAlgorithm::None);
+    config.blend_factor = BlendFactor::Uniform(1.0);
+    config.invert_luminance = true;
+
+    let inverted = match colorize(&img, &config).await {
+        Ok(inverted) => inverted,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+
+    let mean_lightness = |columns: std::ops::Range<u32>| {
+        let pixels: Vec<f32> = inverted
+            .enumerate_pixels()
+            .filter(|(x, _, _)| columns.contains(x))
+            .map(|(_, _, pixel)| lightness(pixel))
+            .collect();
+        pixels.iter().sum::<f32>() / pixels.len() as f32
+    };
+    assert!(mean_lightness(0..32) > mean_lightness(96..128) + 30.0);
+}
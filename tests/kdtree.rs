@@ -0,0 +1,52 @@
+use image_colorizer::{
+    kdtree::KdTree,
+    types::{DistanceMetric, WorkingSpace},
+    utils::find_closest_color,
+};
+
+// Small deterministic generator so the test doesn't depend on rand's output staying the same
+fn lcg(state: &mut u32) -> f32 {
+    *state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+    (*state >> 8) as f32 / 16777216.0
+}
+
+fn random_lab(state: &mut u32) -> [f32; 3] {
+    [
+        lcg(state) * 100.0,
+        lcg(state) * 200.0 - 100.0,
+        lcg(state) * 200.0 - 100.0,
+    ]
+}
+
+#[test]
+fn kd_tree_matches_linear_scan() {
+    let mut state = 7;
+
+    for palette_size in [1, 2, 16, 256] {
+        let mut colors: Vec<[f32; 3]> = (0..palette_size).map(|_| random_lab(&mut state)).collect();
+        // Duplicates make sure ties go to the same color as the linear scan
+        colors.extend_from_within(..palette_size / 2);
+
+        for luminance_weight in [1.0, 0.25, 0.0] {
+            let tree = KdTree::new(&colors, luminance_weight);
+
+            for _ in 0..500 {
+                let color = random_lab(&mut state);
+                assert_eq!(
+                    tree.nearest(&color).unwrap().1,
+                    find_closest_color(
+                        &color,
+                        &colors,
+                        DistanceMetric::Cie76,
+                        WorkingSpace::Lab,
+                        luminance_weight
+                    ),
+                    "palette of {}, luminance weight {}, color {:?}",
+                    palette_size,
+                    luminance_weight,
+                    color
+                );
+            }
+        }
+    }
+}
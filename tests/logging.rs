@@ -0,0 +1,59 @@
+mod common;
+
+use common::{test_config, test_image};
+use image_colorizer::{
+    colorize::{colorize, ColorizeError},
+    types::DitherAlgorithm,
+};
+
+use std::sync::Mutex;
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+struct Recorder(Mutex<Vec<String>>);
+
+impl Log for Recorder {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        // wgpu logs through the same facade
+        if record.target().starts_with("image_colorizer") {
+            self.0.lock().unwrap().push(record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static RECORDER: Recorder = Recorder(Mutex::new(Vec::new()));
+
+#[tokio::test]
+async fn every_stage_is_timed() {
+    log::set_logger(&RECORDER).unwrap();
+    log::set_max_level(LevelFilter::Debug);
+
+    match colorize(&test_image(), &test_config(DitherAlgorithm::Random)).await {
+        Ok(_) => {}
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    }
+
+    let messages = RECORDER.0.lock().unwrap();
+    for expected in [
+        "Colorizing 64x48 image (3072 pixels)",
+        "First pass on the GPU took",
+        "Summed area table took",
+        "Spatial averaging with the Box kernel took",
+        "Second pass on the GPU took",
+        "Colorized 64x48 image in",
+    ] {
+        assert!(
+            messages.iter().any(|message| message.starts_with(expected)),
+            "No '{}' in {:?}",
+            expected,
+            messages
+        );
+    }
+}
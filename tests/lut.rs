@@ -0,0 +1,111 @@
+mod common;
+
+use common::test_config;
+use image_colorizer::{
+    dither::error_diffusion,
+    lut::{export_cube_lut, export_hald_clut, identity_hald_clut},
+    types::{BlendFactor, DitherAlgorithm},
+};
+
+use image::DynamicImage;
+use palette::{FromColor, Lab, Srgb};
+
+fn cube_entries(cube: &str) -> Vec<[f32; 3]> {
+    cube.lines()
+        .skip(4)
+        .map(|line| {
+            let values: Vec<f32> = line
+                .split(' ')
+                .map(|value| value.parse().unwrap())
+                .collect();
+            [values[0], values[1], values[2]]
+        })
+        .collect()
+}
+
+#[test]
+fn identity_covers_every_step_of_every_channel() {
+    let clut = identity_hald_clut(2);
+    assert_eq!(clut.dimensions(), (8, 8));
+
+    // Four steps per channel, red changing fastest
+    assert_eq!(clut.get_pixel(0, 0).0, [0, 0, 0]);
+    assert_eq!(clut.get_pixel(1, 0).0, [85, 0, 0]);
+    assert_eq!(clut.get_pixel(4, 0).0, [0, 85, 0]);
+    assert_eq!(clut.get_pixel(0, 2).0, [0, 0, 85]);
+    assert_eq!(clut.get_pixel(7, 7).0, [255, 255, 255]);
+
+    assert_eq!(identity_hald_clut(8).dimensions(), (512, 512));
+}
+
+#[test]
+fn nothing_changes_without_blending() {
+    let mut config = test_config(DitherAlgorithm::None);
+    config.blend_factor = BlendFactor::Uniform(0.0);
+
+    assert_eq!(export_hald_clut(&config, 4), identity_hald_clut(4));
+}
+
+#[test]
+fn clut_matches_the_undithered_first_pass() {
+    let undithered = test_config(DitherAlgorithm::None);
+    let expected = error_diffusion(&DynamicImage::ImageRgb8(identity_hald_clut(4)), &undithered);
+
+    // Dithering depends on neighboring pixels, so it's left out of the LUT
+    for dither_algorithm in [
+        DitherAlgorithm::None,
+        DitherAlgorithm::Random,
+        DitherAlgorithm::FloydSteinberg,
+    ] {
+        let config = test_config(dither_algorithm);
+        assert_eq!(
+            export_hald_clut(&config, 4),
+            expected,
+            "{:?}",
+            dither_algorithm
+        );
+    }
+}
+
+#[test]
+fn cube_header_and_grid_order_follow_the_spec() {
+    let mut config = test_config(DitherAlgorithm::None);
+    config.blend_factor = BlendFactor::Uniform(0.0);
+
+    let cube = export_cube_lut(&config, 5);
+    let header: Vec<&str> = cube.lines().take(4).collect();
+    assert_eq!(
+        header,
+        [
+            "TITLE \"image-colorizer\"",
+            "LUT_3D_SIZE 5",
+            "DOMAIN_MIN 0.0 0.0 0.0",
+            "DOMAIN_MAX 1.0 1.0 1.0",
+        ]
+    );
+
+    // Without blending the LUT is the identity, which shows red changing fastest
+    let entries = cube_entries(&cube);
+    assert_eq!(entries.len(), 125);
+    for (i, entry) in entries.iter().enumerate() {
+        let expected = [i % 5, i / 5 % 5, i / 25].map(|step| step as f32 / 4.0);
+        for (value, expected) in entry.iter().zip(expected) {
+            assert!((value - expected).abs() < 1e-4, "entry {}: {:?}", i, entry);
+        }
+    }
+}
+
+#[test]
+fn cube_maps_every_sample_onto_the_palette() {
+    let teal = Srgb::new(0.2, 0.6, 0.6);
+    let mut config = test_config(DitherAlgorithm::None);
+    config.colors = vec![Lab::from_color(teal.into_linear())];
+    config.blend_factor = BlendFactor::Uniform(1.0);
+    config.transfer_luminance = false;
+
+    for entry in cube_entries(&export_cube_lut(&config, 3)) {
+        for (value, expected) in entry.iter().zip([teal.red, teal.green, teal.blue]) {
+            assert!((value - expected).abs() < 1e-3, "{:?}", entry);
+        }
+    }
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use common::{test_config, test_image};
+use image_colorizer::{
+    colorize::{colorize_with_mask, ColorizeError},
+    types::DitherAlgorithm,
+};
+
+use image::{DynamicImage, GrayImage, Luma};
+
+#[tokio::test]
+async fn mask_must_match_the_image() {
+    let config = test_config(DitherAlgorithm::Random);
+    let mask = DynamicImage::ImageLuma8(GrayImage::new(10, 10));
+
+    match colorize_with_mask(&test_image(), Some(&mask), &config).await {
+        Err(ColorizeError::MaskDimensions { mask, image }) => {
+            assert_eq!(mask, (10, 10));
+            assert_eq!(image, (64, 48));
+        }
+        result => panic!("Expected a mask dimension error, got {:?}", result.err()),
+    }
+}
+
+#[tokio::test]
+async fn masked_out_pixels_keep_the_original() {
+    let config = test_config(DitherAlgorithm::Random);
+    let img = test_image();
+    // Only the left half is colorized
+    let mask = DynamicImage::ImageLuma8(GrayImage::from_fn(64, 48, |x, _| {
+        Luma([if x < 32 { 255 } else { 0 }])
+    }));
+
+    let colorized = match colorize_with_mask(&img, Some(&mask), &config).await {
+        Ok(colorized) => colorized,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+
+    let original = img.to_rgb8();
+    for (x, y, pixel) in colorized.enumerate_pixels() {
+        if x >= 32 {
+            assert_eq!(pixel, original.get_pixel(x, y), "{:?}", (x, y));
+        }
+    }
+}
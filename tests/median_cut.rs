@@ -0,0 +1,62 @@
+mod common;
+
+use common::test_image;
+use image::{DynamicImage, Rgb, RgbImage};
+use image_colorizer::{palette::extract_median_cut, utils::rgb8_to_lab};
+
+#[test]
+fn returns_at_most_k_colors() {
+    let img = test_image();
+
+    for k in [1, 2, 7, 16, 64] {
+        assert_eq!(extract_median_cut(&img, k).len(), k);
+    }
+    assert!(extract_median_cut(&img, 0).is_empty());
+}
+
+#[test]
+fn is_deterministic() {
+    let img = test_image();
+
+    assert_eq!(extract_median_cut(&img, 16), extract_median_cut(&img, 16));
+}
+
+#[test]
+fn recovers_every_color_of_small_images() {
+    let colors = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [40, 40, 40]];
+    let img = DynamicImage::ImageRgb8(RgbImage::from_fn(16, 16, |x, y| {
+        Rgb(colors[((x / 4 + y) % 4) as usize])
+    }));
+
+    let mut extracted = extract_median_cut(&img, 8);
+    let mut expected: Vec<_> = colors
+        .iter()
+        .map(|c| rgb8_to_lab(c[0], c[1], c[2]))
+        .collect();
+
+    let key = |lab: &palette::Lab| (lab.l, lab.a, lab.b);
+    extracted.sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap());
+    expected.sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap());
+
+    assert_eq!(extracted.len(), expected.len());
+    for (a, b) in extracted.iter().zip(&expected) {
+        assert!((a.l - b.l).abs() < 1e-3 && (a.a - b.a).abs() < 1e-3 && (a.b - b.b).abs() < 1e-3);
+    }
+}
+
+#[test]
+fn splits_two_tones_apart() {
+    let img = DynamicImage::ImageRgb8(RgbImage::from_fn(32, 32, |x, _| {
+        if x < 16 {
+            Rgb([10, 10, 10])
+        } else {
+            Rgb([250, 250, 250])
+        }
+    }));
+
+    let extracted = extract_median_cut(&img, 2);
+    let mut lightness: Vec<f32> = extracted.iter().map(|lab| lab.l).collect();
+    lightness.sort_by(f32::total_cmp);
+
+    assert!(lightness[0] < 10.0 && lightness[1] > 90.0);
+}
@@ -0,0 +1,44 @@
+mod common;
+
+use common::{test_config, test_image};
+use image_colorizer::{
+    colorize::{colorize, ColorizeError},
+    dither::error_diffusion,
+    types::{AppConfig, DitherAlgorithm},
+};
+
+use palette::Lab;
+
+// A NaN component makes the distance to that color NaN for every pixel
+fn with_nan_color(mut config: AppConfig) -> AppConfig {
+    config.colors.insert(0, Lab::new(f32::NAN, 0.0, 0.0));
+    config.colors.push(Lab::new(50.0, -f32::NAN, f32::NAN));
+    config
+}
+
+#[test]
+fn error_diffusion_skips_nan_colors() {
+    let config = test_config(DitherAlgorithm::FloydSteinberg);
+    let nan_config = with_nan_color(test_config(DitherAlgorithm::FloydSteinberg));
+    let img = test_image();
+
+    assert_eq!(
+        error_diffusion(&img, &nan_config).into_raw(),
+        error_diffusion(&img, &config).into_raw()
+    );
+}
+
+#[tokio::test]
+async fn colorize_skips_nan_colors() {
+    let config = test_config(DitherAlgorithm::Random);
+    let img = test_image();
+
+    let expected = match colorize(&img, &config).await {
+        Ok(expected) => expected,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+    let colorized = colorize(&img, &with_nan_color(config)).await.unwrap();
+
+    assert_eq!(colorized.into_raw(), expected.into_raw());
+}
@@ -0,0 +1,24 @@
+mod common;
+
+use common::{test_config, test_image};
+use image_colorizer::{
+    colorize::{colorize, ColorizeError},
+    types::DitherAlgorithm,
+};
+
+#[tokio::test]
+async fn two_levels_leave_only_black_and_white_channels() {
+    let mut config = test_config(DitherAlgorithm::None);
+    config.posterize_levels = Some(2);
+
+    let posterized = match colorize(&test_image(), &config).await {
+        Ok(posterized) => posterized,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+
+    assert!(posterized
+        .as_raw()
+        .iter()
+        .all(|&channel| channel == 0 || channel == 255));
+}
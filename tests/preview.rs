@@ -0,0 +1,35 @@
+mod common;
+
+use common::{test_config, test_image};
+use image_colorizer::{
+    colorize::{colorize, colorize_preview, ColorizeError},
+    types::DitherAlgorithm,
+};
+
+#[tokio::test]
+async fn preview_fits_the_longest_side() {
+    let config = test_config(DitherAlgorithm::Random);
+
+    let preview = match colorize_preview(&test_image(), &config, 16).await {
+        Ok(preview) => preview,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+
+    // 64x48 keeps its aspect ratio
+    assert_eq!(preview.dimensions(), (16, 12));
+}
+
+#[tokio::test]
+async fn small_images_are_not_scaled_up() {
+    let config = test_config(DitherAlgorithm::Random);
+    let img = test_image();
+
+    let preview = match colorize_preview(&img, &config, 256).await {
+        Ok(preview) => preview,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+
+    assert_eq!(preview, colorize(&img, &config).await.unwrap());
+}
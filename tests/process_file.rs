@@ -0,0 +1,124 @@
+mod common;
+
+use common::{test_config, test_image};
+use image_colorizer::{batch::process_file, colorize::ColorizeError, types::DitherAlgorithm};
+
+use std::{fs, path::PathBuf};
+
+use image::{DynamicImage, ImageBuffer, ImageFormat};
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("colorizer-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn output_format_follows_the_extension() {
+    let dir = scratch_dir("formats");
+    let input = dir.join("input.png");
+    test_image().save(&input).unwrap();
+    let config = test_config(DitherAlgorithm::None);
+
+    for (extension, format) in [
+        ("png", ImageFormat::Png),
+        ("jpg", ImageFormat::Jpeg),
+        ("webp", ImageFormat::WebP),
+        ("bmp", ImageFormat::Bmp),
+    ] {
+        let output = dir.join(format!("output.{}", extension));
+        match process_file(&input, &output, &config) {
+            Ok(()) => {}
+            Err(ColorizeError::NoAdapter) => return,
+            Err(e) => panic!("{}: {}", extension, e),
+        }
+
+        let bytes = fs::read(&output).unwrap();
+        assert_eq!(image::guess_format(&bytes).unwrap(), format);
+        assert_eq!(
+            image::load_from_memory(&bytes)
+                .unwrap()
+                .to_rgb8()
+                .dimensions(),
+            (64, 48)
+        );
+    }
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn jpeg_quality_trades_size_for_fidelity() {
+    let dir = scratch_dir("jpeg-quality");
+    let input = dir.join("input.png");
+    test_image().save(&input).unwrap();
+
+    let mut sizes = Vec::new();
+    for quality in [20, 95] {
+        let mut config = test_config(DitherAlgorithm::None);
+        config.jpeg_quality = quality;
+
+        let output = dir.join(format!("{}.jpg", quality));
+        match process_file(&input, &output, &config) {
+            Ok(()) => {}
+            Err(ColorizeError::NoAdapter) => return,
+            Err(e) => panic!("{}", e),
+        }
+        sizes.push(fs::metadata(&output).unwrap().len());
+    }
+
+    assert!(sizes[0] < sizes[1], "{:?}", sizes);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn unknown_extensions_are_rejected() {
+    let dir = scratch_dir("unknown");
+    let input = dir.join("input.png");
+    test_image().save(&input).unwrap();
+
+    let result = process_file(
+        &input,
+        &dir.join("output.unknown"),
+        &test_config(DitherAlgorithm::None),
+    );
+    assert!(
+        matches!(
+            result,
+            Err(ColorizeError::Image(_)) | Err(ColorizeError::NoAdapter)
+        ),
+        "{:?}",
+        result
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn rgba16_input_keeps_16_bits_and_alpha() {
+    let dir = scratch_dir("rgba16");
+    let input = dir.join("input.png");
+    let rgb = test_image().to_rgba16();
+    let source = ImageBuffer::from_fn(64, 48, |x, y| {
+        let mut pixel = *rgb.get_pixel(x, y);
+        pixel[3] = if x < 8 { 0 } else { (y * 1000) as u16 + 1 };
+        pixel
+    });
+    DynamicImage::ImageRgba16(source.clone())
+        .save(&input)
+        .unwrap();
+
+    let output = dir.join("output.png");
+    match process_file(&input, &output, &test_config(DitherAlgorithm::None)) {
+        Ok(()) => {}
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    }
+
+    let DynamicImage::ImageRgba16(written) = image::open(&output).unwrap() else {
+        panic!("expected a 16 bit RGBA image");
+    };
+    for (written, source) in written.pixels().zip(source.pixels()) {
+        assert_eq!(written[3], source[3]);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,32 @@
+mod common;
+
+use common::{test_config, test_image};
+use image_colorizer::{
+    colorize::{colorize_with_progress_updates, ColorizeError},
+    progress::Stage,
+    types::DitherAlgorithm,
+};
+
+use std::sync::mpsc::channel;
+
+#[tokio::test]
+async fn updates_cover_both_passes_once_per_percent() {
+    let mut config = test_config(DitherAlgorithm::Random);
+    config.tile_height = Some(7);
+    let (sender, receiver) = channel();
+
+    match colorize_with_progress_updates(&test_image(), &config, sender).await {
+        Ok(_) => {}
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    }
+    let updates: Vec<_> = receiver.try_iter().collect();
+
+    assert_eq!(updates[0].stage, Stage::FirstPass);
+    assert!(updates
+        .iter()
+        .any(|update| update.stage == Stage::SecondPass));
+    assert_eq!(updates.last().unwrap().fraction, 1.0);
+    // Every percent at most once, plus one whenever one of the 7 strips starts a pass
+    assert!(updates.len() <= 100 + 2 * 7, "{} updates", updates.len());
+}
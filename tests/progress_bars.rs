@@ -0,0 +1,45 @@
+// The progress bars are part of the command line tool
+#![cfg(feature = "cli")]
+
+use image_colorizer::{
+    batch::BatchSummary,
+    progress_bars::{create_progress_bar, draw_target, skip_warnings},
+};
+use indicatif::MultiProgress;
+
+use std::path::PathBuf;
+
+// Stderr isn't a terminal under the test harness either, so only forced bars draw
+fn visible() -> MultiProgress {
+    MultiProgress::with_draw_target(draw_target(true, false))
+}
+
+#[test]
+fn quiet_bars_are_hidden() {
+    assert!(create_progress_bar(&visible(), false).is_hidden());
+    assert!(!create_progress_bar(&visible(), true).is_hidden());
+}
+
+#[test]
+fn quiet_batches_warn_about_nothing() {
+    let summary = BatchSummary {
+        colorized: 1,
+        skipped: vec![(PathBuf::from("notes.txt"), "Not an image".to_string())],
+    };
+
+    assert!(skip_warnings(&summary, false).is_empty());
+    assert_eq!(
+        skip_warnings(&summary, true),
+        vec!["Warning: Skipped notes.txt: Not an image".to_string()]
+    );
+}
+
+#[test]
+fn bars_are_hidden_unless_stderr_is_a_terminal() {
+    assert!(draw_target(false, false).is_hidden());
+    let piped = MultiProgress::with_draw_target(draw_target(false, false));
+    assert!(create_progress_bar(&piped, true).is_hidden());
+
+    // Forcing progress draws even then
+    assert!(!draw_target(true, false).is_hidden());
+}
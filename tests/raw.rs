@@ -0,0 +1,36 @@
+mod common;
+
+use common::{test_config, test_image};
+use image_colorizer::{
+    colorize::{colorize, colorize_raw, ColorizeError},
+    types::DitherAlgorithm,
+};
+
+#[tokio::test]
+async fn raw_samples_match_colorizing_the_image() {
+    let config = test_config(DitherAlgorithm::Random);
+    let img = test_image();
+    let rgb = img.to_rgb8();
+
+    let raw = match colorize_raw(rgb.as_raw(), rgb.width(), rgb.height(), &config).await {
+        Ok(raw) => raw,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+
+    assert_eq!(raw, colorize(&img, &config).await.unwrap().into_raw());
+}
+
+#[tokio::test]
+async fn sample_count_has_to_match_the_dimensions() {
+    let config = test_config(DitherAlgorithm::Random);
+
+    for len in [0, 64 * 48 * 3 - 1, 64 * 48 * 3 + 3, 64 * 48 * 4] {
+        match colorize_raw(&vec![0; len], 64, 48, &config).await {
+            Err(ColorizeError::RawBufferSize { expected, actual }) => {
+                assert_eq!((expected, actual), (64 * 48 * 3, len))
+            }
+            result => panic!("Expected a size error for {} bytes, got {:?}", len, result),
+        }
+    }
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use common::{test_config, test_image};
+use image_colorizer::{
+    colorize::{colorize, ColorizeError},
+    types::DitherAlgorithm,
+};
+
+#[tokio::test]
+async fn refining_changes_the_result() {
+    let mut config = test_config(DitherAlgorithm::Random);
+    let img = test_image();
+
+    let once = match colorize(&img, &config).await {
+        Ok(once) => once,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+    config.refine_iterations = 3;
+    let refined = colorize(&img, &config).await.unwrap();
+
+    assert_ne!(once, refined);
+}
+
+#[tokio::test]
+async fn refined_tiles_are_seamless() {
+    let mut config = test_config(DitherAlgorithm::Random);
+    config.refine_iterations = 3;
+    let img = test_image();
+
+    let whole = match colorize(&img, &config).await {
+        Ok(whole) => whole,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+    config.tile_height = Some(7);
+    let tiled = colorize(&img, &config).await.unwrap();
+
+    assert_eq!(whole, tiled);
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use common::{test_config, test_image};
+use image::{DynamicImage, GrayImage, Luma, Rgb, RgbImage};
+use image_colorizer::{
+    colorize::{colorize_with_regions, ColorizeError},
+    types::{BlendFactor, ConfigError, DitherAlgorithm},
+    utils::rgb8_to_lab,
+};
+use palette::Lab;
+
+fn palettes() -> Vec<Vec<Lab>> {
+    vec![
+        vec![Lab::new(50.0, 60.0, 40.0)],
+        vec![Lab::new(50.0, 10.0, -60.0)],
+    ]
+}
+
+// Label 0 left of column 32 and label 1 from there on
+fn halves() -> GrayImage {
+    GrayImage::from_fn(64, 48, |x, _| Luma([(x >= 32) as u8]))
+}
+
+#[tokio::test]
+async fn every_region_maps_onto_its_own_palette() {
+    let mut config = test_config(DitherAlgorithm::None);
+    config.blend_factor = BlendFactor::Uniform(1.0);
+
+    // Mid grey leaves the palette colors in gamut once luminance is transferred
+    let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(64, 48, Rgb([119; 3])));
+    let output = match colorize_with_regions(&img, &halves(), &palettes(), &config).await {
+        Ok(output) => output,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+
+    // Averaging mixes the palettes within its radius of the boundary, so those columns are skipped
+    let radius = config.spatial_averaging_radius;
+    for (x, y, pixel) in output.enumerate_pixels() {
+        let lab = rgb8_to_lab(pixel[0], pixel[1], pixel[2]);
+        if x + radius < 32 {
+            assert!(lab.a > 20.0 && lab.b > 10.0, "({}, {}) is {:?}", x, y, lab);
+        } else if x >= 32 + radius {
+            assert!(lab.b < -20.0, "({}, {}) is {:?}", x, y, lab);
+        }
+    }
+}
+
+#[tokio::test]
+async fn labels_have_to_fit_the_image_and_palettes() {
+    let config = test_config(DitherAlgorithm::None);
+    let img = test_image();
+
+    match colorize_with_regions(&img, &GrayImage::new(32, 48), &palettes(), &config).await {
+        Err(ColorizeError::LabelDimensions { labels, image }) => {
+            assert_eq!((labels, image), ((32, 48), (64, 48)))
+        }
+        result => panic!("Expected a dimension error, got {:?}", result.map(|_| ())),
+    }
+
+    let labels = GrayImage::from_pixel(64, 48, Luma([2]));
+    match colorize_with_regions(&img, &labels, &palettes(), &config).await {
+        Err(ColorizeError::MissingRegionPalette { label, palettes }) => {
+            assert_eq!((label, palettes), (2, 2))
+        }
+        result => panic!("Expected a missing palette, got {:?}", result.map(|_| ())),
+    }
+
+    let empty = vec![palettes()[0].clone(), Vec::new()];
+    match colorize_with_regions(&img, &halves(), &empty, &config).await {
+        Err(ColorizeError::Config(ConfigError::EmptyColors)) => {}
+        result => panic!("Expected an empty palette, got {:?}", result.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn region_palettes_stand_in_for_the_config_colors() {
+    let mut config = test_config(DitherAlgorithm::None);
+    config.colors.clear();
+
+    match colorize_with_regions(&test_image(), &halves(), &palettes(), &config).await {
+        Ok(output) => assert_eq!(output.dimensions(), (64, 48)),
+        Err(ColorizeError::NoAdapter) => {}
+        Err(e) => panic!("{}", e),
+    }
+}
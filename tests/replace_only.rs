@@ -0,0 +1,55 @@
+mod common;
+
+use common::test_config;
+use image::{DynamicImage, Rgb, RgbImage};
+use image_colorizer::{
+    colorize::{colorize, ColorizeError},
+    palette::parse_color_keys,
+    types::{BlendFactor, DitherAlgorithm},
+};
+
+#[test]
+fn parses_colors_with_optional_tolerances() {
+    assert_eq!(
+        parse_color_keys("#e02020:40, #ffffff").unwrap(),
+        vec![(Rgb([224, 32, 32]), 40), (Rgb([255, 255, 255]), 0)]
+    );
+
+    assert!(parse_color_keys("#e02020:300").is_err());
+    assert!(parse_color_keys("nope:10").is_err());
+}
+
+#[tokio::test]
+async fn only_pixels_near_a_key_are_colorized() {
+    // Reddish on the left with a little noise, blue on the right
+    let img = RgbImage::from_fn(64, 48, |x, y| {
+        if x < 32 {
+            Rgb([200 + (x + y) as u8 % 8, 30, 30])
+        } else {
+            Rgb([30, 60, 200])
+        }
+    });
+    let mut config = test_config(DitherAlgorithm::None);
+    config.blend_factor = BlendFactor::Uniform(1.0);
+    config.replace_only = Some(vec![(Rgb([204, 30, 30]), 10)]);
+
+    let output = match colorize(&DynamicImage::ImageRgb8(img.clone()), &config).await {
+        Ok(output) => output,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+
+    for (x, y, pixel) in output.enumerate_pixels() {
+        if x < 32 {
+            assert_ne!(
+                pixel,
+                img.get_pixel(x, y),
+                "({}, {}) wasn't colorized",
+                x,
+                y
+            );
+        } else {
+            assert_eq!(pixel, img.get_pixel(x, y), "({}, {}) was colorized", x, y);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use common::test_config;
+use image::{DynamicImage, Rgba, RgbaImage};
+use image_colorizer::{
+    colorize::{colorize, colorize_rgba, ColorizeError},
+    types::DitherAlgorithm,
+};
+
+// An opaque gradient on the left half, and bright red with `alpha` on the right
+fn sprite(alpha: impl Fn(u32, u32) -> u8) -> RgbaImage {
+    RgbaImage::from_fn(32, 16, |x, y| match x {
+        0..=15 => Rgba([(x * 16) as u8, (y * 16) as u8, 128, 255]),
+        _ => Rgba([255, 0, 0, alpha(x, y)]),
+    })
+}
+
+#[tokio::test]
+async fn alpha_is_carried_through() {
+    let config = test_config(DitherAlgorithm::Random);
+    let source = sprite(|x, y| ((x - 16) * y) as u8);
+
+    let output = match colorize_rgba(&DynamicImage::ImageRgba8(source.clone()), &config).await {
+        Ok(output) => output,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+
+    assert_eq!(output.dimensions(), source.dimensions());
+    assert!(output
+        .pixels()
+        .zip(source.pixels())
+        .all(|(colorized, original)| colorized[3] == original[3]));
+}
+
+#[tokio::test]
+async fn transparent_pixels_stay_out_of_their_neighbors() {
+    let config = test_config(DitherAlgorithm::None);
+    let source = sprite(|_, _| 0);
+
+    let output = match colorize_rgba(&DynamicImage::ImageRgba8(source.clone()), &config).await {
+        Ok(output) => output,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+
+    // The red would tint the right edge of the opaque half if it were averaged in
+    let opaque = image::imageops::crop_imm(&source, 0, 0, 16, 16).to_image();
+    let alone = colorize(&DynamicImage::ImageRgba8(opaque), &config)
+        .await
+        .unwrap();
+    for (x, y, pixel) in alone.enumerate_pixels() {
+        assert_eq!(output.get_pixel(x, y).0[..3], pixel.0, "at {}, {}", x, y);
+    }
+}
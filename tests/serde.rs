@@ -0,0 +1,102 @@
+mod common;
+
+use common::test_config;
+use image::Rgb;
+use image_colorizer::types::{
+    AppConfig, BlendFactor, ConfigFileError, CvdType, DitherAlgorithm, SpatialKernel,
+};
+use palette::{FromColor, Srgb};
+
+fn configured() -> AppConfig {
+    let mut config = test_config(DitherAlgorithm::Ordered { size: 8 });
+    config.blend_factor = BlendFactor::PerChannel([0.25, 0.5, 0.75]);
+    config.spatial_kernel = SpatialKernel::Bilateral {
+        spatial_sigma: 3.0,
+        range_sigma: 12.5,
+    };
+    config.simulate_cvd = Some(CvdType::Tritanopia);
+    config.posterize_levels = Some(6);
+    config.chroma_scale = 1.5;
+    config.replace_only = Some(vec![(Rgb([224, 32, 32]), 40), (Rgb([255, 255, 255]), 0)]);
+    config
+}
+
+fn assert_equivalent(a: &AppConfig, b: &AppConfig) {
+    let hex = |config: &AppConfig| {
+        config
+            .colors
+            .iter()
+            .map(|&lab| Srgb::from_color(lab).into_format::<u8>())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(hex(a), hex(b));
+
+    // Every other setting is covered by the debug output
+    let settings = |config: &AppConfig| {
+        let mut config = format!("{:?}", config);
+        let start = config.find("colors: [").unwrap();
+        let end = start + config[start..].find("], ").unwrap();
+        config.replace_range(start..end, "");
+        config
+    };
+    assert_eq!(settings(a), settings(b));
+}
+
+#[test]
+fn round_trips_through_toml() {
+    let config = configured();
+    let path = std::env::temp_dir().join("image-colorizer-serde-test.toml");
+    std::fs::write(&path, toml::to_string(&config).unwrap()).unwrap();
+
+    let loaded = AppConfig::from_toml_path(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_equivalent(&config, &loaded);
+}
+
+#[test]
+fn round_trips_through_json() {
+    let config = configured();
+    let path = std::env::temp_dir().join("image-colorizer-serde-test.json");
+    std::fs::write(&path, serde_json::to_string(&config).unwrap()).unwrap();
+
+    let loaded = AppConfig::from_json_path(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_equivalent(&config, &loaded);
+}
+
+#[test]
+fn missing_settings_keep_their_defaults() {
+    let path = std::env::temp_dir().join("image-colorizer-serde-defaults.toml");
+    std::fs::write(&path, "colors = [\"#ff0000\", \"#0000ff\"]\nsoft_k = 2\n").unwrap();
+
+    let loaded = AppConfig::from_toml_path(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.soft_k, 2);
+    assert_eq!(loaded.colors.len(), 2);
+    assert_eq!(
+        format!("{:?}", loaded.dither_algorithm),
+        format!("{:?}", AppConfig::default().dither_algorithm)
+    );
+}
+
+#[test]
+fn rejects_invalid_configs() {
+    let path = std::env::temp_dir().join("image-colorizer-serde-invalid.json");
+
+    std::fs::write(&path, r##"{ "colors": ["#ff0000"], "soft_k": 0 }"##).unwrap();
+    assert!(matches!(
+        AppConfig::from_json_path(&path),
+        Err(ConfigFileError::Invalid(_))
+    ));
+
+    std::fs::write(&path, r#"{ "colors": ["not a color"] }"#).unwrap();
+    assert!(matches!(
+        AppConfig::from_json_path(&path),
+        Err(ConfigFileError::Json(_))
+    ));
+
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,32 @@
+mod common;
+
+use common::test_config;
+use image_colorizer::{dither::error_diffusion, types::DitherAlgorithm};
+
+use image::{imageops, DynamicImage, Rgb, RgbImage};
+
+// A preserved black first row passes no error on, so the second row is diffused on its own
+fn black_then(row: impl Fn(u32) -> Rgb<u8>) -> RgbImage {
+    RgbImage::from_fn(64, 2, |x, y| if y == 0 { Rgb([0, 0, 0]) } else { row(x) })
+}
+
+#[test]
+fn odd_rows_are_diffused_right_to_left() {
+    for dither_algorithm in [DitherAlgorithm::FloydSteinberg, DitherAlgorithm::Atkinson] {
+        let mut config = test_config(dither_algorithm);
+        config.preserve_extremes = true;
+        let gradient = |x: u32| Rgb([(x * 4) as u8, 100, 200 - (x * 3) as u8]);
+
+        let forward = error_diffusion(
+            &DynamicImage::ImageRgb8(black_then(|x| gradient(63 - x))),
+            &config,
+        );
+        config.serpentine = true;
+        let serpentine = error_diffusion(&DynamicImage::ImageRgb8(black_then(gradient)), &config);
+
+        assert_eq!(
+            imageops::flip_horizontal(&serpentine).into_raw(),
+            forward.into_raw()
+        );
+    }
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::test_config;
+use image::{DynamicImage, Rgb, RgbImage};
+use image_colorizer::{
+    colorize::{colorize, ColorizeError},
+    types::{BlendFactor, DitherAlgorithm, SkinTone},
+    utils::rgb8_to_lab,
+};
+use palette::Lab;
+
+const SKIN: Rgb<u8> = Rgb([186, 140, 110]);
+const SKY: Rgb<u8> = Rgb([70, 130, 200]);
+
+#[test]
+fn protection_fades_out_towards_the_edge() {
+    let tone = SkinTone::default();
+
+    assert_eq!(tone.protection(&Lab::new(60.0, 14.0, 26.0)), 1.0);
+    assert_eq!(tone.protection(&Lab::new(60.0, 19.0, 26.0)), 1.0);
+    assert_eq!(tone.protection(&Lab::new(60.0, 14.0, 26.0 + 15.0)), 0.5);
+    assert_eq!(tone.protection(&Lab::new(60.0, 14.0, 26.0 + 20.0)), 0.0);
+    assert_eq!(tone.protection(&Lab::new(60.0, 0.0, 0.0)), 0.0);
+
+    let skin = rgb8_to_lab(SKIN[0], SKIN[1], SKIN[2]);
+    let sky = rgb8_to_lab(SKY[0], SKY[1], SKY[2]);
+    assert_eq!(tone.protection(&skin), 1.0);
+    assert_eq!(tone.protection(&sky), 0.0);
+}
+
+#[test]
+fn skin_tones_round_trip_through_strings() {
+    let tone: SkinTone = "10, 20.5, 8, 12".parse().unwrap();
+
+    assert_eq!(
+        tone,
+        SkinTone {
+            center_a: 10.0,
+            center_b: 20.5,
+            radius_a: 8.0,
+            radius_b: 12.0,
+        }
+    );
+    assert_eq!(tone.to_string().parse::<SkinTone>(), Ok(tone));
+    assert!("10,20,8".parse::<SkinTone>().is_err());
+}
+
+#[tokio::test]
+async fn protected_skin_keeps_its_color() {
+    let img = RgbImage::from_fn(64, 32, |x, _| if x < 32 { SKIN } else { SKY });
+    let mut config = test_config(DitherAlgorithm::None);
+    config.blend_factor = BlendFactor::Uniform(1.0);
+    config.spatial_averaging_radius = 0;
+
+    let unprotected = match colorize(&DynamicImage::ImageRgb8(img.clone()), &config).await {
+        Ok(unprotected) => unprotected,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+    config.protect_skin = true;
+    let protected = colorize(&DynamicImage::ImageRgb8(img.clone()), &config)
+        .await
+        .unwrap();
+
+    for (x, y, pixel) in protected.enumerate_pixels() {
+        if x < 32 {
+            assert_eq!(*pixel, SKIN, "({}, {})", x, y);
+            assert_ne!(*unprotected.get_pixel(x, y), SKIN);
+        } else {
+            assert_eq!(pixel, unprotected.get_pixel(x, y), "({}, {})", x, y);
+        }
+    }
+}
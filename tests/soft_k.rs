@@ -0,0 +1,44 @@
+use image_colorizer::{
+    types::{DistanceMetric, WorkingSpace},
+    utils::{find_closest_color, find_soft_closest_color},
+};
+
+const COLORS: [[f32; 3]; 3] = [[20.0, 0.0, 0.0], [60.0, 0.0, 0.0], [90.0, 40.0, 40.0]];
+
+#[test]
+fn single_color_matches_hard_mapping() {
+    for metric in [
+        DistanceMetric::Cie76,
+        DistanceMetric::Cie94,
+        DistanceMetric::Ciede2000,
+    ] {
+        for l in [0.0, 30.0, 45.0, 75.0, 100.0] {
+            let color = [l, 10.0, -5.0];
+            assert_eq!(
+                find_soft_closest_color(&color, &COLORS, metric, WorkingSpace::Lab, 1.0, 1),
+                find_closest_color(&color, &COLORS, metric, WorkingSpace::Lab, 1.0)
+            );
+        }
+    }
+}
+
+#[test]
+fn closest_colors_are_blended_by_inverse_distance() {
+    let soft = |color: [f32; 3]| {
+        find_soft_closest_color(
+            &color,
+            &COLORS,
+            DistanceMetric::Cie76,
+            WorkingSpace::Lab,
+            1.0,
+            2,
+        )
+    };
+
+    // 10 away from the first color and 30 from the second, so the first weighs three times as much
+    let blended = soft([30.0, 0.0, 0.0]);
+    assert!((blended[0] - 30.0).abs() < 1e-4, "{:?}", blended);
+
+    // Exact matches keep their palette color
+    assert_eq!(soft(COLORS[1]), COLORS[1]);
+}
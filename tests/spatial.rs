@@ -0,0 +1,234 @@
+use image_colorizer::{
+    progress::Progress,
+    spatial::{
+        adaptive_box_average, adaptive_radii, box_average, multi_scale_box_average,
+        separable_box_average, weighted_pixels,
+    },
+    types::{KernelShape, WorkingSpace},
+    utils::{compute_integral_image, rgb8_to_working},
+};
+
+use image::{Rgb, RgbImage};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+#[test]
+fn box_average_of_a_large_uniform_image_is_uniform() {
+    let (width, height) = (1024, 1024);
+    let image = RgbImage::from_pixel(width, height, Rgb([201, 117, 58]));
+    let expected = rgb8_to_working(201, 117, 58, WorkingSpace::Lab);
+
+    let integral = compute_integral_image(&image, None, WorkingSpace::Lab, &Progress::new(None, 0));
+
+    for shape in [KernelShape::Square, KernelShape::Circle] {
+        let averages = box_average(&integral, 10, shape);
+
+        // Every pixel is checked since the lower right, where the sums are largest, is where
+        // precision runs out first
+
+        for (i, &[l, a, b, weight]) in averages.iter().enumerate() {
+            assert_eq!(weight, 1.0);
+            for (channel, expected) in [l, a, b].into_iter().zip(expected) {
+                assert!(
+                    (channel - expected).abs() < 1e-4,
+                    "{:?} average at ({}, {}) is {} instead of {}",
+                    shape,
+                    i % width as usize,
+                    i / width as usize,
+                    channel,
+                    expected
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn box_average_of_radius_zero_is_the_pixel_itself() {
+    let image = RgbImage::from_fn(64, 48, |x, y| {
+        Rgb([(x * 4) as u8, (y * 5) as u8, ((x + y) * 2) as u8])
+    });
+    // Every other pixel is transparent
+    let alpha: Vec<u8> = (0..64 * 48).map(|i| (i % 2 * 255) as u8).collect();
+
+    for space in [WorkingSpace::Lab, WorkingSpace::Oklab] {
+        let integral = compute_integral_image(&image, Some(&alpha), space, &Progress::new(None, 0));
+
+        for shape in [KernelShape::Square, KernelShape::Circle] {
+            assert_eq!(
+                box_average(&integral, 0, shape),
+                weighted_pixels(&image, Some(&alpha), space)
+            );
+        }
+    }
+}
+
+#[test]
+fn integral_image_matches_a_serial_prefix_sum() {
+    let mut rng = StdRng::seed_from_u64(62);
+    let (width, height) = (97, 61);
+    let image = RgbImage::from_fn(width, height, |_, _| Rgb(rng.gen()));
+    let alpha: Vec<u8> = (0..width * height)
+        .map(|_| rng.gen_range(0..=1) * 255)
+        .collect();
+
+    for space in [WorkingSpace::Lab, WorkingSpace::Oklab] {
+        let pixels = weighted_pixels(&image, Some(&alpha), space);
+
+        // Rows first, then columns, in the same order the parallel version sums them in
+        let (width, height) = (width as usize, height as usize);
+        let mut expected = vec![vec![(0.0, 0.0, 0.0, 0.0); width + 1]; height + 1];
+        for y in 1..=height {
+            let mut sum = (0.0, 0.0, 0.0, 0.0);
+            for x in 1..=width {
+                let [l, a, b, weight] = pixels[(y - 1) * width + x - 1];
+                sum = (
+                    sum.0 + l as f64,
+                    sum.1 + a as f64,
+                    sum.2 + b as f64,
+                    sum.3 + weight as f64,
+                );
+                let above: (f64, f64, f64, f64) = expected[y - 1][x];
+                expected[y][x] = (
+                    sum.0 + above.0,
+                    sum.1 + above.1,
+                    sum.2 + above.2,
+                    sum.3 + above.3,
+                );
+            }
+        }
+
+        for threads in [1, 4] {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            let integral = pool.install(|| {
+                compute_integral_image(&image, Some(&alpha), space, &Progress::new(None, 0))
+            });
+
+            assert_eq!(integral, expected, "{:?} on {} threads", space, threads);
+        }
+    }
+}
+
+#[test]
+fn separable_box_average_matches_the_integral_image() {
+    let mut rng = StdRng::seed_from_u64(77);
+
+    for _ in 0..8 {
+        let (width, height) = (rng.gen_range(1..120), rng.gen_range(1..90));
+        let image = RgbImage::from_fn(width, height, |_, _| Rgb(rng.gen()));
+        let alpha: Vec<u8> = (0..width * height)
+            .map(|_| if rng.gen_bool(0.2) { 0 } else { 255 })
+            .collect();
+        let radius = rng.gen_range(0..40);
+        let space = if rng.gen() {
+            WorkingSpace::Lab
+        } else {
+            WorkingSpace::Oklab
+        };
+        let progress = Progress::new(None, 0);
+
+        let integral = compute_integral_image(&image, Some(&alpha), space, &progress);
+        let expected = box_average(&integral, radius, KernelShape::Square);
+        let separable = separable_box_average(&image, Some(&alpha), space, radius, &progress);
+
+        for (i, (a, b)) in separable.iter().zip(&expected).enumerate() {
+            assert_eq!(a[3], b[3], "weight of pixel {} in {}x{}", i, width, height);
+            for (a, b) in a.iter().zip(b) {
+                assert!(
+                    (a - b).abs() < 1e-3,
+                    "pixel {} in {}x{} with radius {}: {} != {}",
+                    i,
+                    width,
+                    height,
+                    radius,
+                    a,
+                    b
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn adaptive_radius_shrinks_only_at_edges() {
+    // Dark on the left and light on the right of column 32
+    let image = RgbImage::from_fn(64, 16, |x, _| Rgb([if x < 32 { 40 } else { 220 }; 3]));
+
+    for space in [WorkingSpace::Lab, WorkingSpace::Oklab] {
+        let radii = adaptive_radii(&image, space, 12);
+        for (i, &radius) in radii.iter().enumerate() {
+            match i % 64 {
+                31 | 32 => assert!(radius <= 2, "{:?} radius {} at {}", space, radius, i),
+                30 | 33 => {}
+                _ => assert_eq!(radius, 12, "{:?} at {}", space, i),
+            }
+        }
+    }
+}
+
+#[test]
+fn adaptive_box_average_with_one_radius_matches_box_average() {
+    let mut rng = StdRng::seed_from_u64(92);
+    let image = RgbImage::from_fn(57, 41, |_, _| Rgb(rng.gen()));
+    let integral = compute_integral_image(&image, None, WorkingSpace::Lab, &Progress::new(None, 0));
+
+    for shape in [KernelShape::Square, KernelShape::Circle] {
+        let radii = vec![7; 57 * 41];
+        assert_eq!(
+            adaptive_box_average(&integral, &radii, shape),
+            box_average(&integral, 7, shape)
+        );
+    }
+}
+
+#[test]
+fn multi_scale_average_with_one_level_matches_box_average() {
+    let mut rng = StdRng::seed_from_u64(97);
+    let image = RgbImage::from_fn(57, 41, |_, _| Rgb(rng.gen()));
+    let integral = compute_integral_image(&image, None, WorkingSpace::Lab, &Progress::new(None, 0));
+
+    for shape in [KernelShape::Square, KernelShape::Circle] {
+        assert_eq!(
+            multi_scale_box_average(&integral, WorkingSpace::Lab, 5, &[1.0], shape),
+            box_average(&integral, 5, shape)
+        );
+    }
+}
+
+#[test]
+fn multi_scale_average_smooths_flat_noise_but_keeps_edges() {
+    // Faint noise on both sides of a sharp edge at column 64
+    let mut rng = StdRng::seed_from_u64(97);
+    let image = RgbImage::from_fn(128, 64, |x, _| {
+        let base: u8 = if x < 64 { 60 } else { 180 };
+        Rgb([0; 3].map(|_| base + rng.gen_range(0..3)))
+    });
+    let space = WorkingSpace::Lab;
+    let integral = compute_integral_image(&image, None, space, &Progress::new(None, 0));
+    let shape = KernelShape::Square;
+
+    let single = box_average(&integral, 3, shape);
+    let multi = multi_scale_box_average(&integral, space, 3, &[1.0, 1.0, 1.0], shape);
+    let at = |averages: &[[f32; 4]], x: usize, y: usize| averages[y * 128 + x][1];
+
+    // The spread of a in the flat middle of the left half shrinks
+    let spread = |averages: &[[f32; 4]]| {
+        let values: Vec<f32> = (20..44)
+            .flat_map(|y| (20..44).map(move |x| (x, y)))
+            .map(|(x, y)| at(averages, x, y))
+            .collect();
+        let max = values.iter().cloned().fold(f32::MIN, f32::max);
+        let min = values.iter().cloned().fold(f32::MAX, f32::min);
+        max - min
+    };
+    assert!(spread(&multi) < spread(&single) * 0.75);
+
+    // Next to the edge the lightness stays close to the first level, where a box of the largest
+    // radius alone would pull it far across the edge
+    let wide = box_average(&integral, 12, shape);
+    let lightness = |averages: &[[f32; 4]]| averages[32 * 128 + 60][0];
+    assert!((lightness(&multi) - lightness(&single)).abs() < 4.0);
+    assert!((lightness(&wide) - lightness(&single)).abs() > 10.0);
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use common::test_config;
+use image::{DynamicImage, Rgb, RgbImage};
+use image_colorizer::{
+    colorize::{colorize, ColorizeError},
+    types::{BlendFactor, DitherAlgorithm, SplitTone},
+    utils::rgb8_to_lab,
+};
+
+#[test]
+fn parses_the_tones_and_balance() {
+    let tone: SplitTone = "#2a7f7f,#f0a050,0.3".parse().unwrap();
+    assert_eq!(tone.balance, 0.3);
+    assert_eq!(tone.to_string(), "#2a7f7f,#f0a050,0.3");
+
+    let tone: SplitTone = "#2a7f7f, #f0a050".parse().unwrap();
+    assert_eq!(tone.balance, 0.5);
+
+    assert!("#2a7f7f".parse::<SplitTone>().is_err());
+    assert!("#2a7f7f,#f0a050,1.5".parse::<SplitTone>().is_err());
+    assert!("#2a7f7f,nope".parse::<SplitTone>().is_err());
+}
+
+// Mean b of the left and right halves of a ramp from black to white
+fn shadow_and_highlight_b(img: &RgbImage) -> (f32, f32) {
+    let mean_b = |columns: std::ops::Range<u32>| {
+        let pixels: Vec<f32> = img
+            .enumerate_pixels()
+            .filter(|(x, _, _)| columns.contains(x))
+            .map(|(_, _, p)| rgb8_to_lab(p[0], p[1], p[2]).b)
+            .collect();
+        pixels.iter().sum::<f32>() / pixels.len() as f32
+    };
+    (mean_b(0..32), mean_b(96..128))
+}
+
+#[tokio::test]
+async fn tints_shadows_and_highlights_apart() {
+    let img = DynamicImage::ImageRgb8(RgbImage::from_fn(128, 16, |x, _| {
+        let v = (x * 2) as u8;
+        Rgb([v, v, v])
+    }));
+    let mut config = test_config(DitherAlgorithm::None);
+    config.blend_factor = BlendFactor::Uniform(1.0);
+
+    let plain = match colorize(&img, &config).await {
+        Ok(plain) => plain,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+
+    // Teal shadows have a negative b and orange highlights a positive one
+    config.split_tone = Some("#1f6f78,#f0a050".parse().unwrap());
+    let toned = colorize(&img, &config).await.unwrap();
+
+    let (plain_shadows, plain_highlights) = shadow_and_highlight_b(&plain);
+    let (toned_shadows, toned_highlights) = shadow_and_highlight_b(&toned);
+    assert!(toned_shadows < plain_shadows - 1.0);
+    assert!(toned_highlights > plain_highlights + 1.0);
+}
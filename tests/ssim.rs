@@ -0,0 +1,77 @@
+mod common;
+
+use common::{test_config, test_image};
+use image::{GrayImage, Luma};
+use image_colorizer::{
+    colorize::{colorize, colorize_with_report, ColorizeError},
+    metrics::ssim,
+    types::DitherAlgorithm,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+fn gradient(width: u32, height: u32) -> GrayImage {
+    GrayImage::from_fn(width, height, |x, y| Luma([((x * 3 + y * 2) % 256) as u8]))
+}
+
+#[test]
+fn identical_images_score_one() {
+    for (width, height) in [(64, 48), (5, 3), (1, 1)] {
+        let img = gradient(width, height);
+        assert!((ssim(&img, &img) - 1.0).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn more_noise_scores_lower() {
+    let img = gradient(64, 48);
+    let mut rng = StdRng::seed_from_u64(80);
+    let noisy = |amount: i16, rng: &mut StdRng| {
+        GrayImage::from_fn(64, 48, |x, y| {
+            let noise = rng.gen_range(-amount..=amount);
+            Luma([(img.get_pixel(x, y)[0] as i16 + noise).clamp(0, 255) as u8])
+        })
+    };
+
+    let slightly = ssim(&img, &noisy(5, &mut rng));
+    let heavily = ssim(&img, &noisy(60, &mut rng));
+
+    assert!(slightly < 1.0);
+    assert!(heavily < slightly);
+}
+
+#[test]
+fn is_symmetric() {
+    let a = gradient(40, 30);
+    let b = GrayImage::from_fn(40, 30, |x, y| Luma([((x * y) % 256) as u8]));
+
+    assert!((ssim(&a, &b) - ssim(&b, &a)).abs() < 1e-12);
+}
+
+#[test]
+fn inverted_images_score_negative() {
+    let img = gradient(64, 48);
+    let inverted = GrayImage::from_fn(64, 48, |x, y| Luma([255 - img.get_pixel(x, y)[0]]));
+
+    assert!(ssim(&img, &inverted) < 0.0);
+}
+
+#[tokio::test]
+async fn report_measures_ssim_only_when_asked() {
+    let mut config = test_config(DitherAlgorithm::Random);
+    let img = test_image();
+
+    let report = match colorize_with_report(&img, &config).await {
+        Ok(report) => report,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+    assert_eq!(report.output, colorize(&img, &config).await.unwrap());
+    assert_eq!(report.ssim, None);
+
+    config.compute_ssim = true;
+    let report = colorize_with_report(&img, &config).await.unwrap();
+
+    // Luminance transfer keeps the structure of the original
+    let ssim = report.ssim.unwrap();
+    assert!(ssim > 0.5 && ssim <= 1.0, "SSIM is {}", ssim);
+}
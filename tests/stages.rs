@@ -0,0 +1,44 @@
+mod common;
+
+use common::{test_config, test_image};
+use image_colorizer::{
+    colorize::{colorize, colorize_stages, ColorizeError},
+    dither::error_diffusion,
+    types::DitherAlgorithm,
+};
+
+#[tokio::test]
+async fn stages_end_in_the_colorized_image() {
+    let config = test_config(DitherAlgorithm::Random);
+    let img = test_image();
+
+    let stages = match colorize_stages(&img, &config).await {
+        Ok(stages) => stages,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+
+    assert_eq!(stages.output, colorize(&img, &config).await.unwrap());
+    assert_ne!(stages.first_pass, stages.output);
+}
+
+#[tokio::test]
+async fn first_pass_is_the_same_with_tiling() {
+    let img = test_image();
+
+    for dither_algorithm in [DitherAlgorithm::Random, DitherAlgorithm::FloydSteinberg] {
+        let mut config = test_config(dither_algorithm);
+        let whole = match colorize_stages(&img, &config).await {
+            Ok(whole) => whole,
+            Err(ColorizeError::NoAdapter) => return,
+            Err(e) => panic!("{}", e),
+        };
+        config.tile_height = Some(7);
+        let tiled = colorize_stages(&img, &config).await.unwrap();
+
+        assert_eq!(whole.first_pass, tiled.first_pass);
+        if dither_algorithm == DitherAlgorithm::FloydSteinberg {
+            assert_eq!(whole.first_pass, error_diffusion(&img, &config));
+        }
+    }
+}
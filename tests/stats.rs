@@ -0,0 +1,42 @@
+mod common;
+
+use common::{odd_test_image, test_config, test_image};
+use image_colorizer::{
+    colorize::{colorize, colorize_with_stats, ColorizeError},
+    types::DitherAlgorithm,
+};
+
+#[tokio::test]
+async fn every_pixel_is_counted_once() {
+    let img = test_image();
+
+    for dither_algorithm in [DitherAlgorithm::None, DitherAlgorithm::FloydSteinberg] {
+        let mut config = test_config(dither_algorithm);
+
+        let (colorized, stats) = match colorize_with_stats(&img, &config).await {
+            Ok(result) => result,
+            Err(ColorizeError::NoAdapter) => return,
+            Err(e) => panic!("{}", e),
+        };
+        assert_eq!(stats.total(), 64 * 48);
+        assert_eq!(stats.counts.len(), config.colors.len());
+        assert_eq!(colorized, colorize(&img, &config).await.unwrap());
+
+        // Overlapping rows of neighboring strips must not be counted twice
+        config.tile_height = Some(7);
+        let (_, tiled_stats) = colorize_with_stats(&img, &config).await.unwrap();
+        assert_eq!(tiled_stats, stats);
+    }
+}
+
+#[tokio::test]
+async fn invocations_past_the_right_edge_count_nothing() {
+    let config = test_config(DitherAlgorithm::None);
+
+    let stats = match colorize_with_stats(&odd_test_image(), &config).await {
+        Ok((_, stats)) => stats,
+        Err(ColorizeError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+    assert_eq!(stats.total(), 18 * 10);
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use common::{test_config, test_image};
+use image::{DynamicImage, Rgb, RgbImage};
+use image_colorizer::{
+    colorize::{colorize_stages, ColorizeError},
+    types::{AppConfig, BlendFactor, DistanceMetric, DitherAlgorithm, WorkingSpace},
+    utils::{find_stochastic_index, rgb8_to_lab},
+};
+use palette::Lab;
+
+fn stochastic_config(seed: u64) -> AppConfig {
+    let mut config = test_config(DitherAlgorithm::None);
+    config.blend_factor = BlendFactor::Uniform(1.0);
+    config.stochastic_assignment = true;
+    config.dither_seed = seed;
+    config
+}
+
+async fn first_pass(img: &DynamicImage, config: &AppConfig) -> Option<RgbImage> {
+    match colorize_stages(img, config).await {
+        Ok(stages) => Some(stages.first_pass),
+        Err(ColorizeError::NoAdapter) => None,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+#[tokio::test]
+async fn the_same_seed_gives_the_same_texture() {
+    let img = test_image();
+
+    let Some(first) = first_pass(&img, &stochastic_config(7)).await else {
+        return;
+    };
+    assert_eq!(
+        first,
+        first_pass(&img, &stochastic_config(7)).await.unwrap()
+    );
+    assert_ne!(
+        first,
+        first_pass(&img, &stochastic_config(8)).await.unwrap()
+    );
+}
+
+#[tokio::test]
+async fn pixels_between_two_colors_split_between_them() {
+    // Grey sits halfway between the two palette colors on the a axis
+    let mut config = stochastic_config(0);
+    config.colors = vec![Lab::new(50.0, 40.0, 0.0), Lab::new(50.0, -40.0, 0.0)];
+    let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(64, 48, Rgb([119; 3])));
+
+    let Some(output) = first_pass(&img, &config).await else {
+        return;
+    };
+    let reddish = output
+        .pixels()
+        .filter(|pixel| rgb8_to_lab(pixel[0], pixel[1], pixel[2]).a > 0.0)
+        .count();
+    let total = output.pixels().count();
+    assert!(
+        reddish > total / 3 && reddish < total * 2 / 3,
+        "{} of {} pixels picked the first color",
+        reddish,
+        total
+    );
+
+    // Without it every pixel snaps to the same color
+    config.stochastic_assignment = false;
+    let snapped = first_pass(&img, &config).await.unwrap();
+    assert!(snapped
+        .pixels()
+        .all(|pixel| pixel == snapped.get_pixel(0, 0)));
+}
+
+#[test]
+fn closer_colors_are_picked_more_often() {
+    let colors = [[50.0, 0.0, 0.0], [50.0, 30.0, 0.0], [50.0, -90.0, 0.0]];
+    let pick = |color: &[f32; 3], noise| {
+        find_stochastic_index(
+            color,
+            &colors,
+            DistanceMetric::Cie76,
+            WorkingSpace::Lab,
+            1.0,
+            noise,
+        )
+    };
+
+    // Distances 10, 20 and 100 give weights of 10/16, 5/16 and 1/16
+    let color = [50.0, 10.0, 0.0];
+    let picks: Vec<usize> = (0..1000).map(|i| pick(&color, i as f32 / 1000.0)).collect();
+    let count = |index| picks.iter().filter(|&&pick| pick == index).count();
+    assert_eq!((count(0), count(1), count(2)), (625, 313, 62));
+
+    // An exact match never strays
+    assert!((0..10).all(|i| pick(&colors[2], i as f32 / 10.0) == 2));
+}
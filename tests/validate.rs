@@ -0,0 +1,99 @@
+mod common;
+
+use common::{test_config, test_image};
+use image_colorizer::{
+    colorize::{colorize, ColorizeError},
+    types::{AppConfig, BlendFactor, ConfigError, DitherAlgorithm, SpatialKernel, SplitTone},
+};
+use palette::Lab;
+
+type Invalidate = fn(&mut AppConfig);
+
+fn invalid_field(error: ConfigError) -> &'static str {
+    match error {
+        ConfigError::EmptyColors => "colors",
+        ConfigError::OutOfRange { field, .. } => field,
+    }
+}
+
+#[test]
+fn test_config_is_valid() {
+    assert_eq!(test_config(DitherAlgorithm::Random).validate(), Ok(()));
+}
+
+#[test]
+fn each_invalid_setting_is_named() {
+    let cases: [(&str, Invalidate); 27] = [
+        ("colors", |config| config.colors.clear()),
+        ("blend_factor", |config| {
+            config.blend_factor = BlendFactor::PerChannel([0.5, 5.0, 0.5])
+        }),
+        ("dither_amount", |config| config.dither_amount = -0.1),
+        ("dither_amount", |config| config.dither_amount = f32::NAN),
+        ("dither_amount", |config| {
+            config.dither_amount = f32::INFINITY
+        }),
+        ("dither_algorithm", |config| {
+            config.dither_algorithm = DitherAlgorithm::Ordered { size: 3 }
+        }),
+        ("dither_algorithm", |config| {
+            config.dither_algorithm = DitherAlgorithm::Ordered { size: 0 }
+        }),
+        ("spatial_averaging_radius", |config| {
+            config.spatial_averaging_radius = 1000
+        }),
+        ("spatial_kernel", |config| {
+            config.spatial_kernel = SpatialKernel::Gaussian { sigma: 0.0 }
+        }),
+        ("tint", |config| config.tint = f32::INFINITY),
+        ("luminance_weight", |config| config.luminance_weight = -1.0),
+        ("luminance_weight", |config| {
+            config.luminance_weight = f32::NAN
+        }),
+        ("brightness", |config| config.brightness = 150.0),
+        ("brightness", |config| config.brightness = f32::NEG_INFINITY),
+        ("contrast", |config| config.contrast = -0.5),
+        ("contrast", |config| config.contrast = f32::NAN),
+        ("sharpen_amount", |config| config.sharpen_amount = 6.0),
+        ("sharpen_amount", |config| {
+            config.sharpen_amount = f32::INFINITY
+        }),
+        ("refine_iterations", |config| config.refine_iterations = 0),
+        ("chroma_scale", |config| config.chroma_scale = -1.0),
+        ("posterize_levels", |config| {
+            config.posterize_levels = Some(1)
+        }),
+        ("multi_scale_weights", |config| {
+            config.multi_scale_weights = Some(vec![1.0, -0.5])
+        }),
+        ("skin_tone", |config| config.skin_tone.radius_b = 0.0),
+        ("split_tone", |config| {
+            config.split_tone = Some(SplitTone {
+                shadow: Lab::new(30.0, -20.0, -10.0),
+                highlight: Lab::new(70.0, 20.0, 40.0),
+                balance: 1.5,
+            })
+        }),
+        ("jpeg_quality", |config| config.jpeg_quality = 0),
+        ("threads", |config| config.threads = Some(0)),
+        ("tile_height", |config| config.tile_height = Some(0)),
+    ];
+
+    for (field, invalidate) in cases {
+        let mut config = test_config(DitherAlgorithm::Random);
+        invalidate(&mut config);
+
+        assert_eq!(invalid_field(config.validate().unwrap_err()), field);
+    }
+}
+
+#[tokio::test]
+async fn colorize_rejects_invalid_configs() {
+    let mut config = test_config(DitherAlgorithm::Random);
+    config.soft_k = 0;
+
+    match colorize(&test_image(), &config).await {
+        Err(ColorizeError::Config(error)) => assert_eq!(invalid_field(error), "soft_k"),
+        result => panic!("Expected a config error, got {:?}", result.err()),
+    }
+}